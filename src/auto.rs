@@ -0,0 +1,175 @@
+use crate::status::Error;
+use crate::{connect, read_layout};
+use anyhow::{Context, Result};
+use std::path::{Path, PathBuf};
+
+/// Where `--auto` looks for layouts when neither the command line nor the config say.
+fn default_dir() -> Option<PathBuf> {
+    Some(dirs::config_dir()?.join("swaystart").join("layouts"))
+}
+
+/// How well a layout for the output `name` fits the active outputs, 0 if not at all.
+///
+/// A name made of the make, model and serial of an output, as sway takes them for output
+/// commands, names that very screen, so it beats a connector name like `DP-1`.
+fn score(name: &str, outputs: &[swayipc::Output]) -> u32 {
+    let by_description = outputs
+        .iter()
+        .any(|o| format!("{} {} {}", o.make, o.model, o.serial) == name);
+    if by_description {
+        2
+    } else if outputs.iter().any(|o| o.name == name) {
+        1
+    } else {
+        0
+    }
+}
+
+/// The layout directory, `dir` if given.
+pub fn dir(dir: Option<&Path>) -> Result<PathBuf> {
+    match dir {
+        Some(dir) => Ok(dir.to_owned()),
+        None => default_dir().context("there is no config directory to look for layouts in"),
+    }
+}
+
+/// Picks the layout in `dir`, or the default directory, whose output is connected, failing if
+/// none or several fit as well.
+pub fn choose(dir: Option<&Path>) -> Result<PathBuf, Error> {
+    let dir = self::dir(dir)?;
+    let mut outputs = connect()?.get_outputs()?;
+    outputs.retain(|o| o.active);
+    pick(&dir, &outputs)
+}
+
+/// Picks the layout in `dir` that fits the active `outputs` the best.
+fn pick(dir: &Path, outputs: &[swayipc::Output]) -> Result<PathBuf, Error> {
+    let entries = std::fs::read_dir(dir)
+        .with_context(|| format!("listing the layouts in {}", dir.display()))?;
+    let mut best = 0;
+    let mut candidates = Vec::new();
+    for entry in entries {
+        let path = entry?.path();
+        if path.extension().is_none_or(|e| e != "json") {
+            continue;
+        }
+        let output = match read_layout(&path, false) {
+            Ok(output) => output,
+            Err(e) => {
+                eprintln!("warning: skipping {}: {:#}", path.display(), e);
+                continue;
+            }
+        };
+        let score = score(&output.name, outputs);
+        log::debug!(
+            "{} for output {}: score {}",
+            path.display(),
+            output.name,
+            score
+        );
+        if score > best {
+            best = score;
+            candidates.clear();
+        }
+        if score == best {
+            candidates.push(path);
+        }
+    }
+    let active: Vec<&str> = outputs.iter().map(|o| o.name.as_str()).collect();
+    if best == 0 {
+        return Err(anyhow::anyhow!(
+            "no layout in {} is for the active outputs {}",
+            dir.display(),
+            active.join(", ")
+        )
+        .into());
+    }
+    if candidates.len() > 1 {
+        candidates.sort();
+        let names: Vec<String> = candidates.iter().map(|p| p.display().to_string()).collect();
+        return Err(anyhow::anyhow!(
+            "several layouts fit the active outputs {} as well: {}",
+            active.join(", "),
+            names.join(", ")
+        )
+        .into());
+    }
+    Ok(candidates.remove(0))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ipc::mock::output;
+    use crate::status::describe;
+    use serde_json::json;
+
+    /// A directory named after `name` with a layout file for each output of `layouts`.
+    fn layouts(name: &str, layouts: &[(&str, &str)]) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("swaystart-{}-{}", std::process::id(), name));
+        std::fs::create_dir_all(&dir).unwrap();
+        for (file, output) in layouts {
+            let layout = json!({ "name": output, "workspaces": [] });
+            std::fs::write(dir.join(file), layout.to_string()).unwrap();
+        }
+        dir
+    }
+
+    fn outputs() -> Vec<swayipc::Output> {
+        vec![
+            output("eDP-1", "BOE", "0x095F", "0"),
+            output("DP-1", "Dell Inc.", "U2720Q", "ABC123"),
+        ]
+    }
+
+    #[test]
+    fn picks_the_layout_of_a_connected_output() {
+        let dir = layouts(
+            "connected",
+            &[
+                ("home.json", "HDMI-A-1"),
+                ("office.json", "DP-1"),
+                ("notes.txt", "DP-1"),
+            ],
+        );
+        assert_eq!(pick(&dir, &outputs()).unwrap(), dir.join("office.json"));
+        // Naming the screen beats naming the connector.
+        std::fs::write(
+            dir.join("dell.json"),
+            json!({ "name": "Dell Inc. U2720Q ABC123", "workspaces": [] }).to_string(),
+        )
+        .unwrap();
+        assert_eq!(pick(&dir, &outputs()).unwrap(), dir.join("dell.json"));
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn refuses_layouts_that_fit_as_well() {
+        let dir = layouts("tie", &[("laptop.json", "eDP-1"), ("desk.json", "DP-1")]);
+        let e = describe(&pick(&dir, &outputs()).unwrap_err());
+        assert!(
+            e.ends_with(&format!(
+                "several layouts fit the active outputs eDP-1, DP-1 as well: {}, {}",
+                dir.join("desk.json").display(),
+                dir.join("laptop.json").display()
+            )),
+            "{}",
+            e
+        );
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn fails_without_a_layout_for_the_outputs() {
+        let dir = layouts("none", &[("home.json", "HDMI-A-1")]);
+        let e = describe(&pick(&dir, &outputs()).unwrap_err());
+        assert_eq!(
+            e,
+            format!(
+                "no layout in {} is for the active outputs eDP-1, DP-1",
+                dir.display()
+            )
+        );
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,26 @@
+use serde::Deserialize;
+
+/// The window manager swaystart talks to, over the IPC they share.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum Backend {
+    /// Sway, with Wayland placeholders
+    Sway,
+    /// i3, with X11 placeholders, and neither app_id matching nor the progress overlay
+    I3,
+}
+
+impl Backend {
+    /// The window manager of the session swaystart runs in, sway unless the environment only
+    /// points to i3.
+    pub fn detect() -> Backend {
+        let set = |var: &str| std::env::var_os(var).is_some_and(|v| !v.is_empty());
+        if set("SWAYSOCK") || set("WAYLAND_DISPLAY") {
+            Backend::Sway
+        } else if set("I3SOCK") || set("DISPLAY") {
+            Backend::I3
+        } else {
+            Backend::Sway
+        }
+    }
+}
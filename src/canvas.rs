@@ -0,0 +1,236 @@
+/// A software canvas over an `Argb8888` shm buffer.
+///
+/// Colors are premultiplied `0xAARRGGBB` values. Everything drawn is clipped to the canvas.
+pub struct Canvas<'a> {
+    data: &'a mut [u8],
+    width: u32,
+    height: u32,
+}
+
+impl<'a> Canvas<'a> {
+    pub fn new(data: &'a mut [u8], width: u32, height: u32) -> Self {
+        Canvas {
+            data,
+            width,
+            height,
+        }
+    }
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+    pub fn fill(&mut self, color: u32) {
+        for chunk in self.data.chunks_exact_mut(4) {
+            chunk.copy_from_slice(&color.to_le_bytes());
+        }
+    }
+    pub fn fill_rect(&mut self, x: i32, y: i32, width: u32, height: u32, color: u32) {
+        let x0 = x.clamp(0, self.width as i32) as u32;
+        let y0 = y.clamp(0, self.height as i32) as u32;
+        let x1 = (x + width as i32).clamp(0, self.width as i32) as u32;
+        let y1 = (y + height as i32).clamp(0, self.height as i32) as u32;
+        for row in y0..y1 {
+            let start = (row * self.width + x0) as usize * 4;
+            let end = (row * self.width + x1.max(x0)) as usize * 4;
+            for chunk in self.data[start..end].chunks_exact_mut(4) {
+                chunk.copy_from_slice(&color.to_le_bytes());
+            }
+        }
+    }
+    /// Composites `color` over the whole canvas.
+    pub fn blend(&mut self, color: u32) {
+        for chunk in self.data.chunks_exact_mut(4) {
+            let dst = u32::from_le_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]);
+            chunk.copy_from_slice(&over(color, dst).to_le_bytes());
+        }
+    }
+    /// Draws a frame of the given width along the edges of the canvas.
+    pub fn draw_border(&mut self, width: u32, color: u32) {
+        let (w, h) = (self.width, self.height);
+        self.fill_rect(0, 0, w, width, color);
+        self.fill_rect(0, h as i32 - width as i32, w, width, color);
+        self.fill_rect(0, 0, width, h, color);
+        self.fill_rect(w as i32 - width as i32, 0, width, h, color);
+    }
+    /// Makes the pixels outside of corners rounded with `radius` transparent.
+    pub fn clip_corners(&mut self, radius: u32) {
+        let radius = radius.min(self.width / 2).min(self.height / 2);
+        let r = radius as f32;
+        for dy in 0..radius {
+            for dx in 0..radius {
+                // Distance of the pixel center from the center of the top left corner's circle.
+                let (x, y) = (r - dx as f32 - 0.5, r - dy as f32 - 0.5);
+                if x * x + y * y <= r * r {
+                    continue;
+                }
+                let (right, bottom) = (self.width - 1 - dx, self.height - 1 - dy);
+                for (px, py) in [(dx, dy), (right, dy), (dx, bottom), (right, bottom)] {
+                    self.fill_rect(px as i32, py as i32, 1, 1, 0);
+                }
+            }
+        }
+    }
+    /// Draws `text` with the built-in 5x7 font, with its top left corner at `x`, `y`.
+    ///
+    /// Characters outside of printable ASCII are drawn as `?`.
+    pub fn draw_text(&mut self, x: i32, y: i32, scale: u32, color: u32, text: &str) {
+        let scale = scale as i32;
+        for (i, c) in text.chars().enumerate() {
+            let glyph = glyph(c);
+            let gx = x + i as i32 * GLYPH_ADVANCE as i32 * scale;
+            for (col, bits) in glyph.iter().enumerate() {
+                for row in 0..GLYPH_HEIGHT {
+                    if bits & (1 << row) != 0 {
+                        self.fill_rect(
+                            gx + col as i32 * scale,
+                            y + row as i32 * scale,
+                            scale as u32,
+                            scale as u32,
+                            color,
+                        );
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Horizontal space taken by each character of the built-in font, before scaling.
+pub const GLYPH_ADVANCE: u32 = 6;
+/// Height of the built-in font (including descenders), before scaling.
+pub const GLYPH_HEIGHT: u32 = 8;
+
+/// Width in pixels of `text` drawn with [`Canvas::draw_text`].
+pub fn text_width(text: &str, scale: u32) -> u32 {
+    text.chars().count() as u32 * GLYPH_ADVANCE * scale
+}
+
+/// Composites the premultiplied color `src` over `dst`.
+fn over(src: u32, dst: u32) -> u32 {
+    let inv = 255 - (src >> 24);
+    let mut out = 0;
+    for shift in [0, 8, 16, 24] {
+        let s = (src >> shift) & 0xff;
+        let d = (dst >> shift) & 0xff;
+        out |= (s + d * inv / 255).min(255) << shift;
+    }
+    out
+}
+
+/// Returns the longest prefix of `text` that fits in `width` pixels at the given scale.
+pub fn truncate_to_width(text: &str, width: u32, scale: u32) -> &str {
+    let max_chars = (width / (GLYPH_ADVANCE * scale)) as usize;
+    match text.char_indices().nth(max_chars) {
+        Some((idx, _)) => &text[..idx],
+        None => text,
+    }
+}
+
+fn glyph(c: char) -> &'static [u8; 5] {
+    let idx = match c {
+        ' '..='~' => c as usize - ' ' as usize,
+        _ => '?' as usize - ' ' as usize,
+    };
+    &FONT[idx]
+}
+
+/// Column-major 5x8 bitmaps for printable ASCII, least significant bit at the top.
+const FONT: [[u8; 5]; 95] = [
+    [0x00, 0x00, 0x00, 0x00, 0x00], // ' '
+    [0x00, 0x00, 0x5F, 0x00, 0x00], // '!'
+    [0x00, 0x07, 0x00, 0x07, 0x00], // '"'
+    [0x14, 0x7F, 0x14, 0x7F, 0x14], // '#'
+    [0x24, 0x2A, 0x7F, 0x2A, 0x12], // '$'
+    [0x23, 0x13, 0x08, 0x64, 0x62], // '%'
+    [0x36, 0x49, 0x56, 0x20, 0x50], // '&'
+    [0x00, 0x08, 0x07, 0x03, 0x00], // '\''
+    [0x00, 0x1C, 0x22, 0x41, 0x00], // '('
+    [0x00, 0x41, 0x22, 0x1C, 0x00], // ')'
+    [0x2A, 0x1C, 0x7F, 0x1C, 0x2A], // '*'
+    [0x08, 0x08, 0x3E, 0x08, 0x08], // '+'
+    [0x00, 0x80, 0x70, 0x30, 0x00], // ','
+    [0x08, 0x08, 0x08, 0x08, 0x08], // '-'
+    [0x00, 0x00, 0x60, 0x60, 0x00], // '.'
+    [0x20, 0x10, 0x08, 0x04, 0x02], // '/'
+    [0x3E, 0x51, 0x49, 0x45, 0x3E], // '0'
+    [0x00, 0x42, 0x7F, 0x40, 0x00], // '1'
+    [0x72, 0x49, 0x49, 0x49, 0x46], // '2'
+    [0x21, 0x41, 0x49, 0x4D, 0x33], // '3'
+    [0x18, 0x14, 0x12, 0x7F, 0x10], // '4'
+    [0x27, 0x45, 0x45, 0x45, 0x39], // '5'
+    [0x3C, 0x4A, 0x49, 0x49, 0x31], // '6'
+    [0x41, 0x21, 0x11, 0x09, 0x07], // '7'
+    [0x36, 0x49, 0x49, 0x49, 0x36], // '8'
+    [0x46, 0x49, 0x49, 0x29, 0x1E], // '9'
+    [0x00, 0x00, 0x14, 0x00, 0x00], // ':'
+    [0x00, 0x40, 0x34, 0x00, 0x00], // ';'
+    [0x00, 0x08, 0x14, 0x22, 0x41], // '<'
+    [0x14, 0x14, 0x14, 0x14, 0x14], // '='
+    [0x00, 0x41, 0x22, 0x14, 0x08], // '>'
+    [0x02, 0x01, 0x59, 0x09, 0x06], // '?'
+    [0x3E, 0x41, 0x5D, 0x59, 0x4E], // '@'
+    [0x7C, 0x12, 0x11, 0x12, 0x7C], // 'A'
+    [0x7F, 0x49, 0x49, 0x49, 0x36], // 'B'
+    [0x3E, 0x41, 0x41, 0x41, 0x22], // 'C'
+    [0x7F, 0x41, 0x41, 0x41, 0x3E], // 'D'
+    [0x7F, 0x49, 0x49, 0x49, 0x41], // 'E'
+    [0x7F, 0x09, 0x09, 0x09, 0x01], // 'F'
+    [0x3E, 0x41, 0x41, 0x51, 0x73], // 'G'
+    [0x7F, 0x08, 0x08, 0x08, 0x7F], // 'H'
+    [0x00, 0x41, 0x7F, 0x41, 0x00], // 'I'
+    [0x20, 0x40, 0x41, 0x3F, 0x01], // 'J'
+    [0x7F, 0x08, 0x14, 0x22, 0x41], // 'K'
+    [0x7F, 0x40, 0x40, 0x40, 0x40], // 'L'
+    [0x7F, 0x02, 0x1C, 0x02, 0x7F], // 'M'
+    [0x7F, 0x04, 0x08, 0x10, 0x7F], // 'N'
+    [0x3E, 0x41, 0x41, 0x41, 0x3E], // 'O'
+    [0x7F, 0x09, 0x09, 0x09, 0x06], // 'P'
+    [0x3E, 0x41, 0x51, 0x21, 0x5E], // 'Q'
+    [0x7F, 0x09, 0x19, 0x29, 0x46], // 'R'
+    [0x26, 0x49, 0x49, 0x49, 0x32], // 'S'
+    [0x03, 0x01, 0x7F, 0x01, 0x03], // 'T'
+    [0x3F, 0x40, 0x40, 0x40, 0x3F], // 'U'
+    [0x1F, 0x20, 0x40, 0x20, 0x1F], // 'V'
+    [0x3F, 0x40, 0x38, 0x40, 0x3F], // 'W'
+    [0x63, 0x14, 0x08, 0x14, 0x63], // 'X'
+    [0x03, 0x04, 0x78, 0x04, 0x03], // 'Y'
+    [0x61, 0x59, 0x49, 0x4D, 0x43], // 'Z'
+    [0x00, 0x7F, 0x41, 0x41, 0x41], // '['
+    [0x02, 0x04, 0x08, 0x10, 0x20], // '\\'
+    [0x00, 0x41, 0x41, 0x41, 0x7F], // ']'
+    [0x04, 0x02, 0x01, 0x02, 0x04], // '^'
+    [0x40, 0x40, 0x40, 0x40, 0x40], // '_'
+    [0x00, 0x03, 0x07, 0x08, 0x00], // '`'
+    [0x20, 0x54, 0x54, 0x78, 0x40], // 'a'
+    [0x7F, 0x28, 0x44, 0x44, 0x38], // 'b'
+    [0x38, 0x44, 0x44, 0x44, 0x28], // 'c'
+    [0x38, 0x44, 0x44, 0x28, 0x7F], // 'd'
+    [0x38, 0x54, 0x54, 0x54, 0x18], // 'e'
+    [0x00, 0x08, 0x7E, 0x09, 0x02], // 'f'
+    [0x18, 0xA4, 0xA4, 0x9C, 0x78], // 'g'
+    [0x7F, 0x08, 0x04, 0x04, 0x78], // 'h'
+    [0x00, 0x44, 0x7D, 0x40, 0x00], // 'i'
+    [0x20, 0x40, 0x40, 0x3D, 0x00], // 'j'
+    [0x7F, 0x10, 0x28, 0x44, 0x00], // 'k'
+    [0x00, 0x41, 0x7F, 0x40, 0x00], // 'l'
+    [0x7C, 0x04, 0x78, 0x04, 0x78], // 'm'
+    [0x7C, 0x08, 0x04, 0x04, 0x78], // 'n'
+    [0x38, 0x44, 0x44, 0x44, 0x38], // 'o'
+    [0xFC, 0x18, 0x24, 0x24, 0x18], // 'p'
+    [0x18, 0x24, 0x24, 0x18, 0xFC], // 'q'
+    [0x7C, 0x08, 0x04, 0x04, 0x08], // 'r'
+    [0x48, 0x54, 0x54, 0x54, 0x24], // 's'
+    [0x04, 0x04, 0x3F, 0x44, 0x24], // 't'
+    [0x3C, 0x40, 0x40, 0x20, 0x7C], // 'u'
+    [0x1C, 0x20, 0x40, 0x20, 0x1C], // 'v'
+    [0x3C, 0x40, 0x30, 0x40, 0x3C], // 'w'
+    [0x44, 0x28, 0x10, 0x28, 0x44], // 'x'
+    [0x4C, 0x90, 0x90, 0x90, 0x7C], // 'y'
+    [0x44, 0x64, 0x54, 0x4C, 0x44], // 'z'
+    [0x00, 0x08, 0x36, 0x41, 0x00], // '{'
+    [0x00, 0x00, 0x77, 0x00, 0x00], // '|'
+    [0x00, 0x41, 0x36, 0x08, 0x00], // '}'
+    [0x02, 0x01, 0x02, 0x04, 0x02], // '~'
+];
@@ -0,0 +1,111 @@
+use crate::status::Error;
+use crate::{connect, tree, LayoutVisitor, Output, VisitAction, VisitContext, Workspace};
+use anyhow::Result;
+use std::path::Path;
+use swayipc::{Node, NodeType};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Status {
+    Ok,
+    Warning,
+    Error,
+}
+
+/// Checks a layout against the running session, only querying sway.
+pub struct Checker {
+    tree: Node,
+    outputs: Vec<String>,
+    report: Vec<(Status, String)>,
+}
+
+impl Checker {
+    /// With `strict`, fails on nodes of the session of a type unknown to swaystart.
+    pub fn new(strict: bool) -> Result<Checker> {
+        let mut conn = connect()?;
+        let outputs = conn.get_outputs()?.into_iter().map(|o| o.name).collect();
+        Checker::with_session(conn.get_tree()?, outputs, strict)
+    }
+    /// Checks against the session with the given `tree` and connected `outputs`.
+    pub fn with_session(tree: Node, outputs: Vec<String>, strict: bool) -> Result<Checker> {
+        if strict {
+            tree::check_types(&tree)?;
+        }
+        Ok(Checker {
+            tree,
+            outputs,
+            report: Vec::new(),
+        })
+    }
+    fn add(&mut self, status: Status, item: String) {
+        self.report.push((status, item));
+    }
+    /// Prints the outcome of each check, returning an error if any failed for the layout at
+    /// `path`.
+    pub fn finish(self, path: &Path) -> Result<(), Error> {
+        for (status, item) in &self.report {
+            let label = match status {
+                Status::Ok => "ok",
+                Status::Warning => "warning",
+                Status::Error => "error",
+            };
+            println!("{:<8} {}", label, item);
+        }
+        let errors = self
+            .report
+            .iter()
+            .filter(|(s, _)| *s == Status::Error)
+            .count();
+        if errors > 0 {
+            let reason = format!("the layout has {} problems", errors);
+            return Err(Error::invalid_layout(path, reason));
+        }
+        Ok(())
+    }
+}
+
+impl LayoutVisitor for Checker {
+    fn on_output(&mut self, output: &Output, _ctx: &VisitContext) -> Result<VisitAction> {
+        if self.outputs.contains(&output.name) {
+            self.add(Status::Ok, format!("output {}", output.name));
+        } else {
+            self.add(
+                Status::Error,
+                format!("output {} is not connected", output.name),
+            );
+        }
+        Ok(VisitAction::Continue)
+    }
+    fn on_workspace(&mut self, workspace: &Workspace, _ctx: &VisitContext) -> Result<VisitAction> {
+        let existing = tree::iter_nodes(&self.tree, tree::Order::Document).find(|(n, _, _)| {
+            n.node_type == NodeType::Workspace && n.name.as_deref() == Some(workspace.name.as_str())
+        });
+        let windows = existing.map_or(0, |(n, _, _)| {
+            tree::iter_views(n, tree::Order::Document, false).count()
+        });
+        if windows > 0 {
+            self.add(
+                Status::Warning,
+                format!(
+                    "workspace {} already has {} windows, the ones without a slot will be floated",
+                    workspace.name, windows
+                ),
+            );
+        } else {
+            self.add(Status::Ok, format!("workspace {}", workspace.name));
+        }
+        Ok(VisitAction::Continue)
+    }
+    fn on_app(&mut self, app: &str, id: &str, _ctx: &VisitContext) -> Result<()> {
+        match gio::DesktopAppInfo::new(&format!("{app}.desktop")) {
+            Some(_) => self.add(Status::Ok, format!("app {} for {}", app, id)),
+            None => self.add(
+                Status::Error,
+                format!("app {} for {} has no {}.desktop file", app, id, app),
+            ),
+        }
+        Ok(())
+    }
+    fn on_floating_app(&mut self, app: &str, id: &str, ctx: &VisitContext) -> Result<()> {
+        self.on_app(app, id, ctx)
+    }
+}
@@ -0,0 +1,245 @@
+use crate::status::Error;
+use anyhow::{Context, Result};
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+/// Defaults for the command line options, read from `$XDG_CONFIG_HOME/swaystart/config.toml`.
+///
+/// Options given on the command line take precedence over the ones in the file.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct Config {
+    pub restore: Restore,
+    pub diff: Diff,
+    pub daemon: Daemon,
+    pub log: Log,
+    /// The `[groups]` table, naming lists of workspaces to restore with `--group`.
+    pub groups: BTreeMap<String, Vec<WorkspaceName>>,
+}
+
+/// The `[restore]` table, with the same keys as the options of `swaystart restore`.
+#[derive(Debug, Default, Clone, Deserialize)]
+#[serde(default)]
+pub struct Restore {
+    pub spawn: bool,
+    pub no_animation: bool,
+    pub no_overlay: bool,
+    pub include: Vec<String>,
+    pub exclude: Vec<String>,
+    pub event_log: Option<PathBuf>,
+    pub swap_timeout: Option<u64>,
+    pub idle_timeout: Option<u64>,
+    pub fail_fast: bool,
+    pub strict_timeouts: bool,
+    pub wait_lock: Option<u64>,
+    pub notify: bool,
+    pub timings: bool,
+    pub no_titles: bool,
+    pub first_match: bool,
+    /// Variables set in the environment of the spawned apps.
+    pub env: BTreeMap<String, String>,
+    pub exec_via: Option<crate::launch::ExecVia>,
+    pub backend: Option<crate::Backend>,
+    pub layout_dir: Option<PathBuf>,
+    pub pre_cmd: Vec<String>,
+    pub post_cmd: Vec<String>,
+    pub fatal_hooks: bool,
+}
+
+/// The `[diff]` table, with the same keys as the options of `swaystart diff`.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct Diff {
+    pub threshold: Option<f64>,
+}
+
+/// The `[daemon]` table, with the same keys as the options of `swaystart daemon`.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct Daemon {
+    pub layout_files: Vec<PathBuf>,
+    pub spawn: bool,
+}
+
+/// The `[log]` table, with the same keys as the `--log-*` options.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct Log {
+    pub file: Option<PathBuf>,
+    pub level: Option<log::LevelFilter>,
+    pub truncate: bool,
+    pub trace_file: Option<PathBuf>,
+}
+
+/// A workspace of a group, which can be written as a number for the numbered ones.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(untagged)]
+pub enum WorkspaceName {
+    Name(String),
+    Number(i64),
+}
+
+impl std::fmt::Display for WorkspaceName {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            WorkspaceName::Name(name) => write!(f, "{}", name),
+            WorkspaceName::Number(num) => write!(f, "{}", num),
+        }
+    }
+}
+
+/// The names of the workspaces in the groups called `names`.
+pub fn group_workspaces(
+    groups: &BTreeMap<String, Vec<WorkspaceName>>,
+    names: &[String],
+) -> Result<Vec<String>, Error> {
+    let mut workspaces = Vec::new();
+    for name in names {
+        let Some(group) = groups.get(name) else {
+            if groups.is_empty() {
+                return Err(Error::InvalidConfig(
+                    format!("unknown group {:?}, the config defines no groups", name).into(),
+                ));
+            }
+            let known: Vec<&str> = groups.keys().map(String::as_str).collect();
+            return Err(Error::InvalidConfig(
+                format!(
+                    "unknown group {:?}, the config defines {}",
+                    name,
+                    known.join(", ")
+                )
+                .into(),
+            ));
+        };
+        workspaces.extend(group.iter().map(|w| w.to_string()));
+    }
+    Ok(workspaces)
+}
+
+/// Where the config file is looked for when no `--config` is given.
+fn default_path() -> Option<PathBuf> {
+    Some(dirs::config_dir()?.join("swaystart").join("config.toml"))
+}
+
+/// Loads the config file at `path`, or the one at the default location if there is one.
+pub fn load(path: Option<&Path>) -> Result<Config, Error> {
+    let path = match path {
+        Some(path) => path.to_owned(),
+        None => match default_path() {
+            Some(path) if path.exists() => path,
+            _ => return Ok(Config::default()),
+        },
+    };
+    let text = std::fs::read_to_string(&path)
+        .with_context(|| format!("reading the config from {}", path.display()))
+        .map_err(|e| Error::InvalidConfig(e.into()))?;
+    let de = toml::Deserializer::new(&text);
+    // A misspelled key would otherwise be ignored without a word.
+    let config = serde_ignored::deserialize(de, |key| {
+        eprintln!("warning: unknown key {} in {}", key, path.display());
+    })
+    .with_context(|| format!("parsing the config from {}", path.display()))
+    .map_err(|e| Error::InvalidConfig(e.into()))?;
+    Ok(config)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Format, RestoreOptions};
+    use clap::Parser;
+
+    #[derive(Parser)]
+    struct Cli {
+        #[command(flatten)]
+        restore: RestoreOptions,
+    }
+
+    /// The options of `swaystart restore` given `args`, with the defaults of `config`.
+    fn restore(args: &[&str], config: &str) -> Result<RestoreOptions, Error> {
+        let argv = std::iter::once("swaystart").chain(args.iter().copied());
+        let mut options = Cli::try_parse_from(argv).unwrap().restore;
+        let config: Config = toml::from_str(config).unwrap();
+        options.apply(config.restore)?;
+        Ok(options)
+    }
+
+    const CONFIG: &str = r#"
+        [restore]
+        swap_timeout = 30
+        idle_timeout = 5
+        include = ["app_id=firefox"]
+        pre_cmd = ["echo pre"]
+        fail_fast = true
+
+        [restore.env]
+        GDK_BACKEND = "wayland"
+        MOZ_ENABLE_WAYLAND = "1"
+    "#;
+
+    #[test]
+    fn command_line_takes_precedence() {
+        let args = [
+            "--layout-file=layout.json",
+            "--swap-timeout=10",
+            "--include=foot",
+            "--env=GDK_BACKEND=x11",
+        ];
+        let options = restore(&args, CONFIG).unwrap();
+        assert_eq!(options.swap_timeout, Some(10));
+        assert_eq!(options.include, ["foot"]);
+        assert_eq!(
+            options.env,
+            [
+                ("GDK_BACKEND".to_owned(), "x11".to_owned()),
+                ("MOZ_ENABLE_WAYLAND".to_owned(), "1".to_owned()),
+            ]
+        );
+    }
+
+    #[test]
+    fn config_fills_in_the_rest() {
+        let options = restore(&["--layout-file=layout.json"], CONFIG).unwrap();
+        assert_eq!(options.swap_timeout, Some(30));
+        assert_eq!(options.idle_timeout, Some(5));
+        assert_eq!(options.include, ["firefox"]);
+        assert_eq!(options.pre_cmd, ["echo pre"]);
+        // Flags can be turned on from either.
+        assert!(options.fail_fast);
+        assert!(!options.spawn);
+        let options = restore(&["--layout-file=layout.json", "--spawn"], CONFIG).unwrap();
+        assert!(options.spawn && options.fail_fast);
+    }
+
+    #[test]
+    fn detach_conflicts_are_config_errors() {
+        // clap catches the conflicts on the command line, but not the ones of a caller.
+        let mut options = restore(&["--layout-file=layout.json", "--detach"], "").unwrap();
+        options.format = Format::Json;
+        let e = options.apply(Restore::default()).unwrap_err();
+        assert!(matches!(e, Error::InvalidConfig(_)));
+    }
+
+    #[test]
+    fn bad_filter_in_the_config_is_a_config_error() {
+        let e = restore(
+            &["--layout-file=layout.json"],
+            "[restore]\ninclude = [\"\"]",
+        );
+        assert!(matches!(e, Err(Error::InvalidConfig(_))));
+    }
+
+    #[test]
+    fn groups_name_their_workspaces() {
+        let config: Config = toml::from_str("[groups]\nweb = [1, \"mail\"]\ncode = [3]").unwrap();
+        let names = ["code".to_owned(), "web".to_owned()];
+        let workspaces = group_workspaces(&config.groups, &names).unwrap();
+        assert_eq!(workspaces, ["3", "1", "mail"]);
+        let e = group_workspaces(&config.groups, &["chat".to_owned()]).unwrap_err();
+        assert_eq!(
+            e.to_string(),
+            "unknown group \"chat\", the config defines code, web"
+        );
+    }
+}
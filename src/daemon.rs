@@ -0,0 +1,149 @@
+use crate::dbus::Service;
+use crate::events::{CompositorGone, Events};
+use crate::notify::Notifier;
+use crate::status::{self, Error};
+use crate::{connect, load_layout, tree};
+use anyhow::Result;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+use swayipc::{Connection, Event, EventType, NodeType, WorkspaceChange};
+
+/// How often the requests of the D-Bus service are looked at while waiting for workspaces.
+const REQUEST_POLL: Duration = Duration::from_millis(200);
+
+/// A restore for the daemon to run.
+pub struct Job<'a> {
+    pub layout_file: &'a Path,
+    /// The workspace to restore, all of them if `None`.
+    pub workspace: Option<&'a str>,
+    /// Whether to spawn the apps, besides when the daemon does anyway.
+    pub spawn: bool,
+}
+
+/// Runs the restores asked for over D-Bus so far.
+fn serve(
+    service: &Service,
+    restore: &mut impl FnMut(Job) -> Result<(), Error>,
+) -> Result<(), Error> {
+    while let Ok(request) = service.requests.try_recv() {
+        log::info!("restoring {}", request.layout_file.display());
+        let res = restore(Job {
+            layout_file: &request.layout_file,
+            workspace: None,
+            spawn: request.spawn,
+        });
+        service.handle.end();
+        match res {
+            Ok(()) => {}
+            Err(e @ Error::CompositorGone(_)) => return Err(e),
+            Err(e) => log::error!("{}", status::describe(&e)),
+        }
+    }
+    Ok(())
+}
+
+/// Restores the workspaces of the layouts in `layout_files` whenever sway creates them, until
+/// sway goes away.
+///
+/// With a D-Bus `service`, the restores asked for over D-Bus are run too, one at a time with the
+/// others.
+pub fn run(
+    layout_files: &[PathBuf],
+    service: Option<Service>,
+    mut restore: impl FnMut(Job) -> Result<(), Error>,
+) -> Result<()> {
+    if layout_files.is_empty() {
+        anyhow::bail!("no layout file, give one with -l or in the [daemon] table of the config");
+    }
+    let mut layouts: HashMap<String, &Path> = HashMap::new();
+    for path in layout_files {
+        for w in load_layout(path)?.workspaces {
+            if let Some(first) = layouts.get(&w.name) {
+                log::warn!(
+                    "workspace {} is in both {} and {}, using the first",
+                    w.name,
+                    first.display(),
+                    path.display()
+                );
+                continue;
+            }
+            layouts.insert(w.name, path);
+        }
+    }
+    let mut conn = connect()?;
+    let mut events = Events::subscribe(&[EventType::Workspace, EventType::Shutdown])?;
+    let mut notifier = Notifier::from_env();
+    notifier.ready();
+    notifier.status("waiting for workspaces");
+    loop {
+        let poll = service.as_ref().map(|_| Instant::now() + REQUEST_POLL);
+        let event = events.next_until(poll.into_iter().chain(notifier.next_ping()).min());
+        notifier.watchdog();
+        if let Some(service) = &service {
+            match serve(service, &mut restore) {
+                Ok(()) => notifier.status("waiting for workspaces"),
+                Err(Error::CompositorGone(_)) => return Ok(()),
+                Err(e) => return Err(e.into()),
+            }
+        }
+        let event = match event {
+            Ok(Some(event)) => event,
+            Ok(None) => continue,
+            Err(e) if e.is::<CompositorGone>() => return Ok(()),
+            Err(e) => return Err(e),
+        };
+        let Event::Workspace(w) = event else {
+            continue;
+        };
+        if w.change != WorkspaceChange::Init {
+            continue;
+        }
+        let Some(name) = w.current.and_then(|n| n.name) else {
+            continue;
+        };
+        let Some(path) = layouts.get(&name) else {
+            continue;
+        };
+        // Restores run one at a time, so a workspace created during the previous one may have
+        // been filled or closed since.
+        if !is_empty(&mut conn, &name)? {
+            log::debug!(
+                "workspace {} is gone or has windows, leaving it alone",
+                name
+            );
+            continue;
+        }
+        log::info!("restoring workspace {} from {}", name, path.display());
+        if let Some(service) = &service {
+            service.handle.begin();
+        }
+        let res = restore(Job {
+            layout_file: path,
+            workspace: Some(&name),
+            spawn: false,
+        });
+        if let Some(service) = &service {
+            service.handle.end();
+        }
+        notifier.status("waiting for workspaces");
+        match res {
+            Ok(()) => {}
+            Err(Error::CompositorGone(_)) => return Ok(()),
+            Err(e) => log::error!("{}, waiting for the next workspace", status::describe(&e)),
+        }
+    }
+}
+
+/// Whether the workspace `name` exists and has no windows.
+fn is_empty(conn: &mut Connection, name: &str) -> Result<bool> {
+    let root = conn.get_tree()?;
+    let workspace = tree::iter_nodes(&root, tree::Order::Document)
+        .map(|(n, _, _)| n)
+        .find(|n| n.node_type == NodeType::Workspace && n.name.as_deref() == Some(name));
+    Ok(workspace.is_some_and(|w| {
+        tree::iter_views(w, tree::Order::Document, false)
+            .next()
+            .is_none()
+    }))
+}
@@ -0,0 +1,260 @@
+use crate::{auto, lock};
+use anyhow::{Context, Result};
+use gio::prelude::*;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::sync::{Arc, Mutex};
+
+const NAME: &str = "io.github.swaystart";
+const PATH: &str = "/io/github/swaystart";
+const INTERFACE: &str = r#"<node>
+  <interface name="io.github.swaystart">
+    <method name="Save">
+      <arg name="profile" type="s" direction="in"/>
+    </method>
+    <method name="Restore">
+      <arg name="profile" type="s" direction="in"/>
+      <arg name="flags" type="as" direction="in"/>
+    </method>
+    <method name="Cancel"/>
+    <method name="Status">
+      <arg name="state" type="s" direction="out"/>
+      <arg name="done" type="u" direction="out"/>
+      <arg name="total" type="u" direction="out"/>
+    </method>
+    <signal name="Progress">
+      <arg name="done" type="u"/>
+      <arg name="total" type="u"/>
+      <arg name="pending" type="as"/>
+    </signal>
+  </interface>
+</node>"#;
+
+/// A restore asked for with the `Restore` method.
+pub struct Request {
+    pub layout_file: PathBuf,
+    /// Whether to spawn the apps, besides when the daemon does anyway.
+    pub spawn: bool,
+}
+
+#[derive(Debug, Default)]
+struct State {
+    restoring: bool,
+    done: u32,
+    total: u32,
+}
+
+/// The side of the service that the restores see, telling how far they got and whether they
+/// were cancelled.
+#[derive(Debug, Clone)]
+pub struct Handle {
+    conn: gio::DBusConnection,
+    state: Arc<Mutex<State>>,
+    cancel: Arc<AtomicBool>,
+}
+
+impl Handle {
+    fn state(&self) -> std::sync::MutexGuard<'_, State> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+    /// Marks a restore as running.
+    pub fn begin(&self) {
+        *self.state() = State {
+            restoring: true,
+            ..State::default()
+        };
+        self.cancel.store(false, Ordering::SeqCst);
+    }
+    pub fn end(&self) {
+        *self.state() = State::default();
+    }
+    /// Emits the `Progress` signal, `done` slots out of `total` having their window.
+    pub fn progress(&self, done: usize, total: usize, pending: &[String]) {
+        let (done, total) = (done as u32, total as u32);
+        {
+            let mut state = self.state();
+            state.done = done;
+            state.total = total;
+        }
+        let params = (done, total, pending.to_vec()).to_variant();
+        if let Err(e) = self
+            .conn
+            .emit_signal(None, PATH, NAME, "Progress", Some(&params))
+        {
+            log::warn!("could not emit the progress signal: {}", e);
+        }
+    }
+    /// Whether the `Cancel` method was called since the restore began.
+    pub fn cancelled(&self) -> bool {
+        self.cancel.load(Ordering::SeqCst)
+    }
+}
+
+/// The `io.github.swaystart` service of the daemon, on the session bus.
+pub struct Service {
+    pub handle: Handle,
+    /// The restores asked for, once the service made sure none is running.
+    pub requests: Receiver<Request>,
+}
+
+/// The layout file of `profile`: a path, the name of a layout in `layout_dir`, or if empty, the
+/// one picked as with `--auto`.
+fn layout_file(profile: &str, layout_dir: Option<&Path>) -> Result<PathBuf> {
+    if profile.is_empty() {
+        return Ok(auto::choose(layout_dir)?);
+    }
+    if profile.contains('/') {
+        return Ok(PathBuf::from(profile));
+    }
+    Ok(auto::dir(layout_dir)?.join(format!("{}.json", profile)))
+}
+
+/// Whether the flags of a `Restore` call ask to spawn the apps, the only flag there is.
+fn parse_flags(flags: &[String]) -> Result<bool> {
+    let mut spawn = false;
+    for flag in flags {
+        match flag.as_str() {
+            "spawn" => spawn = true,
+            _ => anyhow::bail!("unknown flag {:?}, expected spawn", flag),
+        }
+    }
+    Ok(spawn)
+}
+
+/// Answers a method call, returning the D-Bus error to reply with if it fails.
+fn call(
+    handle: &Handle,
+    requests: &Sender<Request>,
+    layout_dir: Option<&Path>,
+    method: &str,
+    params: &glib::Variant,
+) -> Result<Option<glib::Variant>, (&'static str, String)> {
+    let invalid = |e: anyhow::Error| ("org.freedesktop.DBus.Error.InvalidArgs", format!("{:#}", e));
+    let failed = |e: anyhow::Error| ("org.freedesktop.DBus.Error.Failed", format!("{:#}", e));
+    let busy = |msg: String| ("io.github.swaystart.Error.Busy", msg);
+    match method {
+        "Save" => Err((
+            "org.freedesktop.DBus.Error.NotSupported",
+            "saving layouts is not supported, layouts are written by hand".to_owned(),
+        )),
+        "Restore" => {
+            let (profile, flags) = params
+                .get::<(String, Vec<String>)>()
+                .ok_or_else(|| invalid(anyhow::anyhow!("expected (sas)")))?;
+            let spawn = parse_flags(&flags).map_err(invalid)?;
+            let layout_file = layout_file(&profile, layout_dir).map_err(invalid)?;
+            if let Some(pid) = lock::current_holder().map_err(failed)? {
+                return Err(busy(format!("swaystart {} is restoring", pid)));
+            }
+            // The restore counts as running from now on, so that a second call is turned down
+            // even before the daemon gets to this one.
+            let mut state = handle.state();
+            if state.restoring {
+                return Err(busy("a restore is running".to_owned()));
+            }
+            log::info!("restore of {} asked over D-Bus", layout_file.display());
+            let request = Request { layout_file, spawn };
+            if requests.send(request).is_err() {
+                return Err(failed(anyhow::anyhow!("the daemon is shutting down")));
+            }
+            *state = State {
+                restoring: true,
+                ..State::default()
+            };
+            Ok(None)
+        }
+        "Cancel" => {
+            if !handle.state().restoring {
+                return Err((
+                    "io.github.swaystart.Error.NotRunning",
+                    "no restore is running".to_owned(),
+                ));
+            }
+            handle.cancel.store(true, Ordering::SeqCst);
+            Ok(None)
+        }
+        "Status" => {
+            let state = handle.state();
+            let name = if state.restoring { "restoring" } else { "idle" };
+            Ok(Some((name, state.done, state.total).to_variant()))
+        }
+        _ => Err((
+            "org.freedesktop.DBus.Error.UnknownMethod",
+            format!("no method {}", method),
+        )),
+    }
+}
+
+fn register(handle: &Handle, requests: Sender<Request>, layout_dir: Option<PathBuf>) -> Result<()> {
+    let node = gio::DBusNodeInfo::for_xml(INTERFACE)?;
+    let interface = node
+        .lookup_interface(NAME)
+        .context("the interface is missing from its description")?;
+    let state = handle.clone();
+    handle
+        .conn
+        .register_object(PATH, &interface)
+        .method_call(move |_, _, _, _, method, params, invocation| {
+            match call(&state, &requests, layout_dir.as_deref(), method, &params) {
+                Ok(value) => invocation.return_value(value.as_ref()),
+                Err((name, msg)) => invocation.return_dbus_error(name, &msg),
+            }
+        })
+        .build()?;
+    gio::bus_own_name_on_connection(
+        &handle.conn,
+        NAME,
+        gio::BusNameOwnerFlags::NONE,
+        |_, _| {},
+        |_, name| log::warn!("could not own {} on the session bus", name),
+    );
+    Ok(())
+}
+
+/// Starts serving `io.github.swaystart` on the session bus from a thread of its own.
+///
+/// Without a session bus there is no service, and the daemon works as before.
+pub fn start(layout_dir: Option<PathBuf>) -> Option<Service> {
+    let conn = match gio::bus_get_sync(gio::BusType::Session, None::<&gio::Cancellable>) {
+        Ok(conn) => conn,
+        Err(e) => {
+            log::warn!("no D-Bus service, the session bus is unavailable: {}", e);
+            return None;
+        }
+    };
+    let handle = Handle {
+        conn,
+        state: Arc::default(),
+        cancel: Arc::default(),
+    };
+    let (requests, receiver) = channel();
+    let (registered, result) = channel();
+    let service = handle.clone();
+    std::thread::spawn(move || {
+        // The method calls are dispatched by the context that was the default when registering.
+        let context = glib::MainContext::new();
+        let res = context.with_thread_default(|| {
+            let res = register(&service, requests, layout_dir);
+            let ok = res.is_ok();
+            let _ = registered.send(res);
+            if ok {
+                glib::MainLoop::new(Some(&context), false).run();
+            }
+        });
+        if let Err(e) = res {
+            log::warn!("the D-Bus service stopped: {}", e);
+        }
+    });
+    match result.recv() {
+        Ok(Ok(())) => Some(Service {
+            handle,
+            requests: receiver,
+        }),
+        Ok(Err(e)) => {
+            log::warn!("no D-Bus service: {:#}", e);
+            None
+        }
+        Err(_) => None,
+    }
+}
@@ -0,0 +1,72 @@
+use crate::report::Summary;
+use anyhow::Result;
+use gio::prelude::*;
+use std::collections::HashMap;
+
+/// How long the notification server may take to answer, in milliseconds.
+const NOTIFY_TIMEOUT_MS: i32 = 1000;
+/// How many of the slots without their window are listed in a notification.
+const MAX_LISTED: usize = 10;
+
+/// Shows a desktop notification through the org.freedesktop.Notifications D-Bus interface.
+///
+/// Failing to notify is only logged: there may be no notification server, and that is no reason
+/// to make the restore fail.
+pub fn notify(summary: &str, body: &str) {
+    if let Err(e) = send(summary, body) {
+        log::warn!("could not show a notification: {}", e);
+    }
+}
+
+/// Tells how many windows the restore of `summary` brought back, listing the slots left empty.
+pub fn notify_restored(summary: &Summary, success: bool) {
+    let title = if success {
+        "Layout restored"
+    } else {
+        "Layout partly restored"
+    };
+    let mut body = summary.headline();
+    let unfilled: Vec<_> = summary.unfilled().collect();
+    for slot in unfilled.iter().take(MAX_LISTED) {
+        body.push_str(&format!(
+            "\n{}: {} {}",
+            slot.workspace, slot.id, slot.outcome
+        ));
+        if let Some(path) = &slot.layout_file {
+            body.push_str(&format!(" ({})", path.display()));
+        }
+    }
+    if unfilled.len() > MAX_LISTED {
+        body.push_str(&format!("\nand {} more", unfilled.len() - MAX_LISTED));
+    }
+    notify(title, &body);
+}
+
+fn send(summary: &str, body: &str) -> Result<()> {
+    let bus = gio::bus_get_sync(gio::BusType::Session, None::<&gio::Cancellable>)?;
+    let hints: HashMap<String, glib::Variant> = HashMap::new();
+    // Notify(app_name, replaces_id, app_icon, summary, body, actions, hints, expire_timeout)
+    let params = (
+        "swaystart",
+        0u32,
+        "",
+        summary,
+        body,
+        Vec::<String>::new(),
+        hints,
+        -1i32,
+    )
+        .to_variant();
+    bus.call_sync(
+        Some("org.freedesktop.Notifications"),
+        "/org/freedesktop/Notifications",
+        "org.freedesktop.Notifications",
+        "Notify",
+        Some(&params),
+        None,
+        gio::DBusCallFlags::NONE,
+        NOTIFY_TIMEOUT_MS,
+        None::<&gio::Cancellable>,
+    )?;
+    Ok(())
+}
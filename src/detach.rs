@@ -0,0 +1,97 @@
+use crate::status::Error;
+use anyhow::{Context, Result};
+use std::fs::{File, OpenOptions};
+use std::io::{BufRead, BufReader, Write};
+use std::os::fd::{AsFd, AsRawFd};
+use std::os::unix::process::CommandExt;
+use std::process::{Command, Stdio};
+use std::sync::Mutex;
+
+/// Set in the environment of the swaystart running a `--detach` restore in the background.
+const BACKGROUND_ENV: &str = "SWAYSTART_DETACHED";
+/// The line the background swaystart prints once the layout is built.
+const READY: &str = "ready";
+
+/// The pipe to the foreground swaystart, in the background swaystart until it is told the
+/// layout is built.
+static PIPE: Mutex<Option<File>> = Mutex::new(None);
+
+/// Whether this is the background swaystart of a `--detach` restore, forgetting it so that the
+/// apps it spawns don't inherit it.
+fn take_background() -> bool {
+    let background = std::env::var_os(BACKGROUND_ENV).is_some();
+    std::env::remove_var(BACKGROUND_ENV);
+    background
+}
+
+/// Moves the pipe to the foreground swaystart from stdout to a descriptor of its own, and
+/// points stdout at /dev/null instead.
+///
+/// The foreground swaystart stops reading once the layout is built, so writing to the pipe
+/// would fail from then on, for the background swaystart as for the apps and hooks it starts
+/// with its stdout. The descriptor of the pipe is not inherited by them.
+fn keep_pipe() -> Result<()> {
+    let pipe = std::io::stdout()
+        .as_fd()
+        .try_clone_to_owned()
+        .context("duplicating stdout")?;
+    let null = OpenOptions::new()
+        .write(true)
+        .open("/dev/null")
+        .context("opening /dev/null")?;
+    // SAFETY: both descriptors are open, and stdout is not otherwise written to yet.
+    if unsafe { libc::dup2(null.as_raw_fd(), libc::STDOUT_FILENO) } == -1 {
+        return Err(std::io::Error::last_os_error()).context("pointing stdout at /dev/null");
+    }
+    *PIPE.lock().unwrap_or_else(|e| e.into_inner()) = Some(File::from(pipe));
+    Ok(())
+}
+
+/// Runs swaystart again in the background with the same arguments, and returns once it has
+/// built the layout, or `false` if this is that background swaystart.
+///
+/// Exits with the status of the background swaystart if it fails before the layout is built,
+/// its errors having been printed on the shared stderr.
+pub fn run() -> Result<bool, Error> {
+    if take_background() {
+        keep_pipe()?;
+        return Ok(false);
+    }
+    let exe = std::env::current_exe().context("finding the swaystart executable")?;
+    let mut child = Command::new(exe)
+        .args(std::env::args_os().skip(1))
+        .env(BACKGROUND_ENV, "1")
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        // Closing the terminal must not take the background swaystart down with it.
+        .process_group(0)
+        .spawn()
+        .context("starting swaystart in the background")?;
+    let stdout = child.stdout.take().expect("piped");
+    let ready = BufReader::new(stdout)
+        .lines()
+        .map_while(Result::ok)
+        .any(|line| line == READY);
+    if ready {
+        log::info!("restore continues in the background as {}", child.id());
+        return Ok(true);
+    }
+    let status = child
+        .wait()
+        .context("waiting for the background swaystart")?;
+    std::process::exit(status.code().unwrap_or(1));
+}
+
+/// Tells the foreground swaystart that the layout is built, so that it can exit, and closes the
+/// pipe to it.
+pub fn ready() {
+    let Some(mut pipe) = PIPE.lock().unwrap_or_else(|e| e.into_inner()).take() else {
+        return;
+    };
+    if let Err(e) = writeln!(pipe, "{}", READY).and_then(|_| pipe.flush()) {
+        log::warn!(
+            "could not tell the foreground swaystart the layout is built: {}",
+            e
+        );
+    }
+}
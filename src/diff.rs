@@ -0,0 +1,402 @@
+use crate::pattern::Patterns;
+use crate::{
+    is_placeholder, matcher, tree, FloatingApp, LayoutStyle, LayoutVisitor, Output, Slot,
+    VisitAction, VisitContext, WindowFilter, Workspace,
+};
+use anyhow::Result;
+use serde::Serialize;
+use std::collections::VecDeque;
+use swayipc::{Node, NodeType};
+
+/// A way in which the session differs from the layout.
+#[derive(Debug, Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Difference {
+    MissingOutput {
+        output: String,
+    },
+    MissingWorkspace {
+        workspace: String,
+    },
+    /// A workspace of the output that the layout does not mention.
+    ExtraWorkspace {
+        workspace: String,
+    },
+    /// A slot with no window to fill it on its workspace.
+    MissingWindow {
+        workspace: String,
+        id: String,
+    },
+    /// A window that fills no slot of its workspace.
+    ExtraWindow {
+        workspace: String,
+        con_id: i64,
+        matcher: Option<String>,
+    },
+    /// A window whose share of its container is not the size of its slot.
+    Size {
+        workspace: String,
+        id: String,
+        con_id: i64,
+        expected: f64,
+        actual: f64,
+    },
+}
+
+impl std::fmt::Display for Difference {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Difference::MissingOutput { output } => write!(f, "output {} is not connected", output),
+            Difference::MissingWorkspace { workspace } => {
+                write!(f, "workspace {} is missing", workspace)
+            }
+            Difference::ExtraWorkspace { workspace } => {
+                write!(f, "workspace {} is not in the layout", workspace)
+            }
+            Difference::MissingWindow { workspace, id } => {
+                write!(f, "{}: no window for {}", workspace, id)
+            }
+            Difference::ExtraWindow {
+                workspace,
+                con_id,
+                matcher,
+            } => write!(
+                f,
+                "{}: window {} ({}) is not in the layout",
+                workspace,
+                con_id,
+                matcher.as_deref().unwrap_or("no matcher")
+            ),
+            Difference::Size {
+                workspace,
+                id,
+                con_id,
+                expected,
+                actual,
+            } => write!(
+                f,
+                "{}: window {} for {} takes {:.0}% of its container instead of {:.0}%",
+                workspace,
+                con_id,
+                id,
+                actual * 100.,
+                expected * 100.
+            ),
+        }
+    }
+}
+
+/// Compares a layout to the session, matching the slots of each workspace with its windows in
+/// order.
+pub struct Differ {
+    tree: Node,
+    /// How far the share of a window may be from the size of its slot.
+    threshold: f64,
+    pub differences: Vec<Difference>,
+    workspace: String,
+    /// Windows of the workspace not matched with a slot yet.
+    unclaimed: Vec<Node>,
+    /// Size of the slot being visited.
+    size: Option<f64>,
+    /// Filter of the slot being visited.
+    filter: WindowFilter,
+    /// Filters of the floating apps of the workspace not visited yet, in order.
+    floating_filters: VecDeque<WindowFilter>,
+    patterns: Patterns,
+}
+
+impl Differ {
+    pub fn new(tree: Node, threshold: f64) -> Differ {
+        Differ {
+            tree,
+            threshold,
+            differences: Vec::new(),
+            workspace: String::new(),
+            unclaimed: Vec::new(),
+            size: None,
+            filter: WindowFilter::default(),
+            floating_filters: VecDeque::new(),
+            patterns: Patterns::default(),
+        }
+    }
+    fn find_output(&self, name: &str) -> Option<&Node> {
+        self.tree
+            .nodes
+            .iter()
+            .find(|o| o.node_type == NodeType::Output && o.name.as_deref() == Some(name))
+    }
+    /// Pairs the slot `id` with the window that restore would give it, the one fitting `filter`
+    /// with the closest title.
+    fn claim(&mut self, id: &str, size: Option<f64>, filter: &WindowFilter, ctx: &VisitContext) {
+        let patterns = &mut self.patterns;
+        let mut best: Option<(f64, usize)> = None;
+        for (idx, n) in self.unclaimed.iter().enumerate() {
+            if !(matcher(n).is_some_and(|m| patterns.matches(id, m)) && filter.fits(n)) {
+                continue;
+            }
+            let score = filter.title_score(n);
+            if best.is_none_or(|(s, _)| score > s) {
+                best = Some((score, idx));
+            }
+        }
+        let Some((_, idx)) = best else {
+            self.differences.push(Difference::MissingWindow {
+                workspace: self.workspace.clone(),
+                id: id.to_owned(),
+            });
+            return;
+        };
+        let node = self.unclaimed.remove(idx);
+        // Tabs all take the whole container.
+        let split = matches!(
+            ctx.parent,
+            Some(LayoutStyle::Splith) | Some(LayoutStyle::Splitv)
+        );
+        if let (true, Some(expected), Some(actual)) = (split, size, node.percent) {
+            if (expected - actual).abs() > self.threshold {
+                self.differences.push(Difference::Size {
+                    workspace: self.workspace.clone(),
+                    id: id.to_owned(),
+                    con_id: node.id,
+                    expected,
+                    actual,
+                });
+            }
+        }
+    }
+}
+
+impl LayoutVisitor for Differ {
+    fn on_output(&mut self, output: &Output, _ctx: &VisitContext) -> Result<VisitAction> {
+        if self.find_output(&output.name).is_none() {
+            self.differences.push(Difference::MissingOutput {
+                output: output.name.clone(),
+            });
+            return Ok(VisitAction::SkipChildren);
+        }
+        Ok(VisitAction::Continue)
+    }
+    fn on_output_exit(&mut self, output: &Output, _ctx: &VisitContext) -> Result<()> {
+        let extra: Vec<_> = self
+            .find_output(&output.name)
+            .into_iter()
+            .flat_map(|o| &o.nodes)
+            .filter_map(|w| w.name.clone())
+            .filter(|name| !output.workspaces.iter().any(|w| w.name == *name))
+            .map(|workspace| Difference::ExtraWorkspace { workspace })
+            .collect();
+        self.differences.extend(extra);
+        Ok(())
+    }
+    fn on_workspace(&mut self, workspace: &Workspace, ctx: &VisitContext) -> Result<VisitAction> {
+        let node = self.find_output(ctx.output).and_then(|o| {
+            o.nodes
+                .iter()
+                .find(|w| w.name.as_deref() == Some(workspace.name.as_str()))
+        });
+        let Some(node) = node else {
+            self.differences.push(Difference::MissingWorkspace {
+                workspace: workspace.name.clone(),
+            });
+            return Ok(VisitAction::SkipChildren);
+        };
+        self.unclaimed = tree::iter_views(node, tree::Order::Document, false)
+            .filter(|n| !is_placeholder(n))
+            .cloned()
+            .collect();
+        self.workspace = workspace.name.clone();
+        self.floating_filters = workspace
+            .floating
+            .iter()
+            .map(|f| match f {
+                FloatingApp::App(_) => WindowFilter::default(),
+                FloatingApp::AppWithId { filter, .. } => (**filter).clone(),
+            })
+            .collect();
+        Ok(VisitAction::Continue)
+    }
+    fn on_workspace_exit(&mut self, _workspace: &Workspace, _ctx: &VisitContext) -> Result<()> {
+        for node in std::mem::take(&mut self.unclaimed) {
+            self.differences.push(Difference::ExtraWindow {
+                workspace: self.workspace.clone(),
+                con_id: node.id,
+                matcher: matcher(&node).map(str::to_owned),
+            });
+        }
+        Ok(())
+    }
+    fn on_slot(&mut self, slot: &Slot, _ctx: &VisitContext) -> Result<VisitAction> {
+        self.size = Some(slot.size);
+        self.filter = slot.filter.clone();
+        Ok(VisitAction::Continue)
+    }
+    fn on_app(&mut self, _app: &str, id: &str, ctx: &VisitContext) -> Result<()> {
+        let size = self.size.take();
+        let filter = std::mem::take(&mut self.filter);
+        self.claim(id, size, &filter, ctx);
+        Ok(())
+    }
+    fn on_floating_app(&mut self, _app: &str, id: &str, ctx: &VisitContext) -> Result<()> {
+        let filter = self.floating_filters.pop_front().unwrap_or_default();
+        self.claim(id, None, &filter, ctx);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ipc::mock::node;
+    use crate::Output;
+    use serde_json::json;
+
+    /// A session with the windows `views` on the workspace `1` of the output `OUT`.
+    fn session(views: Vec<Node>) -> Node {
+        let workspace = node(json!({ "id": 3, "type": "workspace", "name": "1", "nodes": views }));
+        let output =
+            node(json!({ "id": 2, "type": "output", "name": "OUT", "nodes": [workspace] }));
+        node(json!({ "id": 1, "type": "root", "nodes": [output] }))
+    }
+
+    fn differences(session: Node, slots: serde_json::Value) -> Vec<Difference> {
+        let output: Output = serde_json::from_value(json!({
+            "name": "OUT",
+            "workspaces": [{
+                "name": "1",
+                "style": "splith",
+                "layout": { "style": "splith", "slots": slots },
+            }],
+        }))
+        .unwrap();
+        let mut differ = Differ::new(session, 0.05);
+        differ.visit_output(&output).unwrap();
+        differ.differences
+    }
+
+    #[test]
+    fn pairs_windows_with_slots_through_their_filters() {
+        let views = vec![
+            node(json!({ "id": 10, "app_id": "foot", "name": "vim" })),
+            node(json!({ "id": 11, "app_id": "foot", "name": "htop" })),
+        ];
+        let slots = json!([
+            { "content": "foot", "title_contains": "htop" },
+            { "content": "foot" },
+        ]);
+        assert!(differences(session(views), slots).is_empty());
+
+        let views = vec![node(json!({ "id": 10, "app_id": "foot", "name": "vim" }))];
+        let slots = json!([{ "content": "foot", "title_contains": "htop" }]);
+        let found: Vec<String> = differences(session(views), slots)
+            .iter()
+            .map(ToString::to_string)
+            .collect();
+        assert_eq!(
+            found,
+            [
+                "1: no window for foot",
+                "1: window 10 (foot) is not in the layout"
+            ]
+        );
+    }
+
+    /// What the differences say, as `diff` prints them.
+    fn described(differences: &[Difference]) -> Vec<String> {
+        differences.iter().map(ToString::to_string).collect()
+    }
+
+    #[test]
+    fn finds_slots_without_a_window() {
+        let views = vec![node(json!({ "id": 10, "app_id": "foot" }))];
+        let slots = json!([{ "content": "foot" }, { "content": "firefox" }]);
+        let found = differences(session(views), slots);
+        assert!(matches!(
+            found.as_slice(),
+            [Difference::MissingWindow { workspace, id }] if workspace == "1" && id == "firefox"
+        ));
+        assert_eq!(described(&found), ["1: no window for firefox"]);
+    }
+
+    #[test]
+    fn finds_windows_without_a_slot() {
+        let views = vec![
+            node(json!({ "id": 10, "app_id": "foot" })),
+            node(json!({ "id": 11, "app_id": "mpv" })),
+            // Placeholders left over are not windows of the user.
+            node(json!({ "id": 12, "app_id": "swaystart-firefox" })),
+        ];
+        let found = differences(session(views), json!([{ "content": "foot" }]));
+        assert!(matches!(
+            found.as_slice(),
+            [Difference::ExtraWindow { con_id: 11, matcher: Some(m), .. }] if m == "mpv"
+        ));
+        assert_eq!(
+            described(&found),
+            ["1: window 11 (mpv) is not in the layout"]
+        );
+    }
+
+    #[test]
+    fn finds_windows_of_the_wrong_size() {
+        let views = vec![
+            node(json!({ "id": 10, "app_id": "foot", "percent": 0.7 })),
+            node(json!({ "id": 11, "app_id": "firefox", "percent": 0.3 })),
+        ];
+        let slots = json!([
+            { "size": 0.5, "content": "foot" },
+            { "size": 0.5, "content": "firefox" },
+        ]);
+        let found = differences(session(views.clone()), slots);
+        assert_eq!(
+            described(&found),
+            [
+                "1: window 10 for foot takes 70% of its container instead of 50%",
+                "1: window 11 for firefox takes 30% of its container instead of 50%",
+            ]
+        );
+        // Within the threshold, the sizes are the same.
+        let slots = json!([
+            { "size": 0.68, "content": "foot" },
+            { "size": 0.32, "content": "firefox" },
+        ]);
+        assert!(differences(session(views), slots).is_empty());
+    }
+
+    #[test]
+    fn finds_missing_and_extra_workspaces() {
+        let views = vec![node(json!({ "id": 10, "app_id": "foot" }))];
+        let output: Output = serde_json::from_value(json!({
+            "name": "OUT",
+            "workspaces": [{
+                "name": "2",
+                "style": "splith",
+                "layout": { "style": "splith", "slots": [{ "content": "foot" }] },
+            }],
+        }))
+        .unwrap();
+        let mut differ = Differ::new(session(views.clone()), 0.05);
+        differ.visit_output(&output).unwrap();
+        assert!(matches!(
+            differ.differences.as_slice(),
+            [
+                Difference::MissingWorkspace { workspace: missing },
+                Difference::ExtraWorkspace { workspace: extra },
+            ] if missing == "2" && extra == "1"
+        ));
+        assert_eq!(
+            described(&differ.differences),
+            ["workspace 2 is missing", "workspace 1 is not in the layout"]
+        );
+        // Nor is anything else compared on an output that is not there.
+        let output = Output {
+            name: "HDMI-A-1".to_owned(),
+            ..output
+        };
+        let mut differ = Differ::new(session(views), 0.05);
+        differ.visit_output(&output).unwrap();
+        assert_eq!(
+            described(&differ.differences),
+            ["output HDMI-A-1 is not connected"]
+        );
+    }
+}
@@ -0,0 +1,80 @@
+use crate::status::Error;
+use crate::{ask, read_layout};
+use anyhow::{Context, Result};
+use std::io::IsTerminal;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// Opens the layout at `path` in the user's editor, and replaces it with the edited version
+/// once that is valid.
+///
+/// The editing happens on a copy next to the layout, so the layout is left as it was until a
+/// valid version is renamed over it.
+pub fn edit(path: &Path) -> Result<(), Error> {
+    if path == Path::new("-") {
+        return Err(
+            anyhow::anyhow!("the layout read from stdin can't be edited, give its file").into(),
+        );
+    }
+    if !std::io::stdin().is_terminal() {
+        return Err(anyhow::anyhow!("editing a layout needs a terminal").into());
+    }
+    let editor = ["VISUAL", "EDITOR"]
+        .iter()
+        .filter_map(|var| std::env::var(var).ok())
+        .find(|e| !e.trim().is_empty())
+        .ok_or_else(|| anyhow::anyhow!("neither VISUAL nor EDITOR is set"))?;
+    let draft = draft_path(path)?;
+    std::fs::copy(path, &draft)
+        .with_context(|| format!("copying {} to {}", path.display(), draft.display()))?;
+    let res = edit_until_valid(&editor, path, &draft);
+    if res.is_err() {
+        let _ = std::fs::remove_file(&draft);
+    }
+    res?;
+    std::fs::rename(&draft, path)
+        .with_context(|| format!("moving {} to {}", draft.display(), path.display()))?;
+    Ok(())
+}
+
+/// The copy of the layout at `path` that is edited, a hidden file in the same directory so that
+/// it can be renamed over the layout.
+fn draft_path(path: &Path) -> Result<PathBuf> {
+    let name = path
+        .file_name()
+        .ok_or_else(|| anyhow::anyhow!("{} is not a file", path.display()))?;
+    // The extension is kept, for the editor to recognize the format.
+    let mut draft = std::ffi::OsString::from(".swaystart-edit-");
+    draft.push(name);
+    Ok(path.with_file_name(draft))
+}
+
+/// Runs `editor` on `draft`, the copy of the layout at `path`, until it is valid or the user
+/// gives up.
+fn edit_until_valid(editor: &str, path: &Path, draft: &Path) -> Result<()> {
+    loop {
+        // The editor is run by the shell, as it may come with arguments.
+        let status = Command::new("sh")
+            .arg("-c")
+            .arg(format!("{} \"$1\"", editor))
+            .arg("sh")
+            .arg(draft)
+            .status()
+            .with_context(|| format!("running {}", editor))?;
+        if !status.success() {
+            anyhow::bail!(
+                "{} exited with {}, leaving the layout as it was",
+                editor,
+                status
+            );
+        }
+        let e = match read_layout(draft, false) {
+            Ok(_) => return Ok(()),
+            Err(e) => e,
+        };
+        eprintln!("Error: {:#}", e);
+        if !ask("Edit the layout again?", true)? {
+            return Err(Error::invalid_layout(path, e).into());
+        }
+    }
+}
@@ -0,0 +1,83 @@
+use anyhow::{Context, Result};
+use serde_json::{json, Value};
+use std::fs::OpenOptions;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+use std::sync::mpsc::{channel, Sender};
+use std::thread::spawn;
+use std::time::SystemTime;
+use swayipc::WindowEvent;
+
+/// Version of the JSON written by swaystart, both in the event log and in the summary of a
+/// restore, to be increased when fields change meaning or go away.
+pub const FORMAT_VERSION: u32 = 1;
+
+/// Appends the window events and the decisions of the swapper to a file, one JSON object per
+/// line.
+///
+/// Writing happens on a helper thread so that a slow disk never holds up the event handling.
+pub struct EventLog {
+    sender: Sender<Value>,
+    /// Whether to leave out the titles of the windows.
+    no_titles: bool,
+}
+
+impl EventLog {
+    pub fn open(path: &Path, no_titles: bool) -> Result<Self> {
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .with_context(|| format!("opening event log {}", path.display()))?;
+        let (sender, receiver) = channel::<Value>();
+        spawn(move || {
+            let mut out = BufWriter::new(file);
+            // Everything queued is written at once, and flushed before waiting again so that
+            // the log is complete even if swaystart gets killed.
+            while let Ok(record) = receiver.recv() {
+                let res = std::iter::once(record)
+                    .chain(receiver.try_iter())
+                    .try_for_each(|r| writeln!(out, "{}", r))
+                    .and_then(|_| out.flush());
+                if let Err(e) = res {
+                    log::warn!("failed to write the event log: {}", e);
+                    return;
+                }
+            }
+        });
+        Ok(EventLog { sender, no_titles })
+    }
+
+    pub fn window(&self, event: &WindowEvent) {
+        let node = &event.container;
+        let props = node.window_properties.as_ref();
+        self.record(json!({
+            "event": "window",
+            "change": event.change,
+            "con_id": node.id,
+            "app_id": node.app_id,
+            "class": props.and_then(|p| p.class.as_ref()),
+            "instance": props.and_then(|p| p.instance.as_ref()),
+            "name": node.name.as_ref().filter(|_| !self.no_titles),
+        }));
+    }
+
+    /// Records what the swapper did, with `details` about the windows involved.
+    pub fn decision(&self, decision: &str, details: Value) {
+        self.record(json!({
+            "event": "decision",
+            "decision": decision,
+            "details": details,
+        }));
+    }
+
+    fn record(&self, mut record: Value) {
+        let time = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .unwrap_or_default();
+        record["time"] = json!(time.as_secs_f64());
+        record["version"] = json!(FORMAT_VERSION);
+        // The writer only goes away after failing, which it already reported.
+        let _ = self.sender.send(record);
+    }
+}
@@ -0,0 +1,419 @@
+use anyhow::{Context, Result};
+use std::collections::VecDeque;
+use std::sync::mpsc::{channel, Receiver, RecvTimeoutError};
+use std::thread::{sleep, spawn};
+use std::time::{Duration, Instant};
+use swayipc::{Connection, Error, Event, EventStream, EventType, Fallible, Node, WindowChange};
+
+/// How many times the subscription may be re-established within `RECONNECT_WINDOW` before the
+/// stream is considered broken for good.
+const MAX_RECONNECTS: usize = 3;
+const RECONNECT_WINDOW: Duration = Duration::from_secs(10);
+const RECONNECT_DELAY: Duration = Duration::from_millis(100);
+/// How much of the payload of an event that can't be decoded is logged.
+const LOGGED_PAYLOAD: usize = 512;
+
+/// Error returned when an awaited event does not arrive in time.
+#[derive(Debug)]
+pub struct TimedOut {
+    pub timeout: Duration,
+}
+
+impl std::fmt::Display for TimedOut {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "timed out after {:?}", self.timeout)
+    }
+}
+
+impl std::error::Error for TimedOut {}
+
+/// Error returned once sway shuts down or its IPC socket goes away.
+#[derive(Debug)]
+pub struct CompositorGone;
+
+impl std::fmt::Display for CompositorGone {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "sway exited")
+    }
+}
+
+impl std::error::Error for CompositorGone {}
+
+/// Where the events come from, sway or anything standing in for it.
+pub trait EventSource {
+    /// Returns the next event, or `None` if `deadline` passes before one arrives.
+    ///
+    /// Fails with [`CompositorGone`] once there will be no more events.
+    fn recv_until(&mut self, deadline: Option<Instant>) -> Result<Option<Event>>;
+    /// Whether events may have been missed since the last call.
+    fn take_reconnected(&mut self) -> bool;
+}
+
+/// A stream of events, which can be waited on for a specific one.
+pub struct Events {
+    source: Box<dyn EventSource>,
+    /// Events skipped while waiting for a specific one, returned first by `next_until`.
+    backlog: VecDeque<Event>,
+}
+
+/// A sway event subscription.
+///
+/// The events are read from the socket by a background thread, so that waiting for them can be
+/// bounded by a timeout instead of blocking forever.
+///
+/// Transient errors on the socket make the thread subscribe again, so some events may be lost:
+/// [`EventSource::take_reconnected`] tells when that happened.
+struct Subscription {
+    receiver: Receiver<Message>,
+    reconnected: bool,
+}
+
+enum Message {
+    Event(Event),
+    Reconnected,
+    Error(Error),
+}
+
+impl Subscription {
+    fn new(types: &[EventType]) -> Result<Self> {
+        let types = types.to_vec();
+        let stream = subscribe(&types)?;
+        Ok(Subscription::start(stream, move || subscribe(&types)))
+    }
+
+    /// Reads the events of `stream` in the background, and of the stream `resubscribe` gives
+    /// after an error the socket recovers from.
+    fn start<S>(
+        mut stream: S,
+        mut resubscribe: impl FnMut() -> Fallible<S> + Send + 'static,
+    ) -> Self
+    where
+        S: Iterator<Item = Fallible<Event>> + Send + 'static,
+    {
+        let (sender, receiver) = channel();
+        spawn(move || {
+            let mut reconnects: Vec<Instant> = Vec::new();
+            loop {
+                let error = match stream.next() {
+                    Some(Ok(event)) => {
+                        if sender.send(Message::Event(event)).is_err() {
+                            return;
+                        }
+                        continue;
+                    }
+                    // The whole event was read before failing to decode it, so the stream is
+                    // still fine.
+                    Some(Err(Error::UnimplementedEvent(kind, payload))) => {
+                        log::debug!(
+                            "ignoring unknown event type {}: {}",
+                            kind,
+                            excerpt(&payload)
+                        );
+                        continue;
+                    }
+                    // swayipc drops the payload it failed to decode, only where it failed is
+                    // known.
+                    Some(Err(Error::SerdeJson(e))) => {
+                        log::warn!("ignoring event that could not be decoded: {}", e);
+                        continue;
+                    }
+                    Some(Err(e)) if is_recoverable(&e) => e,
+                    Some(Err(e)) => {
+                        let _ = sender.send(Message::Error(e));
+                        return;
+                    }
+                    // A closed socket shows up as an I/O error rather than the end of the
+                    // stream, but dropping the sender tells the receiving side either way.
+                    None => return,
+                };
+                let now = Instant::now();
+                reconnects.retain(|t| now.duration_since(*t) < RECONNECT_WINDOW);
+                if reconnects.len() >= MAX_RECONNECTS {
+                    let _ = sender.send(Message::Error(error));
+                    return;
+                }
+                reconnects.push(now);
+                log::warn!("event stream failed: {}, subscribing again", error);
+                sleep(RECONNECT_DELAY);
+                match resubscribe() {
+                    Ok(s) => stream = s,
+                    Err(e) => {
+                        log::warn!("failed to subscribe again: {}", e);
+                        return;
+                    }
+                }
+                if sender.send(Message::Reconnected).is_err() {
+                    return;
+                }
+            }
+        });
+        Subscription {
+            receiver,
+            reconnected: false,
+        }
+    }
+}
+
+/// The beginning of `payload`, as text, for the logs.
+fn excerpt(payload: &[u8]) -> String {
+    let text = String::from_utf8_lossy(payload);
+    match text.char_indices().nth(LOGGED_PAYLOAD) {
+        Some((end, _)) => format!("{}... ({} bytes)", &text[..end], payload.len()),
+        None => text.into_owned(),
+    }
+}
+
+impl EventSource for Subscription {
+    fn recv_until(&mut self, deadline: Option<Instant>) -> Result<Option<Event>> {
+        loop {
+            let res = match deadline {
+                Some(deadline) => self
+                    .receiver
+                    .recv_timeout(deadline.saturating_duration_since(Instant::now())),
+                None => self.receiver.recv().map_err(RecvTimeoutError::from),
+            };
+            match res {
+                Ok(Message::Event(Event::Shutdown(_))) => return Err(CompositorGone.into()),
+                Ok(Message::Event(event)) => return Ok(Some(event)),
+                Ok(Message::Reconnected) => self.reconnected = true,
+                Ok(Message::Error(e)) => return Err(e.into()),
+                Err(RecvTimeoutError::Timeout) => return Ok(None),
+                Err(RecvTimeoutError::Disconnected) => return Err(CompositorGone.into()),
+            }
+        }
+    }
+
+    /// Whether the subscription was re-established since the last call.
+    fn take_reconnected(&mut self) -> bool {
+        std::mem::take(&mut self.reconnected)
+    }
+}
+
+/// Subscribes to the events of sway of the given `types`.
+pub fn subscription(types: &[EventType]) -> Result<Box<dyn EventSource>> {
+    Ok(Box::new(Subscription::new(types)?))
+}
+
+impl Events {
+    /// Subscribes to the events of sway of the given `types`.
+    pub fn subscribe(types: &[EventType]) -> Result<Self> {
+        Ok(Events::new(subscription(types)?))
+    }
+
+    pub fn new(source: Box<dyn EventSource>) -> Self {
+        Events {
+            source,
+            backlog: VecDeque::new(),
+        }
+    }
+
+    /// Starts with `backlog`, events received but not handled yet.
+    pub fn with_backlog(mut self, backlog: Vec<Event>) -> Self {
+        self.backlog.extend(backlog);
+        self
+    }
+
+    /// Whether events may have been missed since the last call.
+    pub fn take_reconnected(&mut self) -> bool {
+        self.source.take_reconnected()
+    }
+
+    /// Events received but not handled yet.
+    pub fn backlog(&self) -> impl Iterator<Item = &Event> {
+        self.backlog.iter()
+    }
+
+    /// Returns the next event, or `None` if `deadline` passes before one arrives.
+    pub fn next_until(&mut self, deadline: Option<Instant>) -> Result<Option<Event>> {
+        if let Some(event) = self.backlog.pop_front() {
+            return Ok(Some(event));
+        }
+        self.source.recv_until(deadline)
+    }
+
+    /// Waits for an event for which `pred` returns something, and returns that.
+    ///
+    /// Other events are kept for `next_until`. Fails with [`TimedOut`] if `timeout` passes first.
+    pub fn wait_for<T, F: FnMut(&Event) -> Option<T>>(
+        &mut self,
+        mut pred: F,
+        timeout: Option<Duration>,
+    ) -> Result<T> {
+        let deadline = timeout.map(|t| Instant::now() + t);
+        while let Some(event) = self.source.recv_until(deadline)? {
+            match pred(&event) {
+                Some(res) => return Ok(res),
+                None => self.backlog.push_back(event),
+            }
+        }
+        // Without a deadline `recv_until` never returns `None`.
+        Err(TimedOut {
+            timeout: timeout.unwrap_or_default(),
+        }
+        .into())
+    }
+
+    /// Waits for a window with `app_id` to be created, failing with [`TimedOut`] after `timeout`.
+    pub fn wait_new_window(&mut self, app_id: &str, timeout: Duration) -> Result<Node> {
+        log::debug!("wait for window:");
+        let node = self
+            .wait_for(
+                |event| match event {
+                    Event::Window(w)
+                        if w.change == WindowChange::New
+                            && crate::tree::app_id(&w.container) == Some(app_id) =>
+                    {
+                        Some(w.container.clone())
+                    }
+                    _ => None,
+                },
+                Some(timeout),
+            )
+            .with_context(|| format!("waiting for a new window with app_id {}", app_id))?;
+        log::debug!("new window id={} app_id={:?}", node.id, node.app_id);
+        Ok(node)
+    }
+
+    /// Waits for the window `id` to get focus, failing with [`TimedOut`] after `timeout`.
+    pub fn wait_window_focus(&mut self, id: i64, timeout: Duration) -> Result<Node> {
+        let node = self
+            .wait_for(
+                |event| match event {
+                    Event::Window(w) if w.container.id == id && w.change == WindowChange::Focus => {
+                        Some(w.container.clone())
+                    }
+                    _ => None,
+                },
+                Some(timeout),
+            )
+            .with_context(|| format!("waiting for focus on window {}", id))?;
+        log::debug!("focus window id={} app_id={:?}", node.id, node.app_id);
+        Ok(node)
+    }
+}
+
+fn subscribe(types: &[EventType]) -> Fallible<EventStream> {
+    Connection::new()?.subscribe(types)
+}
+
+/// Errors after which the socket can be expected to work again with a new subscription.
+fn is_recoverable(error: &Error) -> bool {
+    matches!(error, Error::Io(_) | Error::InvalidMagic(_))
+}
+
+/// Stand-ins for sway's events in the tests.
+#[cfg(test)]
+pub mod mock {
+    use super::{CompositorGone, EventSource, Subscription};
+    use anyhow::Result;
+    use serde_json::json;
+    use std::collections::VecDeque;
+    use std::time::Instant;
+    use swayipc::{Event, Fallible, Node, WindowChange, WindowEvent};
+
+    /// Gives scripted events, whatever the deadline.
+    ///
+    /// Once they run out, waiting with a deadline times out, and waiting without one fails
+    /// with [`CompositorGone`], as nothing would ever come.
+    #[derive(Default)]
+    pub struct MockEvents {
+        pub events: VecDeque<Event>,
+    }
+
+    impl MockEvents {
+        pub fn new(events: impl IntoIterator<Item = Event>) -> Self {
+            MockEvents {
+                events: events.into_iter().collect(),
+            }
+        }
+    }
+
+    impl EventSource for MockEvents {
+        fn recv_until(&mut self, deadline: Option<Instant>) -> Result<Option<Event>> {
+            match (self.events.pop_front(), deadline) {
+                (Some(event), _) => Ok(Some(event)),
+                (None, Some(_)) => Ok(None),
+                (None, None) => Err(CompositorGone.into()),
+            }
+        }
+        fn take_reconnected(&mut self) -> bool {
+            false
+        }
+    }
+
+    /// The events of `stream` as a subscription to sway gets them, decoding errors included,
+    /// ending with sway going away.
+    pub fn decoded(stream: Vec<Fallible<Event>>) -> Box<dyn EventSource> {
+        Box::new(Subscription::start(stream.into_iter(), || {
+            panic!("subscribed again")
+        }))
+    }
+
+    /// The window event `change` of `container`.
+    pub fn window(change: WindowChange, container: &Node) -> Event {
+        let change = serde_json::to_value(change).expect("a known change");
+        let event: WindowEvent =
+            serde_json::from_value(json!({ "change": change, "container": container }))
+                .expect("a valid event");
+        Event::Window(Box::new(event))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::mock::window;
+    use super::*;
+    use crate::ipc::mock::node;
+    use serde_json::json;
+
+    fn opened(id: i64) -> Fallible<Event> {
+        Ok(window(WindowChange::New, &node(json!({ "id": id }))))
+    }
+
+    /// The id of the window of the next event of `subscription`.
+    fn next_window(subscription: &mut Subscription) -> i64 {
+        match subscription.recv_until(None).unwrap() {
+            Some(Event::Window(w)) => w.container.id,
+            event => panic!("expected a window event, got {:?}", event),
+        }
+    }
+
+    #[test]
+    fn skips_events_that_cant_be_decoded() {
+        let poison = serde_json::from_str::<serde_json::Value>("{\"change\":").unwrap_err();
+        let stream = vec![
+            opened(1),
+            Err(Error::SerdeJson(poison)),
+            Err(Error::UnimplementedEvent(0x8000_0042, b"{}".to_vec())),
+            opened(2),
+        ];
+        let mut subscription =
+            Subscription::start(stream.into_iter(), || panic!("subscribed again"));
+        assert_eq!(next_window(&mut subscription), 1);
+        assert_eq!(next_window(&mut subscription), 2);
+        assert!(!subscription.take_reconnected());
+        // The end of the stream is sway going away.
+        let e = subscription.recv_until(None).unwrap_err();
+        assert!(e.is::<CompositorGone>());
+    }
+
+    #[test]
+    fn shortens_long_payloads() {
+        assert_eq!(excerpt(b"{}"), "{}");
+        let payload = "x".repeat(LOGGED_PAYLOAD + 10);
+        let logged = excerpt(payload.as_bytes());
+        assert!(logged.starts_with(&payload[..LOGGED_PAYLOAD]));
+        assert!(logged.ends_with(&format!("... ({} bytes)", LOGGED_PAYLOAD + 10)));
+    }
+
+    #[test]
+    fn subscribes_again_after_an_io_error() {
+        let broken = std::io::Error::new(std::io::ErrorKind::ConnectionReset, "reset");
+        let stream = vec![opened(1), Err(Error::Io(broken))];
+        let mut subscription =
+            Subscription::start(stream.into_iter(), || Ok(vec![opened(2)].into_iter()));
+        assert_eq!(next_window(&mut subscription), 1);
+        assert_eq!(next_window(&mut subscription), 2);
+        assert!(subscription.take_reconnected());
+    }
+}
@@ -0,0 +1,81 @@
+use crate::report::Summary;
+use crate::status::{self, Error};
+use anyhow::{Context, Result};
+use std::process::Command;
+
+/// Shell commands run around a restore, with `--pre-cmd` and `--post-cmd`.
+pub struct Hooks {
+    pre: Vec<String>,
+    post: Vec<String>,
+    /// Whether a failing command fails the restore, rather than being reported.
+    fatal: bool,
+    /// Whether the pre hooks ran, so that the post hooks must too.
+    started: bool,
+}
+
+impl Hooks {
+    pub fn new(pre: Vec<String>, post: Vec<String>, fatal: bool) -> Hooks {
+        Hooks {
+            pre,
+            post,
+            fatal,
+            started: false,
+        }
+    }
+    /// Runs the pre hooks. From then on the post hooks run however the restore ends.
+    pub fn pre(&mut self) -> Result<()> {
+        self.started = true;
+        run_all(&self.pre, &[], self.fatal)
+    }
+    /// Runs the post hooks of a restore that ended with `res`, if the pre hooks ran.
+    ///
+    /// The outcome is passed in `SWAYSTART_STATUS`, the exit status of swaystart, and for a
+    /// restore that got to the end in `SWAYSTART_FILLED` and `SWAYSTART_UNMATCHED`, the number
+    /// of slots that got their window and of the ones that did not.
+    pub fn post(&self, res: &Result<(Summary, Result<(), Error>), Error>) -> Result<()> {
+        if !self.started {
+            return Ok(());
+        }
+        let env = match res {
+            Ok((summary, res)) => vec![
+                (
+                    "SWAYSTART_STATUS",
+                    res.as_ref().err().map_or(0, status::code).to_string(),
+                ),
+                ("SWAYSTART_FILLED", summary.filled().to_string()),
+                (
+                    "SWAYSTART_UNMATCHED",
+                    summary.unfilled().count().to_string(),
+                ),
+            ],
+            Err(e) => vec![("SWAYSTART_STATUS", status::code(e).to_string())],
+        };
+        run_all(&self.post, &env, self.fatal)
+    }
+}
+
+fn run(cmd: &str, env: &[(&str, String)]) -> Result<()> {
+    log::debug!("hook: '{}'", cmd);
+    let status = Command::new("sh")
+        .arg("-c")
+        .arg(cmd)
+        .envs(env.iter().map(|(k, v)| (k, v)))
+        .status()
+        .with_context(|| format!("running the hook {:?}", cmd))?;
+    if !status.success() {
+        anyhow::bail!("the hook {:?} failed with {}", cmd, status);
+    }
+    Ok(())
+}
+
+/// Runs `cmds` one after the other, stopping at the first failure if `fatal` is set.
+fn run_all(cmds: &[String], env: &[(&str, String)], fatal: bool) -> Result<()> {
+    for cmd in cmds {
+        match run(cmd, env) {
+            Ok(()) => {}
+            Err(e) if fatal => return Err(e),
+            Err(e) => eprintln!("warning: {:#}", e),
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,202 @@
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use swayipc::{Connection, Node, Workspace};
+
+/// The requests the restore makes to sway, so that they can be answered by something else than
+/// sway's socket.
+pub trait CommandRunner {
+    /// Runs `cmd`, failing with [`CommandFailed`] if any of the commands in it fails.
+    fn run_command(&mut self, cmd: &str) -> Result<()>;
+    fn get_tree(&mut self) -> Result<Node>;
+    fn get_workspaces(&mut self) -> Result<Vec<Workspace>>;
+}
+
+/// Error returned when sway refuses a command.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CommandFailed {
+    /// The command that failed.
+    pub command: String,
+    /// The whole list of commands it was sent in, and its position in it, if there were several.
+    pub batch: Option<(String, usize)>,
+    /// What sway replied.
+    pub message: String,
+    /// Whether sway could not parse the command, rather than failed to run it.
+    pub parse_error: bool,
+}
+
+impl std::fmt::Display for CommandFailed {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.command)?;
+        if let Some((batch, index)) = &self.batch {
+            write!(f, " (command {} of {:?})", index + 1, batch)?;
+        }
+        if self.parse_error {
+            write!(f, ": could not be parsed: {}", self.message)
+        } else {
+            write!(f, ": {}", self.message)
+        }
+    }
+}
+
+impl std::error::Error for CommandFailed {}
+
+/// Splits `cmd` into its commands, separated by `;` or `,` outside of quotes, as sway does.
+fn split_commands(cmd: &str) -> Vec<&str> {
+    let mut commands = Vec::new();
+    let mut quote = None;
+    let mut start = 0;
+    for (i, c) in cmd.char_indices() {
+        match c {
+            '"' | '\'' if quote == Some(c) => quote = None,
+            '"' | '\'' if quote.is_none() => quote = Some(c),
+            ';' | ',' if quote.is_none() => {
+                commands.push(cmd[start..i].trim());
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    commands.push(cmd[start..].trim());
+    commands.retain(|c| !c.is_empty());
+    commands
+}
+
+impl CommandRunner for Connection {
+    fn run_command(&mut self, cmd: &str) -> Result<()> {
+        let results = Connection::run_command(self, cmd)?;
+        let commands = split_commands(cmd);
+        // sway replies once when it can't parse the list, whatever its length.
+        let single = commands.len() != results.len() || results.len() == 1;
+        for (i, res) in results.into_iter().enumerate() {
+            let command = if single { cmd } else { commands[i] };
+            let (message, parse_error) = match res {
+                Ok(()) => {
+                    log::debug!("cmd: '{}': ok", command);
+                    continue;
+                }
+                Err(swayipc::Error::CommandFailed(message)) => (message, false),
+                Err(swayipc::Error::CommandParse(message)) => (message, true),
+                Err(e) => return Err(e.into()),
+            };
+            log::debug!("cmd: '{}': {}", command, message);
+            return Err(CommandFailed {
+                command: command.to_owned(),
+                batch: (!single).then(|| (cmd.to_owned(), i)),
+                message,
+                parse_error,
+            }
+            .into());
+        }
+        Ok(())
+    }
+    fn get_tree(&mut self) -> Result<Node> {
+        Ok(Connection::get_tree(self)?)
+    }
+    fn get_workspaces(&mut self) -> Result<Vec<Workspace>> {
+        Ok(Connection::get_workspaces(self)?)
+    }
+}
+
+/// Stand-ins for sway in the tests.
+#[cfg(test)]
+pub mod mock {
+    use super::{CommandFailed, CommandRunner};
+    use anyhow::Result;
+    use serde_json::{json, Value};
+    use std::collections::{HashMap, VecDeque};
+    use std::sync::{Arc, Mutex};
+    use swayipc::{Node, Workspace};
+
+    /// Answers the requests with scripted replies instead of sway, and records the commands.
+    #[derive(Default)]
+    pub struct MockRunner {
+        /// The replies to `get_tree`, in order, failing once they run out.
+        pub trees: VecDeque<Node>,
+        /// The reply to `get_workspaces`.
+        pub workspaces: Vec<Workspace>,
+        /// The commands refused, with what sway replies, every time they are run.
+        pub failing: HashMap<String, String>,
+        /// The commands run so far, refused ones included.
+        pub commands: Arc<Mutex<Vec<String>>>,
+    }
+
+    impl CommandRunner for MockRunner {
+        fn run_command(&mut self, cmd: &str) -> Result<()> {
+            self.commands.lock().unwrap().push(cmd.to_owned());
+            match self.failing.get(cmd) {
+                Some(message) => Err(CommandFailed {
+                    command: cmd.to_owned(),
+                    batch: None,
+                    message: message.clone(),
+                    parse_error: false,
+                }
+                .into()),
+                None => Ok(()),
+            }
+        }
+        fn get_tree(&mut self) -> Result<Node> {
+            self.trees
+                .pop_front()
+                .ok_or_else(|| anyhow::anyhow!("no tree left to reply with"))
+        }
+        fn get_workspaces(&mut self) -> Result<Vec<Workspace>> {
+            Ok(self.workspaces.clone())
+        }
+    }
+
+    /// A node as sway would send it, with `fields` over the ones every node has.
+    pub fn node(fields: Value) -> Node {
+        let rect = json!({ "x": 0, "y": 0, "width": 0, "height": 0 });
+        let mut node = json!({
+            "id": 0,
+            "type": "con",
+            "border": "none",
+            "current_border_width": 0,
+            "layout": "none",
+            "rect": rect,
+            "window_rect": rect,
+            "deco_rect": rect,
+            "geometry": rect,
+            "urgent": false,
+            "focused": false,
+            "focus": [],
+            "nodes": [],
+            "floating_nodes": [],
+            "sticky": false,
+        });
+        for (key, value) in fields.as_object().expect("an object") {
+            node[key] = value.clone();
+        }
+        serde_json::from_value(node).expect("a valid node")
+    }
+
+    /// The workspace `name` as sway would list it.
+    pub fn workspace(name: &str, focused: bool) -> Workspace {
+        serde_json::from_value(json!({
+            "id": 0,
+            "num": name.parse::<i32>().unwrap_or(-1),
+            "name": name,
+            "visible": focused,
+            "focused": focused,
+            "urgent": false,
+            "rect": { "x": 0, "y": 0, "width": 0, "height": 0 },
+            "output": "OUT",
+        }))
+        .expect("a valid workspace")
+    }
+
+    /// The active output `name` as sway would list it, a screen of `make`, `model` and `serial`.
+    pub fn output(name: &str, make: &str, model: &str, serial: &str) -> swayipc::Output {
+        serde_json::from_value(json!({
+            "name": name,
+            "make": make,
+            "model": model,
+            "serial": serial,
+            "active": true,
+            "dpms": true,
+            "primary": false,
+            "rect": { "x": 0, "y": 0, "width": 0, "height": 0 },
+        }))
+        .expect("a valid output")
+    }
+}
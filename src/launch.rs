@@ -0,0 +1,236 @@
+use crate::connect;
+use anyhow::{Context, Result};
+use gio::prelude::*;
+use serde::Deserialize;
+use std::io::ErrorKind;
+use std::process::Command;
+use swayipc::Connection;
+
+/// How the apps are launched, with `--exec-via` or per slot in the layout.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum ExecVia {
+    /// With the `exec` command of sway, in the environment of sway
+    Sway,
+    /// By swaystart itself, in its environment
+    #[default]
+    Direct,
+    /// In a scope of their own with `systemd-run --user --scope`
+    Systemd,
+}
+
+/// Launches the app of a desktop file.
+pub trait Launcher {
+    /// Launches `app`, handing it `activation_token` if any, with the variables of `env` added to
+    /// its environment.
+    fn launch(
+        &mut self,
+        app: &gio::DesktopAppInfo,
+        activation_token: Option<&str>,
+        env: &[(String, String)],
+    ) -> Result<()>;
+}
+
+/// The launcher going `via` the given way.
+pub fn launcher(via: ExecVia) -> Result<Box<dyn Launcher>> {
+    let launcher: Box<dyn Launcher> = match via {
+        ExecVia::Sway => Box::new(Sway { conn: connect()? }),
+        ExecVia::Direct => Box::new(Direct),
+        ExecVia::Systemd => Box::new(Systemd),
+    };
+    Ok(launcher)
+}
+
+/// The desktop file of `app`, which the launchers that don't launch it themselves hand to
+/// `gio launch`.
+fn desktop_file(app: &gio::DesktopAppInfo) -> Result<String> {
+    let path = app
+        .filename()
+        .ok_or_else(|| anyhow::anyhow!("the app has no desktop file"))?;
+    Ok(path.to_string_lossy().into_owned())
+}
+
+/// Quotes `s` as a single word for `sh`.
+fn shell_quote(s: &str) -> String {
+    format!("'{}'", s.replace('\'', r"'\''"))
+}
+
+struct Direct;
+
+impl Launcher for Direct {
+    fn launch(
+        &mut self,
+        app: &gio::DesktopAppInfo,
+        activation_token: Option<&str>,
+        env: &[(String, String)],
+    ) -> Result<()> {
+        let ctx = gio::AppLaunchContext::new();
+        for (key, value) in env {
+            ctx.setenv(key, value);
+        }
+        if let Some(token) = activation_token {
+            ctx.setenv("XDG_ACTIVATION_TOKEN", token);
+        }
+        log::debug!("env: {:?}", ctx.environment());
+        app.launch_uris(&[], Some(&ctx))?;
+        Ok(())
+    }
+}
+
+/// The sway command launching the desktop file `file`.
+fn sway_exec(file: &str, activation_token: Option<&str>, env: &[(String, String)]) -> String {
+    // sway runs the command with `sh -c`, the variables are set by `env` in front of it.
+    let mut cmd = String::from("exec env");
+    let token = activation_token.map(|t| ("XDG_ACTIVATION_TOKEN", t));
+    let vars = env
+        .iter()
+        .map(|(k, v)| (k.as_str(), v.as_str()))
+        .chain(token);
+    for (key, value) in vars {
+        cmd.push(' ');
+        cmd.push_str(&shell_quote(&format!("{}={}", key, value)));
+    }
+    cmd.push_str(" gio launch ");
+    cmd.push_str(&shell_quote(file));
+    cmd
+}
+
+struct Sway {
+    conn: Connection,
+}
+
+impl Launcher for Sway {
+    fn launch(
+        &mut self,
+        app: &gio::DesktopAppInfo,
+        activation_token: Option<&str>,
+        env: &[(String, String)],
+    ) -> Result<()> {
+        let cmd = sway_exec(&desktop_file(app)?, activation_token, env);
+        log::debug!("cmd: '{}'", cmd);
+        for res in self
+            .conn
+            .run_command(&cmd)
+            .context("running exec in sway")?
+        {
+            res.context("sway refused exec")?;
+        }
+        Ok(())
+    }
+}
+
+/// The `systemd-run` command launching the desktop file `file`.
+fn systemd_run(file: &str, activation_token: Option<&str>, env: &[(String, String)]) -> Command {
+    // With --scope, systemd-run runs the command itself, in its own environment.
+    let mut cmd = Command::new("systemd-run");
+    cmd.args(["--user", "--scope", "--quiet", "--collect"]);
+    cmd.envs(env.iter().map(|(k, v)| (k, v)));
+    if let Some(token) = activation_token {
+        cmd.env("XDG_ACTIVATION_TOKEN", token);
+    }
+    cmd.args(["--", "gio", "launch"]).arg(file);
+    cmd
+}
+
+struct Systemd;
+
+impl Launcher for Systemd {
+    fn launch(
+        &mut self,
+        app: &gio::DesktopAppInfo,
+        activation_token: Option<&str>,
+        env: &[(String, String)],
+    ) -> Result<()> {
+        let mut cmd = systemd_run(&desktop_file(app)?, activation_token, env);
+        log::debug!("cmd: {:?}", cmd);
+        // The scope lasts as long as the app, systemd-run is left to run in the background.
+        match cmd.spawn() {
+            Ok(_) => Ok(()),
+            Err(e) if e.kind() == ErrorKind::NotFound => Err(anyhow::anyhow!(
+                "systemd-run is not installed, use another --exec-via"
+            )),
+            Err(e) => Err(e).context("running systemd-run"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::ffi::OsStr;
+
+    /// Values that mean something to the shell.
+    const VALUES: [&str; 6] = ["two words", "it's", "say \"hi\"", "$HOME", "", "a'b\"c $d"];
+
+    /// What `sh` makes of `script`.
+    fn sh(script: &str) -> String {
+        let out = Command::new("sh").arg("-c").arg(script).output().unwrap();
+        assert!(out.status.success(), "{:?}", out);
+        String::from_utf8(out.stdout).unwrap()
+    }
+
+    #[test]
+    fn quotes_values_as_single_words() {
+        for value in VALUES {
+            assert_eq!(sh(&format!("printf %s {}", shell_quote(value))), value);
+        }
+    }
+
+    /// The variables `VAR0`, `VAR1`... set to the `VALUES`.
+    fn vars() -> Vec<(String, String)> {
+        VALUES
+            .iter()
+            .enumerate()
+            .map(|(i, v)| (format!("VAR{}", i), v.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn sway_sets_the_variables_with_env() {
+        let env = vars();
+        let cmd = sway_exec("/apps/foot.desktop", Some("token"), &env);
+        let (vars, app) = cmd.split_once(" gio launch ").unwrap();
+        assert_eq!(app, "'/apps/foot.desktop'");
+        // Run as sway would, printing the variables instead of launching the app.
+        let names: Vec<&str> = env.iter().map(|(k, _)| k.as_str()).collect();
+        let script = format!(
+            "{} printenv {} XDG_ACTIVATION_TOKEN",
+            vars.strip_prefix("exec ").unwrap(),
+            names.join(" ")
+        );
+        let expected: String = VALUES
+            .iter()
+            .chain(&["token"])
+            .map(|v| format!("{}\n", v))
+            .collect();
+        assert_eq!(sh(&script), expected);
+    }
+
+    #[test]
+    fn systemd_passes_the_variables_as_they_are() {
+        let env = vars();
+        let cmd = systemd_run("/apps/foot.desktop", Some("token"), &env);
+        let envs: Vec<(&OsStr, Option<&OsStr>)> = cmd.get_envs().collect();
+        for (key, value) in &env {
+            assert!(envs.contains(&(OsStr::new(key), Some(OsStr::new(value)))));
+        }
+        assert!(envs.contains(&(
+            OsStr::new("XDG_ACTIVATION_TOKEN"),
+            Some(OsStr::new("token"))
+        )));
+        let args: Vec<&OsStr> = cmd.get_args().collect();
+        assert_eq!(
+            args,
+            [
+                "--user",
+                "--scope",
+                "--quiet",
+                "--collect",
+                "--",
+                "gio",
+                "launch",
+                "/apps/foot.desktop"
+            ]
+        );
+    }
+}
@@ -0,0 +1,4663 @@
+//! Restores sway layouts: the windows of a layout file are opened as placeholders, and swapped
+//! for the windows of the apps as they show up.
+//!
+//! The `swaystart` binary only parses the command line and calls [`restore`] and the other
+//! commands here. The layout files are read into an [`Output`], which the [`LayoutVisitor`]
+//! and [`LayoutVisitorMut`] traits walk.
+
+use anyhow::{Context, Result};
+use clap::ValueEnum;
+use eventlog::EventLog;
+use events::Events;
+use gio::prelude::*;
+use ipc::CommandRunner;
+use placeholder::{ClientHandle, PlaceholderState};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use status::{Cancelled, Incomplete, Unmatched};
+use std::{
+    collections::{hash_map::Entry, BTreeMap, HashMap, HashSet, VecDeque},
+    io::{BufRead, IsTerminal, Read, Write},
+    path::{Path, PathBuf},
+    time::{Duration, Instant},
+};
+use swayipc::{Connection, Event, EventType, Node, NodeType, WindowChange};
+use tree::Order;
+
+mod auto;
+mod backend;
+mod canvas;
+mod check;
+pub mod config;
+mod daemon;
+mod dbus;
+mod desktop;
+mod detach;
+mod diff;
+mod edit;
+mod eventlog;
+mod events;
+mod hooks;
+mod ipc;
+mod launch;
+mod lock;
+pub mod logging;
+mod moves;
+mod notify;
+mod pattern;
+mod placeholder;
+pub mod report;
+mod schema;
+mod screen;
+mod selftest;
+mod session;
+mod show;
+pub mod status;
+mod theme;
+mod tree;
+mod tui;
+mod waybar;
+mod x11;
+
+pub use auto::choose as choose_layout;
+pub use backend::Backend;
+pub use detach::run as detach;
+pub use edit::edit;
+pub use events::{CompositorGone, TimedOut};
+pub use ipc::CommandFailed;
+pub use launch::ExecVia;
+pub use moves::Move;
+pub use placeholder::ClientFailed;
+pub use report::Summary;
+pub use selftest::run as selftest;
+pub use status::Error;
+pub use theme::Theme;
+
+/// How long a placeholder may take to be configured by the compositor.
+const MAP_TIMEOUT: Duration = Duration::from_secs(5);
+/// How long to wait for the IPC events of a placeholder that is known to be mapped.
+const EVENT_TIMEOUT: Duration = Duration::from_secs(1);
+/// How long the compositor may take to hand out an activation token for a launched app.
+const ACTIVATION_TIMEOUT: Duration = Duration::from_millis(500);
+/// Seconds to wait for the placeholders to close, when neither the command line nor the config
+/// say.
+const IDLE_TIMEOUT: u64 = 30;
+/// Seconds `swaystart place` waits for the window, when the command line does not say.
+const PLACE_TIMEOUT: u64 = 30;
+/// How often a restore run by the daemon looks for a cancellation while waiting for windows.
+const CANCEL_POLL: Duration = Duration::from_millis(200);
+/// How many layouts may be nested in a layout file, so that walking them can't run out of stack.
+const MAX_LAYOUT_DEPTH: usize = 32;
+/// Seconds a slot with fallbacks waits for its own app, when the layout does not say.
+const FALLBACK_AFTER: u64 = 10;
+/// How far from 1 the slot sizes of a layout may add up to and still count as adding up to 1.
+const SIZE_TOLERANCE: f64 = 0.01;
+/// How far a window's share of its container may be from its slot size before diff reports it,
+/// when neither the command line nor the config say.
+const DIFF_THRESHOLD: f64 = 0.05;
+
+fn spawn(
+    launcher: &mut dyn launch::Launcher,
+    app: &str,
+    activation_token: Option<&str>,
+    env: &[(String, String)],
+) -> Result<()> {
+    log::debug!("spawn: '{}'", app);
+    let app = gio::DesktopAppInfo::new(app).ok_or_else(|| anyhow::anyhow!("no app: {app}"))?;
+    launcher.launch(&app, activation_token, env)
+}
+
+/// A layout file: the workspaces of an output.
+#[derive(Debug, Deserialize)]
+pub struct Output {
+    pub name: String,
+    pub workspaces: Vec<Workspace>,
+    #[serde(default)]
+    pub theme: Theme,
+    /// What the screen of the output is like, to restore the layout on it if its name changed.
+    #[serde(default)]
+    pub screen: Option<screen::Screen>,
+}
+#[derive(Debug, Deserialize)]
+pub struct Workspace {
+    pub name: String,
+    pub style: LayoutStyle,
+    pub layout: Layout,
+    /// Apps opened as floating windows, after the layout is built.
+    #[serde(default)]
+    pub floating: Vec<FloatingApp>,
+    /// The layout file the workspace comes from, when several were merged.
+    #[serde(skip)]
+    pub source: Option<PathBuf>,
+}
+#[derive(Debug, Deserialize)]
+pub struct Layout {
+    pub style: LayoutStyle,
+    pub slots: Vec<Slot>,
+}
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LayoutStyle {
+    Tabbed,
+    Splitv,
+    Splith,
+}
+impl std::fmt::Display for LayoutStyle {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let style = match self {
+            LayoutStyle::Tabbed => "tabbed",
+            LayoutStyle::Splitv => "splitv",
+            LayoutStyle::Splith => "splith",
+        };
+        write!(f, "{}", style)
+    }
+}
+
+const fn f64_nan() -> f64 {
+    f64::NAN
+}
+#[derive(Debug, Deserialize)]
+pub struct Slot {
+    /// The share of its layout the slot takes, NaN if the layout file does not say until
+    /// [`SizeNormalizer`] makes one up.
+    #[serde(default = "f64_nan")]
+    pub size: f64,
+    pub content: SlotContent,
+    /// How to launch the app of the slot, instead of `--exec-via`.
+    #[serde(default)]
+    pub exec_via: Option<ExecVia>,
+    /// Variables set in the environment of the app of the slot, over the ones of `--env`.
+    #[serde(default)]
+    pub env: BTreeMap<String, String>,
+    #[serde(flatten)]
+    pub filter: WindowFilter,
+}
+/// A nested layout, or an app with the id its windows are matched on, its desktop file name
+/// if not given.
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+pub enum SlotContent {
+    Container(Layout),
+    App(String),
+    AppWithId { app: String, id: String },
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+pub enum FloatingApp {
+    App(String),
+    AppWithId {
+        app: String,
+        id: String,
+        /// How to launch the app, instead of `--exec-via`.
+        #[serde(default)]
+        exec_via: Option<ExecVia>,
+        #[serde(flatten)]
+        filter: Box<WindowFilter>,
+    },
+}
+
+/// What a window must have, besides an app_id or class matching the id, to fill a slot.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct WindowFilter {
+    /// Text that the title of the window must contain.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub title_contains: Option<String>,
+    /// A title the title of the window must be close enough to, for titles that change a
+    /// little from one session to the next.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub title_fuzzy: Option<FuzzyTitle>,
+    /// The X11 role of the window, for xwayland and i3 apps.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub window_role: Option<String>,
+    /// The X11 type of the window, as `dialog`, instead of a main window.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub window_type: Option<String>,
+    /// Text that the command line of the process of the window must contain, its arguments
+    /// separated by spaces. For a terminal that is the terminal itself, as `foot -e htop`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cmdline_contains: Option<String>,
+    /// A mark the window must carry, among the ones it may have.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mark: Option<String>,
+    /// The protocol the window must use, for apps that can run both ways.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub shell: Option<Shell>,
+    /// What the window must not have: a window that has everything this filter asks for is
+    /// left to other slots.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub exclude: Option<Box<WindowFilter>>,
+    /// Which slot gets a new window that several can take: the one with the highest priority,
+    /// the same one as without priorities among equals.
+    #[serde(default, skip_serializing_if = "is_zero")]
+    pub priority: i32,
+    /// Ids of other apps whose windows can fill the slot once it has waited for its own app
+    /// for `fallback_after` seconds, in order of preference.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub fallback: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub fallback_after: Option<u64>,
+    /// Whether only windows that appear on the workspace of the slot can fill it, others being
+    /// left where they are.
+    #[serde(default, skip_serializing_if = "is_false")]
+    pub same_workspace: bool,
+    /// Whether X11 windows transient for another one, as the dialogs an app opens over its
+    /// main window, can fill the slot.
+    #[serde(default, skip_serializing_if = "is_false")]
+    pub allow_transient: bool,
+}
+
+fn is_false(b: &bool) -> bool {
+    !b
+}
+
+fn is_zero(priority: &i32) -> bool {
+    *priority == 0
+}
+
+/// A title to compare the titles of the windows with.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FuzzyTitle {
+    pub value: String,
+    /// How similar a title must be to `value`, from 0 for any title to 1 for the same one.
+    pub threshold: f64,
+}
+
+/// How a window talks to the compositor.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Shell {
+    /// As a native Wayland window.
+    XdgShell,
+    /// As an X11 window, under Xwayland or i3.
+    Xwayland,
+}
+
+/// The X11 window types of main windows, that slots take unless they ask for another one.
+const MAIN_WINDOW_TYPES: &[&str] = &["normal", "unknown"];
+
+impl WindowFilter {
+    fn is_empty(&self) -> bool {
+        self.title_contains.is_none()
+            && self.title_fuzzy.is_none()
+            && self.window_role.is_none()
+            && self.window_type.is_none()
+            && self.cmdline_contains.is_none()
+            && self.mark.is_none()
+            && self.shell.is_none()
+            && self.exclude.is_none()
+            && self.priority == 0
+            && self.fallback.is_empty()
+            && !self.same_workspace
+            && !self.allow_transient
+    }
+    /// Fails if the filter can never make sense, as with a pattern that does not compile.
+    fn check(&self) -> Result<()> {
+        self.fallback.iter().try_for_each(|id| pattern::check(id))?;
+        let texts = [
+            ("title_contains", self.title_contains.as_deref()),
+            (
+                "title_fuzzy",
+                self.title_fuzzy.as_ref().map(|f| f.value.as_str()),
+            ),
+            ("window_role", self.window_role.as_deref()),
+            ("window_type", self.window_type.as_deref()),
+            ("cmdline_contains", self.cmdline_contains.as_deref()),
+            ("mark", self.mark.as_deref()),
+        ];
+        for (key, text) in texts {
+            if text.is_some_and(|t| t.trim().is_empty()) {
+                anyhow::bail!("{} is blank, which is most likely a mistake", key);
+            }
+        }
+        if let Some(fuzzy) = &self.title_fuzzy {
+            if !(0. ..=1.).contains(&fuzzy.threshold) {
+                anyhow::bail!(
+                    "the threshold of title_fuzzy {:?} is {}, it must be between 0 and 1",
+                    fuzzy.value,
+                    fuzzy.threshold
+                );
+            }
+        }
+        match &self.exclude {
+            Some(e) if e.is_empty() => {
+                anyhow::bail!("exclude is empty, so it leaves out every window")
+            }
+            Some(e) => e.check(),
+            None => Ok(()),
+        }
+    }
+    /// How much the filter narrows down the windows that fit, the title counting the most.
+    ///
+    /// Among the slots that can take a window, the most specific one gets it.
+    fn specificity(&self) -> usize {
+        let title = [self.title_contains.is_some(), self.title_fuzzy.is_some()];
+        let others = [
+            self.window_role.is_some(),
+            self.window_type.is_some(),
+            self.cmdline_contains.is_some(),
+            self.mark.is_some(),
+            self.shell.is_some(),
+            self.exclude.is_some(),
+        ];
+        2 * title.iter().filter(|t| **t).count() + others.iter().filter(|o| **o).count()
+    }
+    /// How similar the title of `target` is to the fuzzy title of the filter, 0 without one.
+    ///
+    /// Among windows that fit, the ones with the highest score are preferred.
+    fn title_score(&self, target: &impl MatchTarget) -> f64 {
+        let Some(fuzzy) = &self.title_fuzzy else {
+            return 0.;
+        };
+        target.title().map_or(0., |title| {
+            strsim::normalized_levenshtein(&fuzzy.value, title)
+        })
+    }
+    /// When the slot starts to take its fallbacks, if it has any, the swap having started at
+    /// `started`.
+    fn fallback_due(&self, started: Instant) -> Option<Instant> {
+        let after = self.fallback_after.unwrap_or(FALLBACK_AFTER);
+        (!self.fallback.is_empty()).then(|| started + Duration::from_secs(after))
+    }
+    /// Whether the window `target` passes the filter, which it cannot without what the filter
+    /// asks for, as when i3 does not tell its pid or the process is gone.
+    ///
+    /// Splash screens and dialogs never pass a filter without a type, nor transient windows one
+    /// that does not allow them, so that they don't take the slot of the main window.
+    fn fits(&self, target: &impl MatchTarget) -> bool {
+        let main_window = || {
+            target
+                .window_type()
+                .is_none_or(|t| MAIN_WINDOW_TYPES.contains(&t))
+        };
+        if self.window_type.is_none() && !main_window() {
+            return false;
+        }
+        if !self.allow_transient && target.is_transient() {
+            return false;
+        }
+        self.holds(target)
+    }
+    /// Whether `target` has everything the filter asks for and is not excluded, whatever its
+    /// window type if the filter does not ask for one.
+    ///
+    /// The command line and the exclusion are only looked at once the rest holds.
+    fn holds(&self, target: &impl MatchTarget) -> bool {
+        let title = self
+            .title_contains
+            .as_deref()
+            .is_none_or(|t| target.title().is_some_and(|title| title.contains(t)));
+        let fuzzy = self.title_fuzzy.as_ref().is_none_or(|fuzzy| {
+            target.title().is_some() && self.title_score(target) >= fuzzy.threshold
+        });
+        let role = self
+            .window_role
+            .as_deref()
+            .is_none_or(|r| target.role() == Some(r));
+        let window_type = self
+            .window_type
+            .as_deref()
+            .is_none_or(|t| target.window_type() == Some(t));
+        let mark = self
+            .mark
+            .as_ref()
+            .is_none_or(|mark| target.marks().contains(mark));
+        let shell = self.shell.is_none_or(|shell| target.shell() == shell);
+        if !(title && fuzzy && role && window_type && mark && shell) {
+            return false;
+        }
+        let cmdline = self.cmdline_contains.as_deref().is_none_or(|c| {
+            target
+                .pid()
+                .and_then(cmdline)
+                .is_some_and(|cmdline| cmdline.contains(c))
+        });
+        cmdline && self.exclude.as_ref().is_none_or(|e| !e.holds(target))
+    }
+}
+
+/// The windows on the workspace of the window `id`, none if it is on no workspace or the tree
+/// can't be had.
+fn workspace_views(conn: &mut dyn CommandRunner, id: i64) -> HashSet<i64> {
+    let tree = match conn.get_tree() {
+        Ok(tree) => tree,
+        Err(e) => {
+            log::warn!("could not find the workspace of window {}: {:#}", id, e);
+            return HashSet::new();
+        }
+    };
+    tree::find_workspace(&tree, id).map_or_else(HashSet::new, |workspace| {
+        tree::iter_views(workspace, Order::Document, false)
+            .map(|n| n.id)
+            .collect()
+    })
+}
+
+/// The command line of the process `pid`, its arguments separated by spaces.
+fn cmdline(pid: i32) -> Option<String> {
+    let path = format!("/proc/{}/cmdline", pid);
+    let bytes = std::fs::read(&path)
+        .map_err(|e| log::debug!("reading {}: {}", path, e))
+        .ok()?;
+    let args: Vec<_> = bytes
+        .split(|b| *b == 0)
+        .filter(|arg| !arg.is_empty())
+        .map(String::from_utf8_lossy)
+        .collect();
+    Some(args.join(" "))
+}
+
+/// Where a visited node sits in the layout.
+#[derive(Debug, Clone, Copy)]
+pub struct VisitContext<'a> {
+    pub output: &'a str,
+    pub workspace: Option<&'a str>,
+    /// Style of the layout containing the node, `None` at the top of a workspace.
+    pub parent: Option<&'a LayoutStyle>,
+    /// How many layouts contain the node.
+    pub depth: usize,
+}
+
+impl<'a> VisitContext<'a> {
+    fn in_workspace(self, workspace: &'a Workspace) -> Self {
+        VisitContext {
+            workspace: Some(&workspace.name),
+            ..self
+        }
+    }
+    fn in_layout(self, layout: &'a Layout) -> Self {
+        VisitContext {
+            parent: Some(&layout.style),
+            depth: self.depth + 1,
+            ..self
+        }
+    }
+}
+
+/// Describes where an error happened, as the traversal adds the nodes it was in to the error's
+/// context, e.g. `output "DP-1": workspace "3": splith layout: slot 1: app "nvim": ...`.
+fn layout_context(layout: &Layout) -> String {
+    format!("{} layout", layout.style)
+}
+
+/// What the traversal does after a callback on a node that has children.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum VisitAction {
+    Continue,
+    /// Don't visit the children of this node, and go on with its siblings.
+    SkipChildren,
+    /// End the traversal.
+    Stop,
+}
+
+impl VisitAction {
+    /// What a node reports to its parent when its callback returned `self` and its children
+    /// are not visited, `None` if they are.
+    fn without_children(self) -> Option<VisitAction> {
+        match self {
+            VisitAction::Continue => None,
+            VisitAction::SkipChildren => Some(VisitAction::Continue),
+            VisitAction::Stop => Some(VisitAction::Stop),
+        }
+    }
+}
+
+/// Walks a layout, calling back on each node.
+///
+/// The `visit_*` methods do the walking, and the `on_*` callbacks, doing nothing by default, are
+/// what visitors implement.
+pub trait LayoutVisitor {
+    fn visit_output(&mut self, output: &Output) -> Result<()> {
+        let ctx = VisitContext {
+            output: &output.name,
+            workspace: None,
+            parent: None,
+            depth: 0,
+        };
+        let in_output = || format!("output {:?}", output.name);
+        if self.on_output(output, &ctx).with_context(in_output)? != VisitAction::Continue {
+            return Ok(());
+        }
+        for w in &output.workspaces {
+            let action = self
+                .visit_workspace(w, &ctx.in_workspace(w))
+                .with_context(|| format!("workspace {:?}", w.name))
+                .with_context(in_output)?;
+            if action == VisitAction::Stop {
+                return Ok(());
+            }
+        }
+        self.on_output_exit(output, &ctx).with_context(in_output)?;
+        Ok(())
+    }
+    /// Visits a workspace, returning `Stop` if the traversal must end.
+    fn visit_workspace(
+        &mut self,
+        workspace: &Workspace,
+        ctx: &VisitContext,
+    ) -> Result<VisitAction> {
+        if let Some(action) = self.on_workspace(workspace, ctx)?.without_children() {
+            return Ok(action);
+        }
+        let action = self
+            .visit_layout(&workspace.layout, ctx)
+            .with_context(|| layout_context(&workspace.layout))?;
+        if action == VisitAction::Stop {
+            return Ok(VisitAction::Stop);
+        }
+        for f in &workspace.floating {
+            let (app, id) = match f {
+                FloatingApp::App(a) => (a, a),
+                FloatingApp::AppWithId { app, id, .. } => (app, id),
+            };
+            self.on_floating_app(app, id, ctx)
+                .with_context(|| format!("floating app {:?}", id))?;
+        }
+        self.on_workspace_exit(workspace, ctx)?;
+        Ok(VisitAction::Continue)
+    }
+    fn visit_layout(&mut self, layout: &Layout, ctx: &VisitContext) -> Result<VisitAction> {
+        let inner = ctx.in_layout(layout);
+        if let [first, rest @ ..] = layout.slots.as_slice() {
+            let action = self.visit_slot(first, &inner).context("slot 0")?;
+            if action == VisitAction::Stop {
+                return Ok(VisitAction::Stop);
+            }
+            self.on_layout_enter(layout, ctx)?;
+            for (i, s) in rest.iter().enumerate() {
+                let action = self
+                    .visit_slot(s, &inner)
+                    .with_context(|| format!("slot {}", i + 1))?;
+                if action == VisitAction::Stop {
+                    return Ok(VisitAction::Stop);
+                }
+            }
+            self.on_layout_exit(layout, ctx)?;
+        }
+        Ok(VisitAction::Continue)
+    }
+    fn visit_slot(&mut self, slot: &Slot, ctx: &VisitContext) -> Result<VisitAction> {
+        if let Some(action) = self.on_slot(slot, ctx)?.without_children() {
+            return Ok(action);
+        }
+        let (app, id) = match slot.content {
+            SlotContent::Container(ref c) => {
+                return self.visit_layout(c, ctx).with_context(|| layout_context(c));
+            }
+            SlotContent::App(ref a) => (a, a),
+            SlotContent::AppWithId { ref app, ref id } => (app, id),
+        };
+        self.visit_app(app, id, ctx)
+            .with_context(|| format!("app {:?}", id))?;
+        Ok(VisitAction::Continue)
+    }
+    fn visit_app(&mut self, app: &str, id: &str, ctx: &VisitContext) -> Result<()> {
+        self.on_app(app, id, ctx)?;
+        Ok(())
+    }
+    fn on_slot(&mut self, _slot: &Slot, _ctx: &VisitContext) -> Result<VisitAction> {
+        Ok(VisitAction::Continue)
+    }
+    fn on_app(&mut self, _app: &str, _id: &str, _ctx: &VisitContext) -> Result<()> {
+        Ok(())
+    }
+    fn on_floating_app(&mut self, _app: &str, _id: &str, _ctx: &VisitContext) -> Result<()> {
+        Ok(())
+    }
+    fn on_layout_enter(&mut self, _layout: &Layout, _ctx: &VisitContext) -> Result<()> {
+        Ok(())
+    }
+    fn on_layout_exit(&mut self, _layout: &Layout, _ctx: &VisitContext) -> Result<()> {
+        Ok(())
+    }
+    fn on_workspace(&mut self, _workspace: &Workspace, _ctx: &VisitContext) -> Result<VisitAction> {
+        Ok(VisitAction::Continue)
+    }
+    /// Called once the layout and the floating apps of a workspace have been visited, unless
+    /// the traversal stopped in the middle.
+    fn on_workspace_exit(&mut self, _workspace: &Workspace, _ctx: &VisitContext) -> Result<()> {
+        Ok(())
+    }
+    fn on_output(&mut self, _output: &Output, _ctx: &VisitContext) -> Result<VisitAction> {
+        Ok(VisitAction::Continue)
+    }
+    /// Called once all the workspaces of the output have been visited, unless the traversal
+    /// stopped in the middle.
+    fn on_output_exit(&mut self, _output: &Output, _ctx: &VisitContext) -> Result<()> {
+        Ok(())
+    }
+}
+
+/// Like [`LayoutVisitor`], with callbacks that can change the layout in place.
+///
+/// Nodes are visited in the same order. The children of a node are visited after its callback
+/// returns, so they include whatever it added or removed, except for layouts: as with
+/// `on_layout_enter` being called after the first slot, `on_layout_enter` can only change the
+/// slots after the first one, and changes made by `on_layout_exit` are not visited.
+pub trait LayoutVisitorMut {
+    fn visit_output(&mut self, output: &mut Output) -> Result<()> {
+        let action = self
+            .on_output(output)
+            .with_context(|| format!("output {:?}", output.name))?;
+        if action != VisitAction::Continue {
+            return Ok(());
+        }
+        for w in &mut output.workspaces {
+            let name = &output.name;
+            let action = self
+                .visit_workspace(w)
+                .with_context(|| format!("workspace {:?}", w.name))
+                .with_context(|| format!("output {:?}", name))?;
+            if action == VisitAction::Stop {
+                return Ok(());
+            }
+        }
+        self.on_output_exit(output)
+            .with_context(|| format!("output {:?}", output.name))?;
+        Ok(())
+    }
+    fn visit_workspace(&mut self, workspace: &mut Workspace) -> Result<VisitAction> {
+        if let Some(action) = self.on_workspace(workspace)?.without_children() {
+            return Ok(action);
+        }
+        let action = self
+            .visit_layout(&mut workspace.layout)
+            .with_context(|| layout_context(&workspace.layout))?;
+        if action == VisitAction::Stop {
+            return Ok(VisitAction::Stop);
+        }
+        for (i, f) in workspace.floating.iter_mut().enumerate() {
+            self.on_floating_app(f)
+                .with_context(|| format!("floating app {}", i))?;
+        }
+        self.on_workspace_exit(workspace)?;
+        Ok(VisitAction::Continue)
+    }
+    fn visit_layout(&mut self, layout: &mut Layout) -> Result<VisitAction> {
+        if let Some(first) = layout.slots.first_mut() {
+            if self.visit_slot(first).context("slot 0")? == VisitAction::Stop {
+                return Ok(VisitAction::Stop);
+            }
+            self.on_layout_enter(layout)?;
+            for (i, s) in layout.slots.iter_mut().enumerate().skip(1) {
+                let action = self.visit_slot(s).with_context(|| format!("slot {}", i))?;
+                if action == VisitAction::Stop {
+                    return Ok(VisitAction::Stop);
+                }
+            }
+            self.on_layout_exit(layout)?;
+        }
+        Ok(VisitAction::Continue)
+    }
+    fn visit_slot(&mut self, slot: &mut Slot) -> Result<VisitAction> {
+        if let Some(action) = self.on_slot(slot)?.without_children() {
+            return Ok(action);
+        }
+        if let SlotContent::Container(ref mut c) = slot.content {
+            return self.visit_layout(c).with_context(|| layout_context(c));
+        }
+        Ok(VisitAction::Continue)
+    }
+    /// Called on every slot, apps included.
+    fn on_slot(&mut self, _slot: &mut Slot) -> Result<VisitAction> {
+        Ok(VisitAction::Continue)
+    }
+    fn on_floating_app(&mut self, _app: &mut FloatingApp) -> Result<()> {
+        Ok(())
+    }
+    fn on_layout_enter(&mut self, _layout: &mut Layout) -> Result<()> {
+        Ok(())
+    }
+    fn on_layout_exit(&mut self, _layout: &mut Layout) -> Result<()> {
+        Ok(())
+    }
+    fn on_workspace(&mut self, _workspace: &mut Workspace) -> Result<VisitAction> {
+        Ok(VisitAction::Continue)
+    }
+    fn on_workspace_exit(&mut self, _workspace: &mut Workspace) -> Result<()> {
+        Ok(())
+    }
+    fn on_output(&mut self, _output: &mut Output) -> Result<VisitAction> {
+        Ok(VisitAction::Continue)
+    }
+    fn on_output_exit(&mut self, _output: &mut Output) -> Result<()> {
+        Ok(())
+    }
+}
+
+/// Scales the sizes of the slots of every layout so that they add up to 1.
+///
+/// The slots without a size share equally what the others leave of their layout, or count as 1
+/// if the others take it all.
+#[derive(Default)]
+pub struct SizeNormalizer {
+    /// Whether slots without a size, and sizes that don't add up to 1, are errors instead.
+    pub strict: bool,
+}
+impl LayoutVisitorMut for SizeNormalizer {
+    fn on_layout_exit(&mut self, layout: &mut Layout) -> Result<()> {
+        if let Some(s) = layout
+            .slots
+            .iter()
+            .find(|s| s.size <= 0. || s.size.is_infinite())
+        {
+            anyhow::bail!("slot size must be a positive number, got {}", s.size);
+        }
+        let missing = layout.slots.iter().filter(|s| s.size.is_nan()).count();
+        let given: f64 = layout
+            .slots
+            .iter()
+            .filter(|s| !s.size.is_nan())
+            .map(|s| s.size)
+            .sum();
+        if self.strict {
+            if missing > 0 {
+                anyhow::bail!(
+                    "{} slots of a {} layout have no size",
+                    missing,
+                    layout.style
+                );
+            }
+            if (given - 1.).abs() > SIZE_TOLERANCE {
+                anyhow::bail!(
+                    "the slot sizes of a {} layout add up to {}, not 1",
+                    layout.style,
+                    given
+                );
+            }
+        }
+        if missing > 0 {
+            let share = if given < 1. - SIZE_TOLERANCE {
+                (1. - given) / missing as f64
+            } else {
+                1.
+            };
+            log::info!(
+                "{} slots of a {} layout have no size, giving them {:.3} each",
+                missing,
+                layout.style,
+                share
+            );
+            for s in layout.slots.iter_mut().filter(|s| s.size.is_nan()) {
+                s.size = share;
+            }
+        }
+        let total: f64 = layout.slots.iter().map(|s| s.size).sum();
+        if (total - 1.).abs() > SIZE_TOLERANCE {
+            log::debug!(
+                "the slot sizes of a {} layout add up to {:.3}, scaling them to 1",
+                layout.style,
+                total
+            );
+        }
+        for s in &mut layout.slots {
+            s.size /= total;
+        }
+        Ok(())
+    }
+}
+
+/// Checks what the windows are matched on: that the ids written as patterns compile, and that
+/// the window filters make sense.
+struct MatchCheck;
+impl LayoutVisitor for MatchCheck {
+    fn on_workspace(&mut self, workspace: &Workspace, _ctx: &VisitContext) -> Result<VisitAction> {
+        for f in &workspace.floating {
+            if let FloatingApp::AppWithId { filter, .. } = f {
+                filter.check()?;
+            }
+        }
+        Ok(VisitAction::Continue)
+    }
+    fn on_slot(&mut self, slot: &Slot, _ctx: &VisitContext) -> Result<VisitAction> {
+        slot.filter.check()?;
+        Ok(VisitAction::Continue)
+    }
+    fn on_app(&mut self, _app: &str, id: &str, _ctx: &VisitContext) -> Result<()> {
+        pattern::check(id)
+    }
+    fn on_floating_app(&mut self, _app: &str, id: &str, _ctx: &VisitContext) -> Result<()> {
+        pattern::check(id)
+    }
+}
+
+/// Finds how deep the layouts of a layout file are nested.
+#[derive(Default)]
+struct LayoutDepth {
+    max: usize,
+}
+impl LayoutVisitor for LayoutDepth {
+    fn on_layout_enter(&mut self, _layout: &Layout, ctx: &VisitContext) -> Result<()> {
+        self.max = self.max.max(ctx.depth + 1);
+        Ok(())
+    }
+}
+
+/// Drops the apps left out by the restore filters from the layout, along with the layouts and
+/// workspaces they leave empty.
+struct SlotFilter<'a> {
+    /// Ids of the apps to keep, all of them if empty.
+    include: &'a [String],
+    exclude: &'a [String],
+    /// Whether the tiled slots are kept.
+    tiled: bool,
+    /// Whether the floating apps are kept.
+    floating: bool,
+}
+impl SlotFilter<'_> {
+    fn keeps(&self, id: &str) -> bool {
+        let listed = |ids: &[String]| ids.iter().any(|i| i == id);
+        (self.include.is_empty() || listed(self.include)) && !listed(self.exclude)
+    }
+}
+impl LayoutVisitorMut for SlotFilter<'_> {
+    // Layouts are left after their slots, so nested layouts are already filtered.
+    fn on_layout_exit(&mut self, layout: &mut Layout) -> Result<()> {
+        if !self.tiled {
+            layout.slots.clear();
+        }
+        layout.slots.retain(|s| match s.content {
+            SlotContent::Container(ref c) => !c.slots.is_empty(),
+            SlotContent::App(ref id) | SlotContent::AppWithId { ref id, .. } => self.keeps(id),
+        });
+        Ok(())
+    }
+    fn on_workspace_exit(&mut self, workspace: &mut Workspace) -> Result<()> {
+        if !self.floating {
+            workspace.floating.clear();
+        }
+        workspace.floating.retain(|f| match f {
+            FloatingApp::App(id) | FloatingApp::AppWithId { id, .. } => self.keeps(id),
+        });
+        Ok(())
+    }
+    fn on_output_exit(&mut self, output: &mut Output) -> Result<()> {
+        output
+            .workspaces
+            .retain(|w| !w.layout.slots.is_empty() || !w.floating.is_empty());
+        Ok(())
+    }
+}
+
+/// The app id of a `--include` or `--exclude` filter, written `ID` or `app_id=ID`.
+fn parse_app_filter(filter: &str) -> Result<String, String> {
+    let id = filter.strip_prefix("app_id=").unwrap_or(filter);
+    if id.is_empty() {
+        return Err("the app id is empty".to_owned());
+    }
+    Ok(id.to_owned())
+}
+
+/// A `--env` variable, written KEY=VALUE.
+fn parse_env(var: &str) -> Result<(String, String), String> {
+    let Some((key, value)) = var.split_once('=') else {
+        return Err("expected KEY=VALUE".to_owned());
+    };
+    if key.is_empty() {
+        return Err("the variable name is empty".to_owned());
+    }
+    Ok((key.to_owned(), value.to_owned()))
+}
+
+/// What to do when a step of the restore fails.
+#[derive(Debug, Clone, Copy)]
+struct ErrorPolicy {
+    /// Give up on the whole restore instead of skipping the step.
+    fail_fast: bool,
+    /// Count running out of time waiting for the windows as a failure.
+    strict_timeouts: bool,
+}
+
+/// Applies an [`ErrorPolicy`], keeping the failures that were let through.
+struct Failures {
+    policy: ErrorPolicy,
+    errors: Vec<String>,
+}
+
+impl Failures {
+    fn new(policy: ErrorPolicy) -> Self {
+        Failures {
+            policy,
+            errors: Vec::new(),
+        }
+    }
+    /// Returns the error of `res` if the restore must stop, `None` if it failed but can go on.
+    ///
+    /// The restore always stops when sway is gone.
+    fn tolerate<T>(&mut self, res: Result<T>) -> Result<Option<T>> {
+        match res {
+            Ok(v) => Ok(Some(v)),
+            Err(e) if self.policy.fail_fast || e.is::<CompositorGone>() => Err(e),
+            Err(e) => {
+                log::error!("{:#}, going on with the restore", e);
+                self.errors.push(format!("{:#}", e));
+                Ok(None)
+            }
+        }
+    }
+}
+
+/// The name of the desktop app `app`, from its desktop file.
+fn desktop_name(app: &str) -> Result<String> {
+    let app_info = gio::DesktopAppInfo::new(&format!("{app}.desktop"))
+        .ok_or_else(|| anyhow::anyhow!("no app: {}", app))?;
+    Ok(app_info.display_name().to_string())
+}
+
+struct LayoutBuilder {
+    conn: Box<dyn CommandRunner>,
+    events: Events,
+    placeholder: placeholder::ClientHandle,
+    /// Finds the name of a desktop app, which titles its placeholder.
+    app_name: fn(&str) -> Result<String>,
+    /// Names of the workspaces to build, all of them if empty.
+    workspaces: Vec<String>,
+    /// Whether the layout is built on the focused workspace, without switching to its own.
+    here: bool,
+    /// The workspace that was focused before building the output.
+    previous_workspace: Option<String>,
+    /// The first placeholder of the workspace being built.
+    first_placeholder: Option<i64>,
+    mapping: HashMap<String, Vec<i64>>,
+    tokens: HashMap<i64, u64>,
+    /// Windows that were on the workspace being built, floated out of the way.
+    detached: Vec<DetachedView>,
+    /// Placeholders set aside for a detached window, which takes their place instead of a new
+    /// one.
+    claimed: HashMap<i64, DetachedView>,
+    patterns: pattern::Patterns,
+    /// The filter of the slot being visited.
+    slot_filter: WindowFilter,
+    /// The filters of the floating apps of the workspace being visited.
+    floating_filters: VecDeque<WindowFilter>,
+    /// The filters of the placeholders that have one.
+    filters: HashMap<i64, WindowFilter>,
+    failures: Failures,
+    slots: Vec<report::Slot>,
+    /// The span of the workspace being built.
+    workspace_span: Option<tracing::span::EnteredSpan>,
+    /// When the workspace being built was started.
+    workspace_start: Option<Instant>,
+    /// When each placeholder was made.
+    created: HashMap<i64, Instant>,
+    /// The durations of the detaching and of each workspace.
+    durations: report::Durations,
+}
+
+impl LayoutBuilder {
+    /// Creates a builder sending its commands to `conn`, reading the window and shutdown events
+    /// from `events`, and making the placeholders with `placeholder`.
+    fn new(
+        conn: Box<dyn CommandRunner>,
+        events: Events,
+        placeholder: ClientHandle,
+        workspaces: Vec<String>,
+        policy: ErrorPolicy,
+    ) -> LayoutBuilder {
+        LayoutBuilder {
+            conn,
+            events,
+            placeholder,
+            app_name: desktop_name,
+            workspaces,
+            here: false,
+            previous_workspace: None,
+            first_placeholder: None,
+            mapping: HashMap::new(),
+            tokens: HashMap::new(),
+            detached: Vec::new(),
+            claimed: HashMap::new(),
+            patterns: pattern::Patterns::default(),
+            slot_filter: WindowFilter::default(),
+            floating_filters: VecDeque::new(),
+            filters: HashMap::new(),
+            failures: Failures::new(policy),
+            slots: Vec::new(),
+            workspace_span: None,
+            workspace_start: None,
+            created: HashMap::new(),
+            durations: report::Durations::default(),
+        }
+    }
+    fn run(&mut self, cmd: &str) -> Result<()> {
+        self.conn.run_command(cmd)
+    }
+    /// Floats every window already on the workspace `name`, including the ones nested in
+    /// containers, so that the layout is not built inside them.
+    ///
+    /// Without a `tiled` layout to build, the tiled windows are left alone, and only the
+    /// floating ones can take the place of a placeholder.
+    fn detach(&mut self, name: &str, tiled: bool) -> Result<()> {
+        let _span = tracing::info_span!("detach").entered();
+        let start = Instant::now();
+        let res = self.float_views(name, tiled);
+        self.durations.detach += start.elapsed();
+        res
+    }
+    fn float_views(&mut self, name: &str, tiled: bool) -> Result<()> {
+        self.detached.clear();
+        let tree = self.conn.get_tree()?;
+        let workspace = tree::iter_nodes(&tree, Order::Document)
+            .map(|(n, _, _)| n)
+            .find(|n| n.node_type == NodeType::Workspace && n.name.as_deref() == Some(name));
+        let Some(workspace) = workspace else {
+            return Ok(());
+        };
+        let views: Vec<&Node> = tree::iter_views(workspace, Order::Document, false)
+            .filter(|n| !is_placeholder(n) && (tiled || n.is_floating()))
+            .collect();
+        for node in views {
+            if node.node_type == NodeType::Con {
+                self.run(&format!("[con_id={}] floating enable", node.id))?;
+            }
+            self.detached.push(DetachedView {
+                id: node.id,
+                matcher: matcher(node).map(str::to_owned),
+                title: node.name.clone(),
+                role: node.role().map(str::to_owned),
+                window_type: node.window_type().map(str::to_owned),
+                pid: node.pid,
+                marks: node.marks.clone(),
+                shell: node.shell(),
+                transient: node.is_transient(),
+            });
+        }
+        Ok(())
+    }
+    /// Sets the placeholder `con_id` aside for a detached window that can fill the slot `id`, if
+    /// there is one.
+    fn claim(&mut self, id: &str, con_id: i64) {
+        let patterns = &mut self.patterns;
+        let filter = &self.slot_filter;
+        let mut best: Option<(f64, usize)> = None;
+        for (idx, n) in self.detached.iter().enumerate() {
+            if !(n.matcher().is_some_and(|m| patterns.matches(id, m)) && filter.fits(n)) {
+                continue;
+            }
+            let score = filter.title_score(n);
+            if best.is_none_or(|(s, _)| score > s) {
+                best = Some((score, idx));
+            }
+        }
+        let Some((_, idx)) = best else {
+            return;
+        };
+        let node = self.detached.remove(idx);
+        log::debug!("window {} will take placeholder {}", node.id, con_id);
+        self.claimed.insert(con_id, node);
+    }
+    /// Records the slot of the app `id` for the summary, with the placeholder made for it.
+    fn track(&mut self, id: &str, ctx: &VisitContext, placeholder: Result<i64>) -> Result<()> {
+        let (placeholder, outcome) = match self.failures.tolerate(placeholder)? {
+            // Until the swapper tells otherwise, the placeholder was closed unfilled.
+            Some(con_id) => {
+                self.created.insert(con_id, Instant::now());
+                (Some(con_id), report::Outcome::Closed)
+            }
+            None => {
+                let reason = self.failures.errors.last().cloned().unwrap_or_default();
+                (None, report::Outcome::Failed { reason })
+            }
+        };
+        self.slots.push(report::Slot {
+            workspace: ctx.workspace.unwrap_or_default().to_owned(),
+            id: id.to_owned(),
+            layout_file: None,
+            placeholder,
+            launched: false,
+            outcome,
+            wait: None,
+        });
+        Ok(())
+    }
+    /// Opens a placeholder for the desktop app `app` next to the focused window, returning its
+    /// con id.
+    fn add_placeholder(&mut self, app: &str, id: &str, ctx: &VisitContext) -> Result<i64> {
+        let name = (self.app_name)(app)?;
+        self.open_placeholder(&name, id, ctx)
+    }
+    /// Opens a placeholder titled `title` for the windows of `id` next to the focused window,
+    /// returning its con id.
+    fn open_placeholder(&mut self, title: &str, id: &str, ctx: &VisitContext) -> Result<i64> {
+        let _span = tracing::info_span!("slot", id = %id).entered();
+        log::debug!(
+            "placeholder for {} on {}/{}, {} layouts deep in a {:?} layout",
+            id,
+            ctx.output,
+            ctx.workspace.unwrap_or_default(),
+            ctx.depth,
+            ctx.parent
+        );
+        let placeholder_app_id = format!("swaystart-{}", id);
+        let token =
+            self.placeholder
+                .new_window(title, &placeholder_app_id, &format!("app_id={}", id));
+        let surface_id = self.placeholder.wait_mapped(token, MAP_TIMEOUT)?;
+        log::debug!("placeholder {} mapped as surface {}", token, surface_id);
+        let node_id = match self
+            .events
+            .wait_new_window(&placeholder_app_id, EVENT_TIMEOUT)
+        {
+            Ok(node) => node.id,
+            Err(e) if e.is::<TimedOut>() => {
+                log::warn!("{:#}, looking placeholder {} up in the tree", e, token);
+                self.conn
+                    .get_tree()?
+                    .find(|n| tree::app_id(n) == Some(placeholder_app_id.as_str()))
+                    .ok_or_else(|| anyhow::anyhow!("mapped placeholder {} not in tree", token))?
+                    .id
+            }
+            Err(e) => return Err(e),
+        };
+        self.mapping.entry(id.to_owned()).or_default().push(node_id);
+        self.tokens.insert(node_id, token);
+        if !self.slot_filter.is_empty() {
+            self.filters.insert(node_id, self.slot_filter.clone());
+        }
+        self.first_placeholder.get_or_insert(node_id);
+        self.claim(id, node_id);
+        match self.events.wait_window_focus(node_id, EVENT_TIMEOUT) {
+            Ok(_) => {}
+            Err(e) if e.is::<TimedOut>() => {
+                log::debug!("{:#}, focusing it explicitly", e);
+                self.run(&format!("[con_id={}] focus", node_id))?;
+            }
+            Err(e) => return Err(e),
+        }
+        Ok(node_id)
+    }
+    /// Sizes the slots of `layout`, which was just built, and focuses it.
+    fn resize(&mut self, layout: &Layout) -> Result<()> {
+        let dim = match layout.style {
+            LayoutStyle::Splitv => "height",
+            LayoutStyle::Splith => "width",
+            LayoutStyle::Tabbed => {
+                self.run("focus parent")?;
+                return Ok(());
+            }
+        };
+        let mut nodes = Vec::new();
+        for s in layout.slots.iter().rev() {
+            let node = self
+                .conn
+                .get_tree()
+                .context("getting the tree to size the slots")?
+                .find_focused(|n| n.focused)
+                .ok_or_else(|| anyhow::anyhow!("no focused window"))?;
+            let size_px = match layout.style {
+                LayoutStyle::Splitv => node.rect.height,
+                LayoutStyle::Splith => node.rect.width,
+                LayoutStyle::Tabbed => unreachable!(),
+            } as f64;
+            nodes.push((node.id, size_px, s.size));
+            self.run("focus prev sibling")?;
+        }
+        let tot_size_px = nodes.iter().fold(0., |acc, el| acc + el.1);
+        for n in nodes.iter().rev() {
+            // The sizes of the slots are normalized when loading the layout.
+            let size_px = (n.2 * tot_size_px) as i32;
+            self.run(&format!(
+                "[con_id={}] focus; resize set {} {} px",
+                n.0, dim, size_px
+            ))?;
+        }
+        self.run("focus parent")?;
+        Ok(())
+    }
+}
+impl LayoutVisitor for LayoutBuilder {
+    fn on_output(&mut self, output: &Output, _ctx: &VisitContext) -> Result<VisitAction> {
+        if self.here {
+            return Ok(VisitAction::Continue);
+        }
+        self.previous_workspace = self
+            .conn
+            .get_workspaces()?
+            .into_iter()
+            .find(|w| w.focused)
+            .map(|w| w.name);
+        self.run(&format!("focus output {}", output.name))?;
+        Ok(VisitAction::Continue)
+    }
+    fn on_output_exit(&mut self, _output: &Output, _ctx: &VisitContext) -> Result<()> {
+        if let Some(name) = self.previous_workspace.take() {
+            self.run(&format!("workspace {}", name))?;
+        }
+        Ok(())
+    }
+    fn on_workspace(&mut self, workspace: &Workspace, _ctx: &VisitContext) -> Result<VisitAction> {
+        if !is_selected(&self.workspaces, workspace) {
+            return Ok(VisitAction::SkipChildren);
+        }
+        self.workspace_span =
+            Some(tracing::info_span!("workspace", name = %workspace.name).entered());
+        self.workspace_start = Some(Instant::now());
+        // The layout is set once the workspace is emptied, so that it applies to the workspace
+        // rather than to the container of the focused window. A workspace with only floating
+        // apps keeps its tiled windows and their layout.
+        let tiled = !workspace.layout.slots.is_empty();
+        let res = if self.here {
+            Ok(())
+        } else {
+            self.run(&format!("workspace {}", workspace.name))
+        };
+        let res = res
+            .and_then(|_| self.detach(&workspace.name, tiled))
+            .and_then(|_| {
+                if tiled {
+                    self.run(&format!("layout {}", workspace.style))
+                } else {
+                    Ok(())
+                }
+            });
+        // Without its workspace, the layout would be built wherever the focus is.
+        if self.failures.tolerate(res)?.is_none() {
+            return Ok(VisitAction::SkipChildren);
+        }
+        self.first_placeholder = None;
+        self.floating_filters = workspace
+            .floating
+            .iter()
+            .map(|f| match f {
+                FloatingApp::App(_) => WindowFilter::default(),
+                FloatingApp::AppWithId { filter, .. } => (**filter).clone(),
+            })
+            .collect();
+        Ok(VisitAction::Continue)
+    }
+    fn on_slot(&mut self, slot: &Slot, _ctx: &VisitContext) -> Result<VisitAction> {
+        self.slot_filter = slot.filter.clone();
+        Ok(VisitAction::Continue)
+    }
+    fn on_workspace_exit(&mut self, workspace: &Workspace, _ctx: &VisitContext) -> Result<()> {
+        // Building leaves the last placeholder focused, which in tabbed layouts
+        // hides the slots before it.
+        if let Some(con_id) = self.first_placeholder.take() {
+            self.run(&format!("[con_id={}] focus", con_id))?;
+        }
+        self.workspace_span = None;
+        if let Some(start) = self.workspace_start.take() {
+            self.durations.workspaces.push(report::WorkspaceDuration {
+                name: workspace.name.clone(),
+                build: start.elapsed(),
+            });
+        }
+        Ok(())
+    }
+    fn on_floating_app(&mut self, app: &str, id: &str, ctx: &VisitContext) -> Result<()> {
+        self.slot_filter = self.floating_filters.pop_front().unwrap_or_default();
+        // The placeholder is tiled next to the focused window first, floating it puts the
+        // layout back as it was.
+        let res = self.add_placeholder(app, id, ctx).and_then(|node_id| {
+            self.run(&format!("[con_id={}] floating enable", node_id))?;
+            Ok(node_id)
+        });
+        self.track(id, ctx, res)
+    }
+    fn on_layout_enter(&mut self, layout: &Layout, _ctx: &VisitContext) -> Result<()> {
+        let res = self
+            .run("splith")
+            .and_then(|_| self.run(&format!("layout {}", layout.style)));
+        self.failures.tolerate(res)?;
+        Ok(())
+    }
+    fn on_layout_exit(&mut self, layout: &Layout, _ctx: &VisitContext) -> Result<()> {
+        let res = self.resize(layout);
+        self.failures.tolerate(res)?;
+        Ok(())
+    }
+    fn on_app(&mut self, app: &str, id: &str, ctx: &VisitContext) -> Result<()> {
+        let res = self.add_placeholder(app, id, ctx);
+        self.track(id, ctx, res)
+    }
+}
+
+struct Spawner<'a> {
+    placeholder: &'a ClientHandle,
+    workspaces: &'a [String],
+    mapping: &'a HashMap<String, Vec<i64>>,
+    tokens: &'a HashMap<i64, u64>,
+    claimed: &'a HashMap<i64, DetachedView>,
+    /// Whether to spawn each app, in the order they are visited, all of them if `None`.
+    exec: Option<&'a [bool]>,
+    env: &'a [(String, String)],
+    /// How the apps are launched, unless their slot says otherwise.
+    exec_via: launch::ExecVia,
+    /// How the app of the slot being visited is launched, if its slot says.
+    slot_exec_via: Option<launch::ExecVia>,
+    /// The variables of the slot being visited, over the ones of `env`.
+    slot_env: Vec<(String, String)>,
+    /// How each floating app of the workspace being visited is launched, if it says.
+    floating_exec_via: VecDeque<Option<launch::ExecVia>>,
+    launchers: HashMap<launch::ExecVia, Box<dyn launch::Launcher>>,
+    /// How many apps were visited.
+    visited: usize,
+    spawned: HashMap<String, usize>,
+    /// Placeholders whose app was spawned.
+    launched: HashSet<i64>,
+    failures: Failures,
+}
+impl Spawner<'_> {
+    /// Launches `app` the way `via` says, or `--exec-via` if it does not.
+    fn launch(
+        &mut self,
+        via: Option<launch::ExecVia>,
+        app: &str,
+        activation_token: Option<&str>,
+    ) -> Result<()> {
+        let via = via.unwrap_or(self.exec_via);
+        let launcher = match self.launchers.entry(via) {
+            Entry::Occupied(e) => e.into_mut(),
+            Entry::Vacant(e) => e.insert(launch::launcher(via)?),
+        };
+        let mut env = self.env.to_vec();
+        for (key, value) in &self.slot_env {
+            env.retain(|(k, _)| k != key);
+            env.push((key.clone(), value.clone()));
+        }
+        spawn(launcher.as_mut(), app, activation_token, &env)
+    }
+}
+impl LayoutVisitor for Spawner<'_> {
+    fn on_workspace(&mut self, workspace: &Workspace, _ctx: &VisitContext) -> Result<VisitAction> {
+        if !is_selected(self.workspaces, workspace) {
+            return Ok(VisitAction::SkipChildren);
+        }
+        self.floating_exec_via = workspace
+            .floating
+            .iter()
+            .map(|f| match f {
+                FloatingApp::App(_) => None,
+                FloatingApp::AppWithId { exec_via, .. } => *exec_via,
+            })
+            .collect();
+        Ok(VisitAction::Continue)
+    }
+    fn on_slot(&mut self, slot: &Slot, _ctx: &VisitContext) -> Result<VisitAction> {
+        self.slot_exec_via = slot.exec_via;
+        self.slot_env = slot.env.clone().into_iter().collect();
+        Ok(VisitAction::Continue)
+    }
+    fn on_app(&mut self, app: &str, id: &str, _ctx: &VisitContext) -> Result<()> {
+        // Apps are visited in the same order as by the builder, so the n-th spawn of an id
+        // belongs to its n-th placeholder.
+        let n = self.spawned.entry(id.to_owned()).or_default();
+        let con_id = self.mapping.get(id).and_then(|v| v.get(*n));
+        *n += 1;
+        let exec = self
+            .exec
+            .is_none_or(|e| e.get(self.visited) != Some(&false));
+        self.visited += 1;
+        // The app is already running in a window that was on the workspace.
+        if !exec || con_id.is_some_and(|c| self.claimed.contains_key(c)) {
+            return Ok(());
+        }
+        let token = con_id.and_then(|c| self.tokens.get(c)).copied();
+        // The placeholder hands its activation token to the app, so the app can take focus.
+        let activation =
+            token.and_then(|t| self.placeholder.activation_token(t, ACTIVATION_TIMEOUT));
+        let res = self.launch(
+            self.slot_exec_via,
+            &format!("{}.desktop", app),
+            activation.as_deref(),
+        );
+        if self.failures.tolerate(res)?.is_none() {
+            return Ok(());
+        }
+        self.launched.extend(con_id);
+        if let Some(token) = token {
+            self.placeholder
+                .set_state(token, PlaceholderState::Launched);
+        }
+        Ok(())
+    }
+    fn on_floating_app(&mut self, app: &str, id: &str, ctx: &VisitContext) -> Result<()> {
+        self.slot_exec_via = self.floating_exec_via.pop_front().flatten();
+        self.slot_env.clear();
+        self.on_app(app, id, ctx)
+    }
+}
+
+/// Where the swapper shows how far it got.
+#[derive(Debug)]
+struct Progress {
+    /// In an overlay in the corner of the screen.
+    overlay: bool,
+    /// On stdout, for waybar.
+    waybar: bool,
+    /// On stdout, slot by slot, with `--interactive`.
+    status: Option<tui::Status>,
+    /// Over D-Bus, where the restore can also be cancelled, when run by the daemon.
+    service: Option<dbus::Handle>,
+}
+
+struct Swapper {
+    conn: Box<dyn CommandRunner>,
+    events: Events,
+    mapping: HashMap<String, Vec<i64>>,
+    tokens: HashMap<i64, u64>,
+    /// The filters of the placeholders that have one.
+    filters: HashMap<i64, WindowFilter>,
+    /// Placeholders currently floating, they can be toggled while waiting.
+    floating: HashSet<i64>,
+    /// Windows that replaced a placeholder.
+    adopted: HashSet<i64>,
+    /// New windows that no placeholder could take yet, as their title or marks may change or a
+    /// slot may take them as a fallback later.
+    floated: HashSet<i64>,
+    patterns: pattern::Patterns,
+    progress: Progress,
+    log: Option<EventLog>,
+    notifier: notify::Notifier,
+    failures: Failures,
+    /// What became of the placeholders that were not closed.
+    outcomes: HashMap<i64, report::Outcome>,
+    /// When the placeholders were filled.
+    filled: HashMap<i64, Instant>,
+    /// When the swap started, which the fallbacks of the slots count from.
+    started: Instant,
+    /// Whether a window goes to the first slot that can take it rather than the most specific.
+    first_match: bool,
+}
+
+impl Swapper {
+    /// Creates a swapper reading from the subscription used to build the layout, so that the
+    /// windows opened in the meantime are not missed.
+    #[allow(clippy::too_many_arguments)]
+    fn new(
+        conn: Box<dyn CommandRunner>,
+        events: Events,
+        mapping: HashMap<String, Vec<i64>>,
+        tokens: HashMap<i64, u64>,
+        filters: HashMap<i64, WindowFilter>,
+        progress: Progress,
+        log: Option<EventLog>,
+        notifier: notify::Notifier,
+        policy: ErrorPolicy,
+    ) -> Self {
+        Swapper {
+            conn,
+            events,
+            mapping,
+            tokens,
+            filters,
+            floating: HashSet::new(),
+            adopted: HashSet::new(),
+            floated: HashSet::new(),
+            patterns: pattern::Patterns::default(),
+            progress,
+            log,
+            notifier,
+            failures: Failures::new(policy),
+            outcomes: HashMap::new(),
+            filled: HashMap::new(),
+            started: Instant::now(),
+            first_match: false,
+        }
+    }
+    fn run(&mut self, cmd: &str) -> Result<()> {
+        self.conn.run_command(cmd)
+    }
+    /// The ids of the slots whose placeholders wait for a window with the app_id or class
+    /// `matcher`: that very id first, then the patterns matching it in order.
+    fn slot_ids(&mut self, matcher: &str) -> Vec<String> {
+        let patterns = &mut self.patterns;
+        let mut ids: Vec<String> = self
+            .mapping
+            .iter()
+            .filter(|(id, v)| !v.is_empty() && *id != matcher && patterns.matches(id, matcher))
+            .map(|(id, _)| id.clone())
+            .collect();
+        ids.sort();
+        if self.mapping.get(matcher).is_some_and(|v| !v.is_empty()) {
+            ids.insert(0, matcher.to_owned());
+        }
+        ids
+    }
+    /// Takes the placeholder of the highest priority that can take `node` and that `pick`
+    /// accepts, if any, then the one of the most specific slot unless `first_match` is set,
+    /// then the one whose fuzzy title is the closest to the title of `node`: among equals, the
+    /// last one of the first slot id.
+    fn take_waiting(&mut self, node: &Node, mut pick: impl FnMut(i64) -> bool) -> Option<i64> {
+        let ids = self.slot_ids(matcher(node)?);
+        let no_filter = WindowFilter::default();
+        let conn = &mut self.conn;
+        let mut here = None;
+        let mut best: Option<(i32, usize, f64, usize, usize)> = None;
+        for (i, id) in ids.iter().enumerate() {
+            for (idx, c) in self.mapping[id].iter().enumerate().rev() {
+                let filter = self.filters.get(c).unwrap_or(&no_filter);
+                if best.is_some_and(|(p, ..)| filter.priority < p) {
+                    continue;
+                }
+                if !(filter.fits(node) && pick(*c)) {
+                    continue;
+                }
+                if filter.same_workspace
+                    && !here
+                        .get_or_insert_with(|| workspace_views(conn.as_mut(), node.id))
+                        .contains(c)
+                {
+                    continue;
+                }
+                let specificity = if self.first_match {
+                    0
+                } else {
+                    filter.specificity()
+                };
+                let rank = (filter.priority, specificity, filter.title_score(node));
+                if best.is_none_or(|(p, sp, s, ..)| rank > (p, sp, s)) {
+                    best = Some((rank.0, rank.1, rank.2, i, idx));
+                }
+            }
+        }
+        let (_, _, _, i, idx) = best?;
+        let v = self.mapping.get_mut(&ids[i]).expect("listed above");
+        Some(v.remove(idx))
+    }
+    /// Takes the placeholder whose slot has waited long enough for its own app and prefers
+    /// `node` the most among its fallbacks, if any.
+    fn take_fallback(&mut self, node: &Node) -> Option<i64> {
+        let m = matcher(node)?;
+        let now = Instant::now();
+        let patterns = &mut self.patterns;
+        let conn = &mut self.conn;
+        let mut here = None;
+        let mut best: Option<(usize, &String, usize)> = None;
+        let mut ids: Vec<&String> = self.mapping.keys().collect();
+        ids.sort();
+        for id in ids {
+            for (idx, c) in self.mapping[id].iter().enumerate().rev() {
+                let Some(filter) = self.filters.get(c) else {
+                    continue;
+                };
+                if filter
+                    .fallback_due(self.started)
+                    .is_none_or(|due| now < due)
+                {
+                    continue;
+                }
+                let Some(rank) = filter.fallback.iter().position(|f| patterns.matches(f, m)) else {
+                    continue;
+                };
+                if best.is_some_and(|(r, ..)| rank >= r) || !filter.fits(node) {
+                    continue;
+                }
+                if filter.same_workspace
+                    && !here
+                        .get_or_insert_with(|| workspace_views(conn.as_mut(), node.id))
+                        .contains(c)
+                {
+                    continue;
+                }
+                best = Some((rank, id, idx));
+            }
+        }
+        let (_, id, idx) = best?;
+        let id = id.clone();
+        Some(self.mapping.get_mut(&id)?.remove(idx))
+    }
+    /// Whether `node`, which no placeholder took, would fill a slot that only takes windows
+    /// from its own workspace, so that it was opened for something else.
+    fn waits_elsewhere(&mut self, node: &Node) -> bool {
+        let Some(m) = matcher(node) else {
+            return false;
+        };
+        self.slot_ids(m).iter().any(|id| {
+            self.mapping[id].iter().any(|c| {
+                self.filters
+                    .get(c)
+                    .is_some_and(|f| f.same_workspace && f.fits(node))
+            })
+        })
+    }
+    /// When the next slot starts to take its fallbacks, if one is still to.
+    fn next_fallback(&self) -> Option<Instant> {
+        let now = Instant::now();
+        self.mapping
+            .values()
+            .flatten()
+            .filter_map(|c| self.filters.get(c)?.fallback_due(self.started))
+            .filter(|due| *due > now)
+            .min()
+    }
+    /// Puts the windows floated so far in place of the placeholders that now take them as
+    /// fallbacks, returning how many were.
+    fn adopt_fallbacks(&mut self) -> Result<usize> {
+        let now = Instant::now();
+        let due = self
+            .mapping
+            .values()
+            .flatten()
+            .filter_map(|c| self.filters.get(c)?.fallback_due(self.started))
+            .any(|due| due <= now);
+        if !due || self.floated.is_empty() {
+            return Ok(0);
+        }
+        let tree = self.conn.get_tree()?;
+        let mut floated: Vec<i64> = self.floated.iter().copied().collect();
+        floated.sort();
+        let mut done = 0;
+        for id in floated {
+            let Some(node) = tree.find_as_ref(|n| n.id == id) else {
+                self.floated.remove(&id);
+                continue;
+            };
+            if let Some(con_id) = self.take_fallback(node) {
+                log::debug!("window {} is a fallback for placeholder {}", id, con_id);
+                self.replace(con_id, node)?;
+                done += 1;
+            }
+        }
+        Ok(done)
+    }
+    fn record(&self, decision: &str, details: serde_json::Value) {
+        log::debug!("{}: {}", decision, details);
+        if let Some(log) = &self.log {
+            log.decision(decision, details);
+        }
+    }
+    /// Shows how many windows are in place, and the slots that are still waiting for one.
+    fn report_progress(&self, placeholder: Option<&ClientHandle>, total: usize, count: usize) {
+        self.notifier
+            .status(&format!("{}/{} windows restored", total - count, total));
+        let mut pending: Vec<String> = self
+            .mapping
+            .iter()
+            .filter(|(_, v)| !v.is_empty())
+            .map(|(id, v)| match v.len() {
+                1 => id.clone(),
+                n => format!("{} x{}", id, n),
+            })
+            .collect();
+        pending.sort();
+        if self.progress.waybar {
+            let text = format!("{}/{}", total - count, total);
+            let tooltip = format!("Waiting for {}", pending.join(", "));
+            waybar::print(&text, &tooltip, "restoring");
+        }
+        if let Some(status) = &self.progress.status {
+            let pending = self.mapping.values().flatten().copied().collect();
+            status.update(&pending, &self.outcomes);
+        }
+        if let Some(service) = &self.progress.service {
+            service.progress(total - count, total, &pending);
+        }
+        if let Some(placeholder) = placeholder.filter(|_| self.progress.overlay) {
+            placeholder.show_progress(total - count, total, pending);
+        }
+    }
+    /// Puts the new window `node` in place of a placeholder waiting for it, if any.
+    ///
+    /// Returns whether a placeholder was replaced, windows that no placeholder waits for are
+    /// made floating.
+    fn adopt(&mut self, node: &Node) -> Result<bool> {
+        // Placeholders whose creation was not seen by the builder show up here too.
+        if is_placeholder(node) {
+            return Ok(false);
+        }
+        if let Some(con_id) = self.take_waiting(node, |_| true) {
+            self.replace(con_id, node)?;
+            return Ok(true);
+        }
+        if let Some(con_id) = self.take_fallback(node) {
+            log::debug!(
+                "window {} is a fallback for placeholder {}",
+                node.id,
+                con_id
+            );
+            self.replace(con_id, node)?;
+            return Ok(true);
+        }
+        if self.waits_elsewhere(node) {
+            self.record(
+                "ignore",
+                json!({ "window": node.id, "matcher": matcher(node) }),
+            );
+            return Ok(false);
+        }
+        self.floated.insert(node.id);
+        self.record(
+            "float",
+            json!({ "window": node.id, "matcher": matcher(node) }),
+        );
+        self.run(&format!("[con_id={}] floating enable", node.id))?;
+        Ok(false)
+    }
+    /// Puts the window `node`, just moved to another workspace, in place of a placeholder waiting
+    /// for it there.
+    ///
+    /// Returns whether a placeholder was replaced.
+    fn adopt_moved(&mut self, node: &Node) -> Result<bool> {
+        if is_placeholder(node) || self.adopted.contains(&node.id) {
+            return Ok(false);
+        }
+        if matcher(node).is_none_or(|m| self.slot_ids(m).is_empty()) {
+            return Ok(false);
+        }
+        let tree = self.conn.get_tree()?;
+        // A window moved to the scratchpad is not next to any placeholder.
+        let Some(workspace) = tree::find_workspace(&tree, node.id) else {
+            return Ok(false);
+        };
+        let Some(con_id) =
+            self.take_waiting(node, |c| workspace.find_as_ref(|n| n.id == c).is_some())
+        else {
+            return Ok(false);
+        };
+        log::debug!("window {} moved next to placeholder {}", node.id, con_id);
+        self.replace(con_id, node)?;
+        Ok(true)
+    }
+    /// Puts the window `node`, which no placeholder could take when it opened, in place of a
+    /// placeholder whose slot wants the title or the mark it just got.
+    ///
+    /// Apps that map their window before setting its app_id, or xwayland ones whose class comes
+    /// late, are caught here too when a title event comes after it, as it carries the whole
+    /// node.
+    ///
+    /// Returns whether a placeholder was replaced.
+    fn adopt_changed(&mut self, node: &Node) -> Result<bool> {
+        if !self.floated.contains(&node.id) {
+            return Ok(false);
+        }
+        let Some(con_id) = self
+            .take_waiting(node, |_| true)
+            .or_else(|| self.take_fallback(node))
+        else {
+            return Ok(false);
+        };
+        log::debug!("window {} changed for placeholder {}", node.id, con_id);
+        self.replace(con_id, node)?;
+        Ok(true)
+    }
+    /// Puts the window `node`, which just got marked, in place of a placeholder waiting for a
+    /// slot with the id of one of its marks.
+    ///
+    /// This lets any window fill a slot with `swaymsg mark <id>`.
+    fn adopt_marked(&mut self, node: &Node) -> Result<bool> {
+        if is_placeholder(node) || self.adopted.contains(&node.id) {
+            return Ok(false);
+        }
+        let con_id = node
+            .marks
+            .iter()
+            .find_map(|mark| self.mapping.get_mut(mark).and_then(|v| v.pop()));
+        let Some(con_id) = con_id else {
+            return Ok(false);
+        };
+        log::debug!("window {} marked for placeholder {}", node.id, con_id);
+        self.replace(con_id, node)?;
+        Ok(true)
+    }
+    /// Swaps the window `node` with the placeholder `con_id`, and closes the placeholder.
+    fn replace(&mut self, con_id: i64, node: &impl MatchTarget) -> Result<()> {
+        let id = node.id();
+        // The window takes over the placeholder as it is now, tiled or floating.
+        let floating = self.floating.remove(&con_id);
+        if floating != node.is_floating() {
+            let toggle = if floating { "enable" } else { "disable" };
+            self.run(&format!("[con_id={}] floating {}", id, toggle))?;
+        }
+        self.record(
+            "swap",
+            json!({
+                "window": id,
+                "placeholder": con_id,
+                "matcher": node.matcher(),
+            }),
+        );
+        self.run(&format!(
+            "[con_id={con_id}] swap container with con_id {}",
+            id
+        ))?;
+        self.run(&format!("[con_id={con_id}] kill"))?;
+        self.adopted.insert(id);
+        self.floated.remove(&id);
+        let outcome = report::Outcome::Filled { window: id };
+        self.filled.insert(con_id, Instant::now());
+        self.outcomes.insert(con_id, outcome);
+        Ok(())
+    }
+    /// Puts the windows that were on the workspaces before the restore in place of the
+    /// placeholders set aside for them.
+    fn adopt_detached(&mut self, claimed: HashMap<i64, DetachedView>) -> Result<()> {
+        if claimed.is_empty() {
+            return Ok(());
+        }
+        // The events of the placeholders floated by the builder have not been handled yet.
+        let tree = self.conn.get_tree()?;
+        // In the order of the placeholders, so that a replay sends the same commands.
+        let mut claimed: Vec<_> = claimed.into_iter().collect();
+        claimed.sort_by_key(|(con_id, _)| *con_id);
+        for (con_id, node) in claimed {
+            if tree
+                .find_as_ref(|n| n.id == con_id && n.node_type == NodeType::FloatingCon)
+                .is_some()
+            {
+                self.floating.insert(con_id);
+            }
+            for v in self.mapping.values_mut() {
+                v.retain(|c| *c != con_id);
+            }
+            let res = self.replace(con_id, &node);
+            self.failures.tolerate(res)?;
+        }
+        Ok(())
+    }
+    /// Forgets the placeholder `node`, which was closed before its window showed up.
+    ///
+    /// Returns whether it was still waiting.
+    fn forget(&mut self, node: &Node) -> bool {
+        let Some(id) = tree::app_id(node).and_then(|a| a.strip_prefix("swaystart-")) else {
+            return false;
+        };
+        let Some(v) = self.mapping.get_mut(id) else {
+            return false;
+        };
+        let Some(idx) = v.iter().position(|i| *i == node.id) else {
+            return false;
+        };
+        v.swap_remove(idx);
+        self.record("forget", json!({ "placeholder": node.id }));
+        true
+    }
+    /// Catches up with the windows that opened or closed while events could not be received,
+    /// returning how many placeholders are done with.
+    fn rescan(&mut self, known: &mut HashSet<i64>) -> Result<usize> {
+        log::warn!("looking for windows missed while the event stream was down");
+        let tree = self.conn.get_tree()?;
+        // Placeholders sent to the scratchpad are still waiting, but windows there are not
+        // candidates to replace them.
+        let placeholders: HashSet<i64> = tree::iter_views(&tree, Order::Document, true)
+            .map(|n| n.id)
+            .collect();
+        // Missed windows are adopted the most recently focused first: when there are more of
+        // them than placeholders for their app, the one the user is looking at gets a slot and
+        // the others are floated.
+        let views: Vec<&Node> = tree::iter_views(&tree, Order::Focus, false).collect();
+
+        let mut done = 0;
+        for v in self.mapping.values_mut() {
+            let before = v.len();
+            v.retain(|con_id| placeholders.contains(con_id));
+            done += before - v.len();
+        }
+        let pending: HashSet<i64> = self.mapping.values().flatten().copied().collect();
+        self.floating = views
+            .iter()
+            .filter(|n| n.node_type == NodeType::FloatingCon && pending.contains(&n.id))
+            .map(|n| n.id)
+            .collect();
+        for node in views {
+            if known.insert(node.id) && self.adopt(node)? {
+                done += 1;
+            }
+        }
+        Ok(done)
+    }
+    /// Swaps the windows in as they show up, until the placeholders are done with or `timeout`
+    /// runs out.
+    ///
+    /// Without a `placeholder` client, as in a replay, nothing is shown of the progress.
+    fn swap(
+        &mut self,
+        placeholder: Option<&ClientHandle>,
+        timeout: Option<Duration>,
+    ) -> Result<()> {
+        let mut count = 0;
+        for v in self.mapping.values() {
+            count += v.len();
+        }
+        let total = count;
+        // Windows already open are left alone if a rescan finds them, unless their creation is
+        // still in the backlog.
+        let mut known: HashSet<i64> =
+            tree::iter_views(&self.conn.get_tree()?, Order::Document, true)
+                .map(|n| n.id)
+                .collect();
+        for event in self.events.backlog() {
+            if let Event::Window(w) = event {
+                if w.change == WindowChange::New {
+                    known.remove(&w.container.id);
+                }
+            }
+        }
+        self.report_progress(placeholder, total, count);
+        let deadline = timeout.map(|t| Instant::now() + t);
+        loop {
+            // Waking up for the watchdog or to look for a cancellation is not running out of
+            // time.
+            let cancel_poll = self
+                .progress
+                .service
+                .as_ref()
+                .map(|_| Instant::now() + CANCEL_POLL);
+            let wake_up = deadline
+                .into_iter()
+                .chain(self.notifier.next_ping())
+                .chain(cancel_poll)
+                .chain(self.next_fallback())
+                .min();
+            let event = self.events.next_until(wake_up)?;
+            self.notifier.watchdog();
+            if self
+                .progress
+                .service
+                .as_ref()
+                .is_some_and(|s| s.cancelled())
+            {
+                return Err(Cancelled.into());
+            }
+            if event.is_none() && deadline.is_none_or(|d| Instant::now() < d) {
+                let res = self.adopt_fallbacks();
+                let done = self.failures.tolerate(res)?.unwrap_or(0);
+                if done > 0 {
+                    count -= done;
+                    self.report_progress(placeholder, total, count);
+                    if count == 0 {
+                        break;
+                    }
+                }
+                continue;
+            }
+            let Some(event) = event else {
+                log::warn!("{} windows did not appear in time", count);
+                let pending: Vec<_> = self.mapping.values().flatten().collect();
+                self.record("timeout", json!({ "placeholders": pending }));
+                for con_id in self.mapping.values().flatten() {
+                    if let (Some(token), Some(placeholder)) = (self.tokens.get(con_id), placeholder)
+                    {
+                        placeholder.set_state(*token, PlaceholderState::TimedOut);
+                    }
+                    self.outcomes.insert(*con_id, report::Outcome::TimedOut);
+                }
+                if self.failures.policy.strict_timeouts {
+                    let e = anyhow::anyhow!("{} windows did not appear in time", count);
+                    self.failures.tolerate::<()>(Err(e))?;
+                }
+                break;
+            };
+            log::debug!("{:?}", event);
+            if let (Some(log), Event::Window(w)) = (&self.log, &event) {
+                log.window(w);
+            }
+            if self.events.take_reconnected() {
+                let res = self.rescan(&mut known);
+                let done = self.failures.tolerate(res)?.unwrap_or(0);
+                if done > 0 {
+                    count -= done;
+                    self.report_progress(placeholder, total, count);
+                    if count == 0 {
+                        break;
+                    }
+                }
+            }
+            let res = self.handle(event, &mut known);
+            let done = self.failures.tolerate(res)?.unwrap_or(false);
+            if done {
+                count -= 1;
+                self.report_progress(placeholder, total, count);
+                if count == 0 {
+                    break;
+                }
+            }
+        }
+        if let Some(placeholder) = placeholder {
+            placeholder.hide_progress();
+        }
+
+        Ok(())
+    }
+    /// Reacts to `event`, returning whether a placeholder is done with.
+    fn handle(&mut self, event: Event, known: &mut HashSet<i64>) -> Result<bool> {
+        let done = match event {
+            Event::Window(w) => match w.change {
+                WindowChange::Close => {
+                    self.floated.remove(&w.container.id);
+                    self.forget(&w.container)
+                }
+                WindowChange::Move => self.adopt_moved(&w.container)?,
+                WindowChange::Mark => {
+                    self.adopt_marked(&w.container)? || self.adopt_changed(&w.container)?
+                }
+                WindowChange::Title => self.adopt_changed(&w.container)?,
+                WindowChange::Floating => {
+                    let id = w.container.id;
+                    if self.mapping.values().flatten().any(|c| *c == id) {
+                        if w.container.node_type == NodeType::FloatingCon {
+                            self.floating.insert(id);
+                        } else {
+                            self.floating.remove(&id);
+                        }
+                    }
+                    false
+                }
+                WindowChange::New => known.insert(w.container.id) && self.adopt(&w.container)?,
+                _ => false,
+            },
+            _ => false,
+        };
+        Ok(done)
+    }
+}
+
+fn connect() -> Result<Connection> {
+    Ok(Connection::new().map_err(Error::SwayUnreachable)?)
+}
+
+/// Whether `workspace` is among the `selected` ones, an empty selection meaning all of them.
+fn is_selected(selected: &[String], workspace: &Workspace) -> bool {
+    selected.is_empty() || selected.contains(&workspace.name)
+}
+
+fn is_placeholder(node: &Node) -> bool {
+    tree::app_id(node).is_some_and(|a| a.starts_with("swaystart-"))
+}
+
+/// The id of the slots that `node` can fill, whatever its window type: the filter of the slot
+/// keeps dialogs and splash screens out.
+fn matcher(node: &Node) -> Option<&str> {
+    match node.window_properties.as_ref() {
+        Some(props) => props.class.as_deref(),
+        None => node.app_id.as_deref(),
+    }
+}
+
+/// A window that can take the place of a placeholder.
+trait MatchTarget {
+    fn id(&self) -> i64;
+    fn is_floating(&self) -> bool;
+    /// The id of the slots it can fill, if any.
+    fn matcher(&self) -> Option<&str>;
+    fn title(&self) -> Option<&str>;
+    /// Its X11 window role, if it has one.
+    fn role(&self) -> Option<&str>;
+    /// Its X11 window type, if it has one.
+    fn window_type(&self) -> Option<&str>;
+    /// The process it belongs to, unknown on i3.
+    fn pid(&self) -> Option<i32>;
+    fn marks(&self) -> &[String];
+    fn shell(&self) -> Shell;
+    /// Whether it is an X11 window transient for another one.
+    fn is_transient(&self) -> bool;
+}
+
+impl MatchTarget for Node {
+    fn id(&self) -> i64 {
+        self.id
+    }
+    fn is_floating(&self) -> bool {
+        self.node_type == NodeType::FloatingCon
+    }
+    fn matcher(&self) -> Option<&str> {
+        matcher(self)
+    }
+    fn title(&self) -> Option<&str> {
+        self.name.as_deref()
+    }
+    fn role(&self) -> Option<&str> {
+        self.window_properties.as_ref()?.window_role.as_deref()
+    }
+    fn window_type(&self) -> Option<&str> {
+        // i3 gives the window type next to the properties rather than in them, where swayipc
+        // does not read it.
+        self.window_properties.as_ref()?.window_type.as_deref()
+    }
+    fn pid(&self) -> Option<i32> {
+        self.pid
+    }
+    fn marks(&self) -> &[String] {
+        &self.marks
+    }
+    /// sway only gives the X11 properties of xwayland windows, where the class stands in for
+    /// the app_id, so a slot id matches the same app both ways.
+    fn shell(&self) -> Shell {
+        if self.window_properties.is_some() {
+            Shell::Xwayland
+        } else {
+            Shell::XdgShell
+        }
+    }
+    fn is_transient(&self) -> bool {
+        self.window_properties
+            .as_ref()
+            .is_some_and(|p| p.transient_for.is_some())
+    }
+}
+
+/// What is kept of a window floated out of the way by the builder, rather than its whole node.
+#[derive(Debug)]
+struct DetachedView {
+    id: i64,
+    matcher: Option<String>,
+    title: Option<String>,
+    role: Option<String>,
+    window_type: Option<String>,
+    pid: Option<i32>,
+    marks: Vec<String>,
+    shell: Shell,
+    transient: bool,
+}
+
+impl MatchTarget for DetachedView {
+    fn id(&self) -> i64 {
+        self.id
+    }
+    /// The builder floats the windows it detaches.
+    fn is_floating(&self) -> bool {
+        true
+    }
+    fn matcher(&self) -> Option<&str> {
+        self.matcher.as_deref()
+    }
+    fn title(&self) -> Option<&str> {
+        self.title.as_deref()
+    }
+    fn role(&self) -> Option<&str> {
+        self.role.as_deref()
+    }
+    fn window_type(&self) -> Option<&str> {
+        self.window_type.as_deref()
+    }
+    fn pid(&self) -> Option<i32> {
+        self.pid
+    }
+    fn marks(&self) -> &[String] {
+        &self.marks
+    }
+    fn shell(&self) -> Shell {
+        self.shell
+    }
+    fn is_transient(&self) -> bool {
+        self.transient
+    }
+}
+
+/// How the outcome of `restore` and `diff` is printed.
+#[derive(ValueEnum, Clone, Copy, Debug, Default)]
+pub enum Format {
+    #[default]
+    Text,
+    Json,
+}
+
+/// The options of a restore, those of `swaystart restore`.
+///
+/// More options may come, so build it from [`RestoreOptions::default`].
+#[derive(clap::Args, Debug, Default)]
+pub struct RestoreOptions {
+    #[arg(short, long, default_value = "false")]
+    pub spawn: bool,
+    /// The layout file, `-` to read it from stdin, can be repeated to restore the workspaces of
+    /// several layout files of the same output together
+    #[arg(
+        short,
+        long = "layout-file",
+        value_name = "LAYOUT_FILE",
+        required_unless_present = "auto",
+        conflicts_with = "auto"
+    )]
+    pub layout_files: Vec<PathBuf>,
+    /// Restore the layout in --layout-dir for the outputs that are connected
+    #[arg(long, default_value = "false")]
+    pub auto: bool,
+    /// Where --auto looks for layouts [default: $XDG_CONFIG_HOME/swaystart/layouts]
+    #[arg(long)]
+    pub layout_dir: Option<PathBuf>,
+    /// Print the layout that --auto picks instead of restoring it
+    #[arg(long, default_value = "false", conflicts_with = "layout_files")]
+    pub print_choice: bool,
+    #[arg(long, default_value = "false")]
+    pub no_animation: bool,
+    /// The window manager to restore the layout in [default: the one of the session, from the
+    /// environment]
+    #[arg(long, value_enum)]
+    pub backend: Option<Backend>,
+    /// Only restore the workspace with this name, can be repeated
+    #[arg(long = "workspace")]
+    pub workspaces: Vec<String>,
+    /// Only restore the workspaces of this group of the config, can be repeated
+    #[arg(long = "group")]
+    pub groups: Vec<String>,
+    /// Only restore the slots of this app, written ID or app_id=ID, can be repeated
+    #[arg(long, value_parser = parse_app_filter)]
+    pub include: Vec<String>,
+    /// Leave out the slots of this app, written ID or app_id=ID, can be repeated
+    #[arg(long, value_parser = parse_app_filter)]
+    pub exclude: Vec<String>,
+    /// Only restore the tiled slots of the layout, leaving the floating windows as they are
+    #[arg(long, default_value = "false", conflicts_with = "floating_only")]
+    pub tiling_only: bool,
+    /// Only restore the floating apps of the layout, leaving the tiled windows as they are
+    #[arg(long, default_value = "false")]
+    pub floating_only: bool,
+    /// Restore the slots of an app on another workspace, written ID:WORKSPACE or
+    /// app_id=ID:workspace=WORKSPACE, can be repeated
+    #[arg(long = "move", value_parser = moves::parse)]
+    pub moves: Vec<Move>,
+    /// Don't restore the layout on the output that looks the most like its screen when no output
+    /// goes by its name
+    #[arg(long, default_value = "false")]
+    pub no_output_autodetect: bool,
+    /// Build the only workspace of the layout, once filtered by --workspace, on the focused
+    /// workspace instead of its own
+    #[arg(long, default_value = "false")]
+    pub here: bool,
+    /// Append the window events and what was done about them to this file, as JSON lines
+    #[arg(long)]
+    pub event_log: Option<PathBuf>,
+    /// Record the tree, the commands sent to sway and the events received in this directory,
+    /// for `swaystart replay`
+    #[arg(long)]
+    pub record_session: Option<PathBuf>,
+    /// Leave the titles of the windows out of --event-log and --record-session
+    #[arg(long, default_value = "false")]
+    pub no_titles: bool,
+    /// Give a new window to the first slot that can take it, rather than to the one that asks
+    /// the most of its windows
+    #[arg(long, default_value = "false")]
+    pub first_match: bool,
+    /// Don't show the restore progress in the top right corner of the screen
+    #[arg(long, default_value = "false")]
+    pub no_overlay: bool,
+    /// Seconds to wait for the windows to appear before giving up on the remaining placeholders
+    #[arg(long)]
+    pub swap_timeout: Option<u64>,
+    /// Print how long each phase of the restore took, and each slot waited, on stderr
+    #[arg(long, default_value = "false")]
+    pub timings: bool,
+    /// Print a summary of the restore on stdout when it is done, as JSON with `json`
+    #[arg(long, value_enum, default_value_t = Format::Text)]
+    pub format: Format,
+    /// Print the progress on stdout as JSON lines for a custom waybar module
+    #[arg(long, default_value = "false", conflicts_with = "format")]
+    pub waybar: bool,
+    /// Pick the apps to restore and the ones to spawn in the terminal, then follow the restore
+    /// slot by slot
+    #[arg(short, long, default_value = "false", conflicts_with = "waybar")]
+    pub interactive: bool,
+    /// Exit once the placeholders are open, and swap the windows in from the background
+    #[arg(
+        long,
+        default_value = "false",
+        conflicts_with_all = ["format", "waybar", "interactive", "print_choice"]
+    )]
+    pub detach: bool,
+    /// Stop the restore at the first failure instead of skipping what failed
+    ///
+    /// Running out of --swap-timeout is not a failure unless --strict-timeouts is set. The
+    /// placeholders are closed either way.
+    #[arg(long, default_value = "false")]
+    pub fail_fast: bool,
+    /// Count --swap-timeout running out as a failure
+    #[arg(long, default_value = "false")]
+    pub strict_timeouts: bool,
+    /// Show a desktop notification when the restore is over
+    #[arg(long, default_value = "false")]
+    pub notify: bool,
+    /// Seconds to wait for another swaystart restoring in the session to be done, instead of
+    /// failing right away
+    #[arg(long)]
+    pub wait_lock: Option<u64>,
+    /// Set this variable in the environment of the spawned apps, written KEY=VALUE, can be
+    /// repeated
+    #[arg(long, value_parser = parse_env)]
+    pub env: Vec<(String, String)>,
+    /// How to launch the apps with --spawn, unless their slot in the layout says otherwise
+    /// [default: direct]
+    #[arg(long, value_enum)]
+    pub exec_via: Option<launch::ExecVia>,
+    /// Run this shell command before the layout is built, can be repeated
+    #[arg(long)]
+    pub pre_cmd: Vec<String>,
+    /// Run this shell command once the restore is over, even if it failed, can be repeated
+    ///
+    /// The command gets the exit status of swaystart in SWAYSTART_STATUS, and when the restore
+    /// got to the end, the number of slots that got their window in SWAYSTART_FILLED and of the
+    /// ones that did not in SWAYSTART_UNMATCHED.
+    #[arg(long)]
+    pub post_cmd: Vec<String>,
+    /// Fail the restore when a --pre-cmd or --post-cmd fails, instead of only reporting it
+    #[arg(long, default_value = "false")]
+    pub fatal_hooks: bool,
+    /// Take over the lock of a swaystart that is no longer running
+    #[arg(long, default_value = "false")]
+    pub force: bool,
+    /// Seconds to wait for the placeholders to close once all the windows are swapped in
+    /// [default: 30]
+    #[arg(long)]
+    pub idle_timeout: Option<u64>,
+    /// The D-Bus service of the daemon, when it runs the restore.
+    #[arg(skip)]
+    pub(crate) service: Option<dbus::Handle>,
+}
+
+impl RestoreOptions {
+    /// Takes the options missing from the command line from the `[restore]` table of the config.
+    ///
+    /// Flags can only be turned on from the command line, so a flag set in the config stays set.
+    pub fn apply(&mut self, config: config::Restore) -> Result<(), Error> {
+        let filters = |filters: Vec<String>| -> Result<Vec<String>> {
+            filters
+                .iter()
+                .map(|f| parse_app_filter(f).map_err(anyhow::Error::msg))
+                .collect()
+        };
+        self.spawn |= config.spawn;
+        self.no_animation |= config.no_animation;
+        self.no_overlay |= config.no_overlay;
+        self.fail_fast |= config.fail_fast;
+        self.strict_timeouts |= config.strict_timeouts;
+        self.notify |= config.notify;
+        self.timings |= config.timings;
+        self.no_titles |= config.no_titles;
+        self.first_match |= config.first_match;
+        self.fatal_hooks |= config.fatal_hooks;
+        if self.include.is_empty() {
+            self.include = filters(config.include)
+                .context("include in the config")
+                .map_err(|e| Error::InvalidConfig(e.into()))?;
+        }
+        if self.exclude.is_empty() {
+            self.exclude = filters(config.exclude)
+                .context("exclude in the config")
+                .map_err(|e| Error::InvalidConfig(e.into()))?;
+        }
+        if self.pre_cmd.is_empty() {
+            self.pre_cmd = config.pre_cmd;
+        }
+        if self.post_cmd.is_empty() {
+            self.post_cmd = config.post_cmd;
+        }
+        self.event_log = self.event_log.take().or(config.event_log);
+        self.swap_timeout = self.swap_timeout.or(config.swap_timeout);
+        self.idle_timeout = self.idle_timeout.or(config.idle_timeout);
+        self.wait_lock = self.wait_lock.or(config.wait_lock);
+        self.exec_via = self.exec_via.or(config.exec_via);
+        self.backend = self.backend.or(config.backend);
+        self.layout_dir = self.layout_dir.take().or(config.layout_dir);
+        for (key, value) in config.env {
+            if !self.env.iter().any(|(k, _)| *k == key) {
+                self.env.push((key, value));
+            }
+        }
+        // The background swaystart has nowhere to print to, and no terminal to read from.
+        let json = matches!(self.format, Format::Json);
+        if self.detach && (json || self.waybar || self.interactive || self.print_choice) {
+            return Err(Error::InvalidConfig(
+                "--detach can't be used with --format json, --waybar, --interactive or \
+                 --print-choice"
+                    .into(),
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// Reads and validates the layout file, from stdin if `path` is `-`.
+pub fn load_layout(path: &Path) -> Result<Output, Error> {
+    read_layout(path, false).map_err(|e| Error::invalid_layout(path, e))
+}
+
+/// Loads the layout files in `paths` as a single layout, their workspaces in order.
+///
+/// The files must be layouts of the same output, with no workspace in common. The theme is the
+/// one of the first file.
+fn load_layouts(paths: &[PathBuf]) -> Result<Output, Error> {
+    let (first, rest) = paths.split_first().context("no layout file")?;
+    let mut output = load_layout(first)?;
+    if rest.is_empty() {
+        return Ok(output);
+    }
+    for w in &mut output.workspaces {
+        w.source = Some(first.clone());
+    }
+    for path in rest {
+        let other = load_layout(path)?;
+        if other.name != output.name {
+            return Err(Error::invalid_layout(
+                path,
+                format!(
+                    "it is a layout of output {}, but {} is one of output {}",
+                    other.name,
+                    first.display(),
+                    output.name
+                ),
+            ));
+        }
+        for mut w in other.workspaces {
+            if let Some(source) = output
+                .workspaces
+                .iter()
+                .find(|o| o.name == w.name)
+                .and_then(|o| o.source.as_ref())
+            {
+                return Err(Error::invalid_layout(
+                    path,
+                    format!("workspace {} is also in {}", w.name, source.display()),
+                ));
+            }
+            w.source = Some(path.clone());
+            output.workspaces.push(w);
+        }
+    }
+    Ok(output)
+}
+
+/// Reads the layout file at `path`, failing on missing or inconsistent slot sizes if `strict`.
+fn read_layout(path: &Path, strict: bool) -> Result<Output> {
+    let stdin = path == Path::new("-");
+    let conf = if stdin {
+        if std::io::stdin().is_terminal() {
+            anyhow::bail!("the layout is read from stdin, but nothing is piped into it");
+        }
+        let mut conf = String::new();
+        std::io::stdin()
+            .read_to_string(&mut conf)
+            .context("reading the layout from stdin")?;
+        conf
+    } else {
+        std::fs::read_to_string(path)
+            .with_context(|| format!("reading the layout from {}", path.display()))?
+    };
+    let mut output = schema::parse(&conf).with_context(|| {
+        if stdin {
+            "parsing the layout from stdin".to_owned()
+        } else {
+            format!("parsing the layout from {}", path.display())
+        }
+    })?;
+    output.theme.validate()?;
+    let mut depth = LayoutDepth::default();
+    depth.visit_output(&output)?;
+    if depth.max > MAX_LAYOUT_DEPTH {
+        anyhow::bail!(
+            "layouts are nested {} deep, at most {} are supported",
+            depth.max,
+            MAX_LAYOUT_DEPTH
+        );
+    }
+    MatchCheck.visit_output(&output)?;
+    SizeNormalizer { strict }.visit_output(&mut output)?;
+    Ok(output)
+}
+
+/// Checks that the layout can be restored in the current session, without changing anything.
+///
+/// With `strict`, slots without a size and slot sizes that don't add up to 1 are errors, and so
+/// are nodes of a type sway did not have when this was written.
+pub fn check(layout_file: &Path, strict: bool) -> Result<(), Error> {
+    let output =
+        read_layout(layout_file, strict).map_err(|e| Error::invalid_layout(layout_file, e))?;
+    let mut checker = check::Checker::new(strict)?;
+    checker.visit_output(&output)?;
+    checker.finish(layout_file)
+}
+
+/// Prints how the current session differs from the layout.
+pub fn diff(layout_file: &Path, threshold: Option<f64>, format: Format) -> Result<(), Error> {
+    let output = load_layout(layout_file)?;
+    let mut differ = diff::Differ::new(connect()?.get_tree()?, threshold.unwrap_or(DIFF_THRESHOLD));
+    differ.visit_output(&output)?;
+    match format {
+        Format::Text => {
+            for d in &differ.differences {
+                println!("{}", d);
+            }
+        }
+        Format::Json => println!("{}", serde_json::to_string_pretty(&differ.differences)?),
+    }
+    Ok(())
+}
+
+/// Asks a yes or no question on the terminal, returning `default` if the answer is empty.
+fn ask(question: &str, default: bool) -> Result<bool> {
+    eprint!("{} {} ", question, if default { "[Y/n]" } else { "[y/N]" });
+    std::io::stderr().flush()?;
+    let mut answer = String::new();
+    std::io::stdin().lock().read_line(&mut answer)?;
+    Ok(match answer.trim().to_lowercase().chars().next() {
+        Some('y') => true,
+        Some('n') => false,
+        _ => default,
+    })
+}
+
+/// Opens a single placeholder for the windows of `id` next to the focused window, runs `exec`
+/// if given, and waits for the window to take the place of the placeholder.
+///
+/// Fails if no such window shows up within `timeout` seconds.
+pub fn place(
+    id: &str,
+    exec: Option<&str>,
+    timeout: Option<u64>,
+    backend: Option<Backend>,
+) -> Result<(), Error> {
+    let _lock = lock::acquire(None, false)?;
+    let conn = connect()?;
+    let source = events::subscription(&[EventType::Window, EventType::Shutdown])?;
+    let placeholder = ClientHandle::new(
+        backend.unwrap_or_else(Backend::detect),
+        true,
+        Theme::default(),
+    );
+    place_window(
+        Box::new(conn),
+        Events::new(source),
+        placeholder,
+        id,
+        exec,
+        timeout,
+    )?;
+    Ok(())
+}
+
+/// Does what [`place`] does, through `conn`, `events` and `placeholder`.
+fn place_window(
+    mut conn: Box<dyn CommandRunner>,
+    events: Events,
+    placeholder: ClientHandle,
+    id: &str,
+    exec: Option<&str>,
+    timeout: Option<u64>,
+) -> Result<()> {
+    let focused = conn
+        .get_workspaces()?
+        .into_iter()
+        .find(|w| w.focused)
+        .context("no workspace is focused")?;
+    let policy = ErrorPolicy {
+        fail_fast: true,
+        strict_timeouts: true,
+    };
+    let mut builder = LayoutBuilder::new(conn, events, placeholder, Vec::new(), policy);
+    // The desktop file of the app names the placeholder, if there is one.
+    let title = gio::DesktopAppInfo::new(&format!("{id}.desktop"))
+        .map_or_else(|| id.to_owned(), |a| a.display_name().to_string());
+    let ctx = VisitContext {
+        output: &focused.output,
+        workspace: Some(&focused.name),
+        parent: None,
+        depth: 0,
+    };
+    let con_id = builder.open_placeholder(&title, id, &ctx)?;
+    let LayoutBuilder {
+        conn,
+        events,
+        placeholder,
+        mapping,
+        tokens,
+        filters,
+        ..
+    } = builder;
+    if let Some(exec) = exec {
+        let activation = tokens
+            .get(&con_id)
+            .and_then(|t| placeholder.activation_token(*t, ACTIVATION_TIMEOUT));
+        let mut cmd = std::process::Command::new("sh");
+        cmd.arg("-c").arg(exec);
+        if let Some(token) = activation {
+            cmd.env("XDG_ACTIVATION_TOKEN", token);
+        }
+        log::debug!("cmd: {:?}", cmd);
+        cmd.spawn().with_context(|| format!("running {:?}", exec))?;
+    }
+    let mut swapper = Swapper::new(
+        conn,
+        events,
+        mapping,
+        tokens,
+        filters,
+        Progress {
+            overlay: false,
+            waybar: false,
+            status: None,
+            service: None,
+        },
+        None,
+        notify::Notifier::from_env(),
+        policy,
+    );
+    swapper.swap(
+        Some(&placeholder),
+        Some(Duration::from_secs(timeout.unwrap_or(PLACE_TIMEOUT))),
+    )?;
+    placeholder.wait_until_idle(Duration::from_secs(IDLE_TIMEOUT))?;
+    Ok(())
+}
+
+/// Closes the placeholders left open, asking first unless `yes`, and sparing the ones of the
+/// running restore unless `all`.
+pub fn clean(yes: bool, all: bool) -> Result<(), Error> {
+    let mut conn = connect()?;
+    let running = if all { None } else { lock::current_holder()? };
+    let tree = conn.get_tree()?;
+    let mut stray = Vec::new();
+    for node in tree::iter_views(&tree, Order::Document, true).filter(|n| is_placeholder(n)) {
+        // i3 does not tell the pid of the windows, so a running restore spares them all.
+        let ours = node
+            .pid
+            .is_none_or(|pid| Some(i64::from(pid)) == running.map(i64::from));
+        if running.is_some() && ours {
+            log::debug!("placeholder {} belongs to the running restore", node.id);
+            continue;
+        }
+        let workspace = tree::find_workspace(&tree, node.id).and_then(|w| w.name.as_deref());
+        println!(
+            "{} {} on workspace {}",
+            node.id,
+            tree::app_id(node).unwrap_or_default(),
+            workspace.unwrap_or("scratchpad")
+        );
+        stray.push(node.id);
+    }
+    if stray.is_empty() {
+        return Ok(());
+    }
+    if !yes {
+        if !std::io::stdin().is_terminal() {
+            return Err(anyhow::anyhow!(
+                "not closing the placeholders without a terminal to ask, pass --yes"
+            )
+            .into());
+        }
+        if !ask(&format!("Close these {} placeholders?", stray.len()), false)? {
+            return Ok(());
+        }
+    }
+    for id in stray {
+        for res in conn.run_command(format!("[con_id={}] kill", id))? {
+            res?;
+        }
+    }
+    Ok(())
+}
+
+/// Prints the layout, or the windows of the current session, as an indented tree.
+pub fn show(
+    layout_file: Option<&Path>,
+    depth: Option<usize>,
+    width: Option<usize>,
+) -> Result<(), Error> {
+    let width = width.or_else(|| {
+        let columns = std::env::var("COLUMNS").ok()?;
+        std::io::stdout()
+            .is_terminal()
+            .then(|| columns.parse().ok())
+            .flatten()
+    });
+    let mut renderer = show::Renderer::new(depth, width);
+    match layout_file {
+        Some(path) => renderer.visit_output(&load_layout(path)?)?,
+        None => renderer.render_tree(&connect()?.get_tree()?),
+    }
+    for line in &renderer.lines {
+        println!("{}", line);
+    }
+    Ok(())
+}
+
+/// Keeps running, and restores the workspaces of the layouts as sway creates them, with the
+/// `defaults` of the config.
+pub fn daemon(
+    layout_files: &[PathBuf],
+    spawn: bool,
+    defaults: config::Restore,
+) -> Result<(), Error> {
+    let service = dbus::start(defaults.layout_dir.clone());
+    let handle = service.as_ref().map(|s| s.handle.clone());
+    daemon::run(layout_files, service, |job| {
+        let mut options = RestoreOptions {
+            layout_files: vec![job.layout_file.to_owned()],
+            workspaces: job.workspace.into_iter().map(str::to_owned).collect(),
+            spawn: spawn || job.spawn,
+            service: handle.clone(),
+            ..Default::default()
+        };
+        options.apply(defaults.clone())?;
+        restore(options).and_then(|(_, res)| res)
+    })?;
+    Ok(())
+}
+
+/// Goes through the swap of the session recorded in `dir` with `--record-session` again, against
+/// the recording instead of sway, and prints what became of each placeholder.
+///
+/// With `step`, waits for Enter before each event. The decisions are logged with `--debug`.
+pub fn replay(dir: &Path, step: bool) -> Result<(), Error> {
+    let replay = session::load(dir, step)?;
+    let placeholders: Vec<(String, i64)> = session::placeholders(&replay.mapping)
+        .into_iter()
+        .map(|(id, con_id)| (id.to_owned(), con_id))
+        .collect();
+    let claimed = replay
+        .claimed
+        .into_iter()
+        .map(|c| {
+            let view = DetachedView {
+                id: c.window,
+                matcher: c.matcher,
+                title: None,
+                role: None,
+                window_type: None,
+                pid: None,
+                marks: Vec::new(),
+                // Only the id, the floating state and the matcher are replayed.
+                shell: Shell::XdgShell,
+                transient: false,
+            };
+            (c.placeholder, view)
+        })
+        .collect();
+    let mut swapper = Swapper::new(
+        replay.runner,
+        Events::new(replay.source).with_backlog(replay.backlog),
+        replay.mapping,
+        HashMap::new(),
+        replay.filters,
+        Progress {
+            overlay: false,
+            waybar: false,
+            status: None,
+            service: None,
+        },
+        None,
+        notify::Notifier::from_env(),
+        ErrorPolicy {
+            fail_fast: true,
+            strict_timeouts: false,
+        },
+    );
+    swapper.first_match = replay.first_match;
+    // The deadline has passed by the time the recording says the swap ran out of time.
+    let timeout = replay.timed_out.then_some(Duration::ZERO);
+    let res = swapper
+        .adopt_detached(claimed)
+        .and_then(|()| swapper.swap(None, timeout));
+    for (id, con_id) in placeholders {
+        let outcome = match swapper.outcomes.get(&con_id) {
+            Some(report::Outcome::Filled { window }) => format!("filled by window {}", window),
+            Some(report::Outcome::TimedOut) => "timed out".to_owned(),
+            _ if swapper.mapping.values().flatten().any(|c| *c == con_id) => "waiting".to_owned(),
+            _ => "closed".to_owned(),
+        };
+        println!("{} (placeholder {}): {}", id, con_id, outcome);
+    }
+    match res.map_err(Error::from) {
+        Err(Error::CompositorGone(_)) => {
+            eprintln!("warning: the recording ends before the swap did");
+            Ok(())
+        }
+        res => res,
+    }
+}
+
+/// Restores the layout of `options.layout_files`, running the hooks and reporting the outcome as
+/// the options say.
+///
+/// Returns the summary of the restore along with how it ended, or the error that cut it short.
+pub fn restore(mut args: RestoreOptions) -> Result<(Summary, Result<(), Error>), Error> {
+    let notify = args.notify;
+    let waybar = args.waybar;
+    let mut hooks = hooks::Hooks::new(
+        std::mem::take(&mut args.pre_cmd),
+        std::mem::take(&mut args.post_cmd),
+        args.fatal_hooks,
+    );
+    let mut res = restore_layout(args, &mut hooks).map_err(Error::from);
+    if let Err(e) = hooks.post(&res) {
+        // The hook failing is what to report only if the restore itself went well.
+        match &mut res {
+            Ok((_, res @ Ok(()))) => *res = Err(e.into()),
+            _ => eprintln!("warning: {:#}", e),
+        }
+    }
+    if waybar {
+        waybar::print_outcome(&res);
+    }
+    if notify {
+        match &res {
+            Ok((summary, res)) => desktop::notify_restored(summary, res.is_ok()),
+            // The session is going away, with the notification server.
+            Err(Error::CompositorGone(_)) => {}
+            Err(e) => desktop::notify("Layout restore failed", &status::describe(e)),
+        }
+    }
+    res
+}
+
+/// Restores the layout, returning its summary along with how the restore ended, or the error
+/// that cut it short.
+fn restore_layout(
+    mut args: RestoreOptions,
+    hooks: &mut hooks::Hooks,
+) -> Result<(report::Summary, Result<(), Error>)> {
+    if args.waybar {
+        waybar::print("…", "Building the layout", "restoring");
+    }
+    let mut output = load_layouts(&args.layout_files)?;
+    if !args.include.is_empty()
+        || !args.exclude.is_empty()
+        || args.tiling_only
+        || args.floating_only
+    {
+        let mut filter = SlotFilter {
+            include: &args.include,
+            exclude: &args.exclude,
+            tiled: !args.floating_only,
+            floating: !args.tiling_only,
+        };
+        filter.visit_output(&mut output)?;
+        // The slots left take the room of the ones filtered out.
+        SizeNormalizer::default().visit_output(&mut output)?;
+    }
+    if !args.moves.is_empty() {
+        for m in moves::apply(&mut output, &args.moves) {
+            eprintln!("warning: --move {}: no app with this id in the layout", m);
+        }
+        SizeNormalizer::default().visit_output(&mut output)?;
+    }
+    if args.here {
+        output
+            .workspaces
+            .retain(|w| is_selected(&args.workspaces, w));
+        if output.workspaces.len() != 1 {
+            anyhow::bail!(
+                "--here builds a single workspace, but {} are selected: pick one with --workspace",
+                output.workspaces.len()
+            );
+        }
+    }
+    let mut exec = None;
+    if args.interactive {
+        let Some(selection) = tui::pick(&output, &args.workspaces, args.spawn)? else {
+            return Err(Cancelled.into());
+        };
+        let picked = selection.apply(&mut output, &args.workspaces)?;
+        SizeNormalizer::default().visit_output(&mut output)?;
+        args.spawn = picked.contains(&true);
+        exec = Some(picked);
+    }
+    let event_log = args
+        .event_log
+        .as_deref()
+        .map(|path| EventLog::open(path, args.no_titles))
+        .transpose()?;
+    let _lock = lock::acquire(args.wait_lock.map(Duration::from_secs), args.force)?;
+
+    if let Some(home) = dirs::home_dir() {
+        std::env::set_current_dir(home)?;
+    }
+    hooks.pre()?;
+
+    let policy = ErrorPolicy {
+        fail_fast: args.fail_fast,
+        strict_timeouts: args.strict_timeouts,
+    };
+    let mut sway = connect()?;
+    if !args.no_output_autodetect {
+        if let Some(name) = screen::resolve(&output, &sway.get_outputs()?) {
+            output.name = name;
+        }
+    }
+    let mut conn: Box<dyn CommandRunner> = Box::new(sway);
+    let mut source = events::subscription(&[EventType::Window, EventType::Shutdown])?;
+    let recorder = match &args.record_session {
+        Some(dir) => {
+            let recorder = session::Recorder::create(dir, &conn.get_tree()?, args.no_titles)?;
+            conn = recorder.runner(conn);
+            source = recorder.source(source);
+            Some(recorder)
+        }
+        None => None,
+    };
+    if args.here {
+        let focused = conn
+            .get_workspaces()?
+            .into_iter()
+            .find(|w| w.focused)
+            .context("no workspace is focused")?;
+        log::info!(
+            "building workspace {} on {}",
+            output.workspaces[0].name,
+            focused.name
+        );
+        output.workspaces[0].name = focused.name;
+        args.workspaces.clear();
+    }
+    let start = Instant::now();
+    // Returning early drops the placeholder client, which closes the placeholders.
+    let mut builder = LayoutBuilder::new(
+        conn,
+        Events::new(source),
+        ClientHandle::new(
+            args.backend.unwrap_or_else(Backend::detect),
+            !args.no_animation,
+            output.theme.clone(),
+        ),
+        args.workspaces.clone(),
+        policy,
+    );
+    builder.here = args.here;
+    {
+        let _span = tracing::info_span!("build").entered();
+        builder.visit_output(&output)?;
+    }
+    let notifier = notify::Notifier::from_env();
+    notifier.ready();
+    if args.detach {
+        detach::ready();
+    }
+
+    let LayoutBuilder {
+        conn,
+        events,
+        placeholder,
+        mapping,
+        tokens,
+        filters,
+        claimed,
+        failures,
+        mut slots,
+        created,
+        mut durations,
+        ..
+    } = builder;
+    let mut errors = failures.errors;
+    durations.build = start.elapsed();
+
+    let start = Instant::now();
+    if args.spawn {
+        let _span = tracing::info_span!("spawn").entered();
+        let mut spawner = Spawner {
+            placeholder: &placeholder,
+            workspaces: &args.workspaces,
+            mapping: &mapping,
+            tokens: &tokens,
+            claimed: &claimed,
+            exec: exec.as_deref(),
+            env: &args.env,
+            exec_via: args.exec_via.unwrap_or_default(),
+            slot_exec_via: None,
+            slot_env: Vec::new(),
+            floating_exec_via: VecDeque::new(),
+            launchers: HashMap::new(),
+            visited: 0,
+            spawned: HashMap::new(),
+            launched: HashSet::new(),
+            failures: Failures::new(policy),
+        };
+        spawner.visit_output(&output)?;
+        for slot in &mut slots {
+            slot.launched = slot
+                .placeholder
+                .is_some_and(|p| spawner.launched.contains(&p));
+        }
+        errors.extend(spawner.failures.errors);
+    }
+    durations.spawn = start.elapsed();
+    if let Some(recorder) = &recorder {
+        let claimed = claimed
+            .iter()
+            .map(|(con_id, view)| session::Claim {
+                placeholder: *con_id,
+                window: view.id,
+                matcher: view.matcher.clone(),
+            })
+            .collect();
+        recorder.swap(
+            &mapping,
+            &filters,
+            args.first_match,
+            claimed,
+            events.backlog(),
+        );
+    }
+    let mut swapper = Swapper::new(
+        conn,
+        events,
+        mapping,
+        tokens,
+        filters,
+        Progress {
+            overlay: !args.no_overlay,
+            waybar: args.waybar,
+            status: args.interactive.then(|| tui::Status::new(&slots)),
+            service: args.service.clone(),
+        },
+        event_log,
+        notifier,
+        policy,
+    );
+    swapper.first_match = args.first_match;
+    let start = Instant::now();
+    {
+        let _span = tracing::info_span!("swap").entered();
+        swapper.adopt_detached(claimed)?;
+        swapper.swap(
+            Some(&placeholder),
+            args.swap_timeout.map(Duration::from_secs),
+        )?;
+    }
+    durations.swap = start.elapsed();
+    errors.append(&mut swapper.failures.errors);
+    for slot in &mut slots {
+        let Some(p) = slot.placeholder else {
+            continue;
+        };
+        if let Some(outcome) = swapper.outcomes.remove(&p) {
+            slot.outcome = outcome;
+        }
+        if let (Some(created), Some(filled)) = (created.get(&p), swapper.filled.get(&p)) {
+            slot.wait = Some(filled.saturating_duration_since(*created));
+        }
+    }
+    if let Some(status) = &swapper.progress.status {
+        status.finish(&slots);
+    }
+
+    let start = Instant::now();
+    let stuck = {
+        let _span = tracing::info_span!("idle").entered();
+        placeholder.wait_until_idle(Duration::from_secs(
+            args.idle_timeout.unwrap_or(IDLE_TIMEOUT),
+        ))?
+    };
+    durations.idle = start.elapsed();
+    let res = ending(stuck.len(), errors.len(), &slots);
+    let sources: HashMap<&str, &Path> = output
+        .workspaces
+        .iter()
+        .filter_map(|w| Some((w.name.as_str(), w.source.as_deref()?)))
+        .collect();
+    for slot in &mut slots {
+        slot.layout_file = sources
+            .get(slot.workspace.as_str())
+            .map(|p| p.to_path_buf());
+    }
+    let status = res.as_ref().err().map_or(0, status::code);
+    let summary = report::Summary::new(status, slots, errors, durations);
+    if args.timings {
+        eprint!("{}", summary.timings());
+    }
+    if let Format::Json = args.format {
+        println!("{}", serde_json::to_string(&summary)?);
+    }
+    Ok((summary, res))
+}
+
+/// How a restore that got to the end went, with `stuck` placeholders destroyed, `failed` steps
+/// skipped, and `slots` filled or not.
+fn ending(stuck: usize, failed: usize, slots: &[report::Slot]) -> Result<(), Error> {
+    let unmatched = slots
+        .iter()
+        .filter(|s| {
+            matches!(
+                s.outcome,
+                report::Outcome::Closed | report::Outcome::TimedOut
+            )
+        })
+        .count();
+    if stuck > 0 {
+        Err(anyhow::anyhow!(
+            "{} placeholders did not close and had to be destroyed",
+            stuck
+        )
+        .into())
+    } else if failed > 0 {
+        Err(Error::Incomplete(Incomplete { failed }))
+    } else if unmatched > 0 {
+        Err(Error::Unmatched(Unmatched { slots: unmatched }))
+    } else {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use events::mock::{window, MockEvents};
+    use ipc::mock::{node, workspace, MockRunner};
+    use std::sync::{Arc, Mutex};
+
+    /// A layout file, with the sizes of its slots made up as when it is loaded.
+    fn output(layout: serde_json::Value) -> Output {
+        let mut output: Output = serde_json::from_value(layout).unwrap();
+        SizeNormalizer::default().visit_output(&mut output).unwrap();
+        output
+    }
+
+    /// A tiled window of `app_id`.
+    fn view(id: i64, app_id: &str) -> Node {
+        node(json!({ "id": id, "app_id": app_id }))
+    }
+
+    /// A tree with the windows `views` on the workspace `1`, focused in order.
+    fn tree(views: Vec<Node>) -> Node {
+        let focus: Vec<i64> = views.iter().map(|n| n.id).collect();
+        let workspace = node(json!({
+            "id": 2,
+            "type": "workspace",
+            "name": "1",
+            "nodes": views,
+            "focus": focus,
+        }));
+        node(json!({ "id": 1, "type": "root", "nodes": [workspace], "focus": [2] }))
+    }
+
+    /// A tree where the focused window is `id`, `width` pixels wide.
+    fn focused(id: i64, width: i32) -> Node {
+        let rect = json!({ "x": 0, "y": 0, "width": width, "height": 1080 });
+        tree(vec![node(
+            json!({ "id": id, "focused": true, "rect": rect }),
+        )])
+    }
+
+    /// The events of the placeholders of the slots `ids` showing up and taking focus, as the
+    /// windows `first`, `first + 1`...
+    fn placeholders(ids: &[&str], first: i64) -> Vec<Event> {
+        let mut events = Vec::new();
+        for (con_id, id) in (first..).zip(ids) {
+            let placeholder = view(con_id, &format!("swaystart-{}", id));
+            events.push(window(WindowChange::New, &placeholder));
+            events.push(window(WindowChange::Focus, &placeholder));
+        }
+        events
+    }
+
+    fn policy(fail_fast: bool) -> ErrorPolicy {
+        ErrorPolicy {
+            fail_fast,
+            strict_timeouts: false,
+        }
+    }
+
+    /// A builder sending its commands to `runner`, with `events` coming from sway, and the
+    /// workspace `2` focused to begin with.
+    fn builder(mut runner: MockRunner, events: Vec<Event>, fail_fast: bool) -> LayoutBuilder {
+        runner.workspaces = vec![workspace("2", true)];
+        let mut builder = LayoutBuilder::new(
+            Box::new(runner),
+            Events::new(Box::new(MockEvents::new(events))),
+            ClientHandle::mock(),
+            Vec::new(),
+            policy(fail_fast),
+        );
+        builder.app_name = |app| Ok(app.to_owned());
+        builder
+    }
+
+    /// A swapper sending its commands to `runner`, filling the placeholders of `mapping` with
+    /// the windows of `events`.
+    fn swapper(
+        runner: MockRunner,
+        events: Vec<Event>,
+        mapping: &[(&str, &[i64])],
+        filters: HashMap<i64, WindowFilter>,
+    ) -> Swapper {
+        let mapping = mapping
+            .iter()
+            .map(|(id, v)| (id.to_string(), v.to_vec()))
+            .collect();
+        Swapper::new(
+            Box::new(runner),
+            Events::new(Box::new(MockEvents::new(events))),
+            mapping,
+            HashMap::new(),
+            filters,
+            Progress {
+                overlay: false,
+                waybar: false,
+                status: None,
+                service: None,
+            },
+            None,
+            notify::Notifier::from_env(),
+            policy(true),
+        )
+    }
+
+    fn taken(commands: &Arc<Mutex<Vec<String>>>) -> Vec<String> {
+        std::mem::take(&mut commands.lock().unwrap())
+    }
+
+    #[test]
+    fn builds_split_layout() {
+        let layout = output(json!({
+            "name": "OUT",
+            "workspaces": [{
+                "name": "1",
+                "style": "splith",
+                "layout": {
+                    "style": "splith",
+                    "slots": [
+                        { "size": 0.25, "content": "foot" },
+                        { "size": 0.75, "content": "firefox" },
+                    ],
+                },
+            }],
+        }));
+        let runner = MockRunner {
+            // The workspace is empty, then each slot is focused in turn from the last one.
+            trees: vec![tree(Vec::new()), focused(11, 960), focused(10, 960)].into(),
+            ..Default::default()
+        };
+        let commands = runner.commands.clone();
+        let mut builder = builder(runner, placeholders(&["foot", "firefox"], 10), true);
+        builder.visit_output(&layout).unwrap();
+        assert_eq!(
+            taken(&commands),
+            [
+                "focus output OUT",
+                "workspace 1",
+                "layout splith",
+                "splith",
+                "layout splith",
+                "focus prev sibling",
+                "focus prev sibling",
+                "[con_id=10] focus; resize set width 480 px",
+                "[con_id=11] focus; resize set width 1440 px",
+                "focus parent",
+                "[con_id=10] focus",
+                "workspace 2",
+            ]
+        );
+        assert_eq!(builder.mapping["foot"], [10]);
+        assert_eq!(builder.mapping["firefox"], [11]);
+    }
+
+    /// The layout of `builds_split_layout`, sized without a tree to find the slots in.
+    fn build_without_resize_tree(fail_fast: bool) -> (Result<()>, Vec<String>, Vec<String>) {
+        let layout = output(json!({
+            "name": "OUT",
+            "workspaces": [{
+                "name": "1",
+                "style": "splith",
+                "layout": {
+                    "style": "splith",
+                    "slots": [{ "content": "foot" }, { "content": "firefox" }],
+                },
+            }],
+        }));
+        let runner = MockRunner {
+            trees: vec![tree(Vec::new())].into(),
+            ..Default::default()
+        };
+        let commands = runner.commands.clone();
+        let mut builder = builder(runner, placeholders(&["foot", "firefox"], 10), fail_fast);
+        let res = builder.visit_output(&layout);
+        (res, taken(&commands), builder.failures.errors)
+    }
+
+    #[test]
+    fn failed_resize_is_skipped() {
+        let (res, commands, errors) = build_without_resize_tree(false);
+        res.unwrap();
+        assert_eq!(errors.len(), 1);
+        assert!(errors[0].contains("getting the tree to size the slots"));
+        // The rest of the workspace is built all the same.
+        assert_eq!(
+            commands[commands.len() - 2..],
+            ["[con_id=10] focus", "workspace 2"]
+        );
+    }
+
+    #[test]
+    fn failed_resize_stops_with_fail_fast() {
+        let (res, commands, errors) = build_without_resize_tree(true);
+        let e = res.unwrap_err();
+        assert!(format!("{:#}", e).contains("getting the tree to size the slots"));
+        assert!(errors.is_empty());
+        assert_eq!(commands.last().unwrap(), "layout splith");
+    }
+
+    #[test]
+    fn builds_tabbed_layout_with_floating_app() {
+        let layout = output(json!({
+            "name": "OUT",
+            "workspaces": [{
+                "name": "mail",
+                "style": "tabbed",
+                "layout": {
+                    "style": "tabbed",
+                    "slots": [
+                        { "content": "thunderbird" },
+                        { "content": { "app": "foot", "id": "aerc" } },
+                    ],
+                },
+                "floating": ["pavucontrol"],
+            }],
+        }));
+        let runner = MockRunner {
+            trees: vec![tree(Vec::new())].into(),
+            ..Default::default()
+        };
+        let commands = runner.commands.clone();
+        let events = placeholders(&["thunderbird", "aerc", "pavucontrol"], 10);
+        let mut builder = builder(runner, events, true);
+        builder.visit_output(&layout).unwrap();
+        assert_eq!(
+            taken(&commands),
+            [
+                "focus output OUT",
+                "workspace mail",
+                "layout tabbed",
+                "splith",
+                "layout tabbed",
+                "focus parent",
+                "[con_id=12] floating enable",
+                "[con_id=10] focus",
+                "workspace 2",
+            ]
+        );
+        assert_eq!(builder.mapping["aerc"], [11]);
+    }
+
+    #[test]
+    fn swaps_windows_in_as_they_open() {
+        let runner = MockRunner {
+            trees: vec![tree(Vec::new())].into(),
+            ..Default::default()
+        };
+        let commands = runner.commands.clone();
+        let events = vec![
+            window(WindowChange::New, &view(20, "foot")),
+            window(WindowChange::New, &view(21, "mpv")),
+            window(WindowChange::New, &view(22, "firefox")),
+        ];
+        let mapping: &[(&str, &[i64])] = &[("foot", &[10]), ("firefox", &[11])];
+        let mut swapper = swapper(runner, events, mapping, HashMap::new());
+        swapper.swap(None, None).unwrap();
+        assert_eq!(
+            taken(&commands),
+            [
+                "[con_id=10] swap container with con_id 20",
+                "[con_id=10] kill",
+                "[con_id=21] floating enable",
+                "[con_id=11] swap container with con_id 22",
+                "[con_id=11] kill",
+            ]
+        );
+    }
+
+    #[test]
+    fn adopts_a_window_that_opens_while_a_workspace_is_built() {
+        // Tabbed, for the slots to need no sizing.
+        let slot = |app| json!({ "style": "tabbed", "slots": [{ "content": app }] });
+        let layout = output(json!({
+            "name": "OUT",
+            "workspaces": [
+                { "name": "1", "style": "splith", "layout": slot("foot") },
+                { "name": "2", "style": "splith", "layout": slot("firefox") },
+            ],
+        }));
+        let runner = MockRunner {
+            trees: vec![tree(Vec::new()), tree(Vec::new()), tree(Vec::new())].into(),
+            ..Default::default()
+        };
+        let commands = runner.commands.clone();
+        // foot starts up while the placeholder of workspace 2 is awaited.
+        let mut events = placeholders(&["foot"], 10);
+        events.push(window(WindowChange::New, &view(30, "foot")));
+        events.extend(placeholders(&["firefox"], 11));
+        events.push(window(WindowChange::New, &view(31, "firefox")));
+        let mut builder = builder(runner, events, true);
+        builder.visit_output(&layout).unwrap();
+        assert_eq!(builder.events.backlog().count(), 1);
+        taken(&commands);
+
+        let LayoutBuilder {
+            conn,
+            events,
+            mapping,
+            tokens,
+            filters,
+            ..
+        } = builder;
+        let mut swapper = Swapper::new(
+            conn,
+            events,
+            mapping,
+            tokens,
+            filters,
+            Progress {
+                overlay: false,
+                waybar: false,
+                status: None,
+                service: None,
+            },
+            None,
+            notify::Notifier::from_env(),
+            policy(true),
+        );
+        swapper.swap(None, None).unwrap();
+        assert_eq!(
+            taken(&commands),
+            [
+                "[con_id=10] swap container with con_id 30",
+                "[con_id=10] kill",
+                "[con_id=11] swap container with con_id 31",
+                "[con_id=11] kill",
+            ]
+        );
+    }
+
+    #[test]
+    fn detaches_nested_and_floating_windows() {
+        let split = node(json!({
+            "id": 5,
+            "layout": "splitv",
+            "nodes": [view(20, "foot"), view(21, "foot")],
+        }));
+        let floating = node(json!({ "id": 22, "type": "floating_con", "app_id": "firefox" }));
+        let workspace = node(json!({
+            "id": 2,
+            "type": "workspace",
+            "name": "1",
+            "nodes": [split],
+            "floating_nodes": [floating],
+        }));
+        let runner = MockRunner {
+            trees: vec![node(
+                json!({ "id": 1, "type": "root", "nodes": [workspace] }),
+            )]
+            .into(),
+            ..Default::default()
+        };
+        let commands = runner.commands.clone();
+        let layout = output(json!({
+            "name": "OUT",
+            "workspaces": [{
+                "name": "1",
+                "style": "tabbed",
+                "layout": { "style": "tabbed", "slots": [{ "content": "firefox" }] },
+            }],
+        }));
+        let mut builder = builder(runner, placeholders(&["firefox"], 10), true);
+        builder.visit_output(&layout).unwrap();
+        let commands = taken(&commands);
+        assert_eq!(
+            commands[2..4],
+            ["[con_id=20] floating enable", "[con_id=21] floating enable"]
+        );
+        assert!(!commands.iter().any(|c| c.contains("con_id=22")));
+        let detached: Vec<i64> = builder.detached.iter().map(|n| n.id).collect();
+        assert_eq!(detached, [20, 21]);
+        // The floating window takes the place of the placeholder of its slot.
+        assert_eq!(builder.claimed[&10].id, 22);
+    }
+
+    #[test]
+    fn keeps_what_the_swap_needs_of_detached_windows() {
+        let views: Vec<Node> = (0..500)
+            .map(|i| {
+                node(json!({
+                    "id": 100 + i,
+                    "name": format!("term {}", i),
+                    "pid": 1000 + i,
+                    "marks": [format!("m{}", i)],
+                    "window_properties": {
+                        "class": "URxvt",
+                        "window_role": "term",
+                        "window_type": "normal",
+                    },
+                }))
+            })
+            .collect();
+        let runner = MockRunner {
+            trees: vec![tree(views)].into(),
+            ..Default::default()
+        };
+        let commands = runner.commands.clone();
+        let mut builder = builder(runner, Vec::new(), true);
+        builder.detach("1", true).unwrap();
+        assert_eq!(taken(&commands).len(), 500);
+        assert_eq!(builder.detached.len(), 500);
+        let last = &builder.detached[499];
+        assert_eq!(last.id(), 599);
+        assert_eq!(last.matcher(), Some("URxvt"));
+        assert_eq!(last.title(), Some("term 499"));
+        assert_eq!(last.role(), Some("term"));
+        assert_eq!(last.window_type(), Some("normal"));
+        assert_eq!(last.pid(), Some(1499));
+        assert_eq!(last.marks(), ["m499"]);
+        assert_eq!(last.shell(), Shell::Xwayland);
+        assert!(last.is_floating() && !last.is_transient());
+    }
+
+    #[test]
+    fn detached_window_takes_the_place_of_its_placeholder() {
+        let runner = MockRunner {
+            trees: vec![tree(vec![view(10, "swaystart-foot")])].into(),
+            ..Default::default()
+        };
+        let commands = runner.commands.clone();
+        let mut swapper = swapper(runner, Vec::new(), &[("foot", &[10, 11])], HashMap::new());
+        let detached = DetachedView {
+            id: 20,
+            matcher: Some("foot".to_owned()),
+            title: None,
+            role: None,
+            window_type: None,
+            pid: None,
+            marks: Vec::new(),
+            shell: Shell::XdgShell,
+            transient: false,
+        };
+        swapper
+            .adopt_detached(vec![(10, detached)].into_iter().collect())
+            .unwrap();
+        // The placeholder is tiled, so the window floated out of the way is tiled again.
+        assert_eq!(
+            taken(&commands),
+            [
+                "[con_id=20] floating disable",
+                "[con_id=10] swap container with con_id 20",
+                "[con_id=10] kill",
+            ]
+        );
+        assert_eq!(swapper.mapping["foot"], [11]);
+    }
+
+    #[test]
+    fn walks_the_tree_with_floating_windows_last() {
+        let split = node(json!({
+            "id": 4,
+            "layout": "splitv",
+            "nodes": [view(5, "foot"), view(6, "foot")],
+            "focus": [6, 5],
+        }));
+        let floating = node(json!({ "id": 8, "type": "floating_con", "app_id": "mpv" }));
+        let workspace = node(json!({
+            "id": 3,
+            "type": "workspace",
+            "name": "1",
+            "nodes": [split, view(7, "firefox")],
+            "floating_nodes": [floating],
+            "focus": [8, 7, 4],
+        }));
+        let output =
+            node(json!({ "id": 2, "type": "output", "name": "OUT", "nodes": [workspace] }));
+        let root = node(json!({ "id": 1, "type": "root", "nodes": [output] }));
+
+        let walk = |order| -> Vec<(i64, Vec<i64>, Option<usize>)> {
+            tree::iter_nodes(&root, order)
+                .map(|(n, path, rank)| (n.id, path.iter().map(|a| a.id).collect(), rank))
+                .collect()
+        };
+        assert_eq!(
+            walk(Order::Document),
+            [
+                (1, vec![], None),
+                (2, vec![1], None),
+                (3, vec![1, 2], None),
+                (4, vec![1, 2, 3], Some(2)),
+                (5, vec![1, 2, 3, 4], Some(1)),
+                (6, vec![1, 2, 3, 4], Some(0)),
+                (7, vec![1, 2, 3], Some(1)),
+                (8, vec![1, 2, 3], Some(0)),
+            ]
+        );
+        let ids = |order| -> Vec<i64> { walk(order).into_iter().map(|(id, _, _)| id).collect() };
+        assert_eq!(ids(Order::Focus), [1, 2, 3, 8, 7, 4, 6, 5]);
+        let views: Vec<i64> = tree::iter_views(&root, Order::Document, false)
+            .map(|n| n.id)
+            .collect();
+        assert_eq!(views, [5, 6, 7, 8]);
+    }
+
+    #[test]
+    fn swaps_past_an_event_that_cant_be_decoded() {
+        let runner = MockRunner {
+            trees: vec![tree(Vec::new())].into(),
+            ..Default::default()
+        };
+        let commands = runner.commands.clone();
+        let poison = serde_json::from_str::<serde_json::Value>("{\"change\":").unwrap_err();
+        let stream = vec![
+            Err(swayipc::Error::SerdeJson(poison)),
+            Ok(window(WindowChange::New, &view(20, "foot"))),
+        ];
+        let mut swapper = swapper(runner, Vec::new(), &[("foot", &[10])], HashMap::new());
+        swapper.events = Events::new(events::mock::decoded(stream));
+        swapper.swap(None, None).unwrap();
+        assert_eq!(
+            taken(&commands),
+            [
+                "[con_id=10] swap container with con_id 20",
+                "[con_id=10] kill"
+            ]
+        );
+    }
+
+    /// The slots of the placeholders `ids`, with what `swapper` made of them.
+    fn slots(swapper: &mut Swapper, ids: &[i64]) -> Vec<report::Slot> {
+        ids.iter()
+            .map(|id| report::Slot {
+                workspace: "1".to_owned(),
+                id: id.to_string(),
+                layout_file: None,
+                placeholder: Some(*id),
+                launched: false,
+                outcome: swapper
+                    .outcomes
+                    .remove(id)
+                    .unwrap_or(report::Outcome::Closed),
+                wait: None,
+            })
+            .collect()
+    }
+
+    /// The exit status of a restore that ended with `res`.
+    fn status(res: Result<(), Error>) -> u8 {
+        res.as_ref().err().map_or(0, status::code)
+    }
+
+    #[test]
+    fn filled_slots_end_in_success() {
+        let runner = MockRunner {
+            trees: vec![tree(Vec::new())].into(),
+            ..Default::default()
+        };
+        let events = vec![window(WindowChange::New, &view(20, "foot"))];
+        let mut swapper = swapper(runner, events, &[("foot", &[10])], HashMap::new());
+        swapper.swap(None, Some(Duration::ZERO)).unwrap();
+        let slots = slots(&mut swapper, &[10]);
+        assert_eq!(status(ending(0, 0, &slots)), 0);
+    }
+
+    #[test]
+    fn unmatched_slots_have_their_own_status() {
+        let runner = MockRunner {
+            trees: vec![tree(Vec::new())].into(),
+            ..Default::default()
+        };
+        let events = vec![window(WindowChange::New, &view(20, "foot"))];
+        let mapping: &[(&str, &[i64])] = &[("foot", &[10]), ("firefox", &[11])];
+        let mut swapper = swapper(runner, events, mapping, HashMap::new());
+        // Running out of time is not an error of the swap itself.
+        swapper.swap(None, Some(Duration::ZERO)).unwrap();
+        let slots = slots(&mut swapper, &[10, 11]);
+        let res = ending(0, 0, &slots);
+        assert_eq!(
+            res.as_ref().unwrap_err().to_string(),
+            "1 slots were left without their window"
+        );
+        assert_eq!(status(res), 9);
+        // Failed steps and stuck placeholders are what a script has to hear about first.
+        assert_eq!(status(ending(0, 2, &slots)), 6);
+        assert_eq!(status(ending(1, 2, &slots)), 1);
+    }
+
+    #[test]
+    fn sway_going_away_has_its_own_status() {
+        // Without a deadline, running out of events is sway going away.
+        let runner = MockRunner {
+            trees: vec![tree(Vec::new())].into(),
+            ..Default::default()
+        };
+        let mut swapper = swapper(runner, Vec::new(), &[("foot", &[10])], HashMap::new());
+        let e = swapper.swap(None, None).unwrap_err();
+        assert_eq!(status(Err(e.into())), 3);
+    }
+
+    #[test]
+    fn refused_swap_is_a_failed_command() {
+        let mut runner = MockRunner {
+            trees: vec![tree(Vec::new())].into(),
+            ..Default::default()
+        };
+        runner.failing.insert(
+            "[con_id=10] swap container with con_id 20".to_owned(),
+            "no such container".to_owned(),
+        );
+        let events = vec![window(WindowChange::New, &view(20, "foot"))];
+        let mut swapper = swapper(runner, events, &[("foot", &[10])], HashMap::new());
+        let e = Error::from(swapper.swap(None, None).unwrap_err());
+        assert!(matches!(e, Error::CommandFailed(_)));
+        assert_eq!(status(Err(e)), 1);
+    }
+
+    #[test]
+    fn cancelled_restore_has_its_own_status() {
+        let e = Err::<(), _>(Cancelled)
+            .context("picking the slots")
+            .unwrap_err();
+        assert_eq!(status(Err(e.into())), 8);
+    }
+
+    /// Writes a layout of `output` with empty `workspaces` to a temporary file named after
+    /// `name`.
+    fn layout_file(name: &str, output: &str, workspaces: &[&str]) -> PathBuf {
+        let workspaces: Vec<_> = workspaces
+            .iter()
+            .map(|w| json!({ "name": w, "style": "splith", "layout": { "style": "splith", "slots": [] } }))
+            .collect();
+        let layout = json!({ "name": output, "workspaces": workspaces });
+        let path =
+            std::env::temp_dir().join(format!("swaystart-{}-{}.json", std::process::id(), name));
+        std::fs::write(&path, layout.to_string()).unwrap();
+        path
+    }
+
+    #[test]
+    fn loads_layouts_of_the_same_output_together() {
+        let first = layout_file("first", "OUT", &["1", "2"]);
+        let second = layout_file("second", "OUT", &["3"]);
+        let other = layout_file("other", "HDMI-A-1", &["4"]);
+        let again = layout_file("again", "OUT", &["2"]);
+
+        let output = load_layouts(&[first.clone(), second.clone()]).unwrap();
+        let workspaces: Vec<(&str, Option<&Path>)> = output
+            .workspaces
+            .iter()
+            .map(|w| (w.name.as_str(), w.source.as_deref()))
+            .collect();
+        assert_eq!(
+            workspaces,
+            [
+                ("1", Some(first.as_path())),
+                ("2", Some(first.as_path())),
+                ("3", Some(second.as_path())),
+            ]
+        );
+        let e = load_layouts(&[first.clone(), other.clone()]).unwrap_err();
+        assert!(matches!(&e, Error::InvalidLayout { path, .. } if *path == other));
+        assert!(status::describe(&e).ends_with(&format!(
+            "it is a layout of output HDMI-A-1, but {} is one of output OUT",
+            first.display()
+        )));
+        let e = load_layouts(&[first.clone(), again.clone()]).unwrap_err();
+        assert!(matches!(&e, Error::InvalidLayout { path, .. } if *path == again));
+        assert!(
+            status::describe(&e).ends_with(&format!("workspace 2 is also in {}", first.display()))
+        );
+        for path in [first, second, other, again] {
+            std::fs::remove_file(path).unwrap();
+        }
+    }
+
+    #[test]
+    fn refuses_slots_that_would_match_by_mistake() {
+        let load = |name, slot| {
+            let layout = json!({
+                "name": "OUT",
+                "workspaces": [{
+                    "name": "1",
+                    "style": "splith",
+                    "layout": {
+                        "style": "tabbed",
+                        "slots": [
+                            { "content": "foot" },
+                            { "content": { "style": "splitv", "slots": [slot] } },
+                        ],
+                    },
+                }],
+            });
+            let path = std::env::temp_dir().join(format!(
+                "swaystart-{}-{}.json",
+                std::process::id(),
+                name
+            ));
+            std::fs::write(&path, layout.to_string()).unwrap();
+            let e = load_layouts(std::slice::from_ref(&path)).unwrap_err();
+            std::fs::remove_file(&path).unwrap();
+            assert!(matches!(&e, Error::InvalidLayout { path: p, .. } if *p == path));
+            status::describe(&e)
+        };
+        let slot = "output \"OUT\": workspace \"1\": tabbed layout: slot 1: splitv layout: slot 0";
+        let e = load(
+            "empty-id",
+            json!({ "content": { "app": "foot", "id": "" } }),
+        );
+        assert!(e.contains(slot), "{}", e);
+        assert!(e.ends_with("the id \"\" is blank"), "{}", e);
+        let e = load(
+            "blank-id",
+            json!({ "content": { "app": "foot", "id": " \t" } }),
+        );
+        assert!(e.contains(slot), "{}", e);
+        assert!(e.ends_with("the id \" \\t\" is blank"), "{}", e);
+        let e = load("empty-exclude", json!({ "content": "foot", "exclude": {} }));
+        assert!(e.contains(slot), "{}", e);
+        assert!(
+            e.ends_with("exclude is empty, so it leaves out every window"),
+            "{}",
+            e
+        );
+    }
+
+    #[test]
+    fn checks_a_layout_against_the_session() {
+        let path = Path::new("work.json");
+        let session = || tree(vec![view(10, "foot")]);
+        let layout = |output_name| {
+            output(json!({
+                "name": output_name,
+                "workspaces": [{
+                    "name": "1",
+                    "style": "splith",
+                    "layout": { "style": "splith", "slots": [] },
+                }],
+            }))
+        };
+        // The workspace already has a window, which is only a warning.
+        let mut checker =
+            check::Checker::with_session(session(), vec!["OUT".to_owned()], true).unwrap();
+        checker.visit_output(&layout("OUT")).unwrap();
+        checker.finish(path).unwrap();
+
+        let mut checker =
+            check::Checker::with_session(session(), vec!["OUT".to_owned()], true).unwrap();
+        checker.visit_output(&layout("HDMI-A-1")).unwrap();
+        let e = checker.finish(path).unwrap_err();
+        assert!(matches!(&e, Error::InvalidLayout { .. }));
+        assert_eq!(
+            status::describe(&e),
+            "invalid layout work.json: the layout has 1 problems"
+        );
+        assert_eq!(status::code(&e), 5);
+    }
+
+    #[test]
+    fn options_take_what_the_config_leaves_to_them() {
+        let mut options = RestoreOptions {
+            layout_files: vec![PathBuf::from("work.json")],
+            swap_timeout: Some(5),
+            env: vec![("EDITOR".to_owned(), "vim".to_owned())],
+            ..Default::default()
+        };
+        let config = config::Restore {
+            spawn: true,
+            swap_timeout: Some(30),
+            idle_timeout: Some(2),
+            include: vec!["foot".to_owned()],
+            env: [("EDITOR", "nano"), ("PROJECT", "swaystart")]
+                .iter()
+                .map(|(k, v)| (k.to_string(), v.to_string()))
+                .collect(),
+            ..Default::default()
+        };
+        options.apply(config).unwrap();
+        assert!(options.spawn);
+        assert_eq!(options.swap_timeout, Some(5));
+        assert_eq!(options.idle_timeout, Some(2));
+        assert_eq!(options.include, ["foot"]);
+        assert_eq!(
+            options.env,
+            [
+                ("EDITOR".to_owned(), "vim".to_owned()),
+                ("PROJECT".to_owned(), "swaystart".to_owned()),
+            ]
+        );
+
+        let mut options = RestoreOptions {
+            detach: true,
+            waybar: true,
+            ..Default::default()
+        };
+        let e = options.apply(config::Restore::default()).unwrap_err();
+        assert!(matches!(e, Error::InvalidConfig(_)));
+        assert_eq!(status::code(&e), 2);
+    }
+
+    #[test]
+    fn places_a_window_next_to_the_focused_one() {
+        let runner = MockRunner {
+            workspaces: vec![workspace("3", true)],
+            trees: vec![tree(Vec::new())].into(),
+            ..Default::default()
+        };
+        let commands = runner.commands.clone();
+        let mut events = placeholders(&["foot"], 10);
+        events.push(window(WindowChange::New, &view(20, "foot")));
+        place_window(
+            Box::new(runner),
+            Events::new(Box::new(MockEvents::new(events))),
+            ClientHandle::mock(),
+            "foot",
+            None,
+            Some(1),
+        )
+        .unwrap();
+        assert_eq!(
+            taken(&commands),
+            [
+                "[con_id=10] swap container with con_id 20",
+                "[con_id=10] kill"
+            ]
+        );
+    }
+
+    /// Reads a layout file with the layouts nested `depth` deep.
+    fn read_nested(depth: usize) -> Result<Output> {
+        // Written out as text, as dropping a value that deep would take as deep a stack.
+        let mut layout = String::new();
+        for _ in 0..depth {
+            layout.push_str(r#"{"style":"splitv","slots":[{"content":"#);
+        }
+        layout.push_str(r#""foot""#);
+        layout.push_str(&"}]}".repeat(depth));
+        let text = format!(
+            r#"{{"name":"OUT","workspaces":[{{"name":"1","style":"splitv","layout":{}}}]}}"#,
+            layout
+        );
+        let path = std::env::temp_dir().join(format!(
+            "swaystart-nested-{}-{}.json",
+            std::process::id(),
+            depth
+        ));
+        std::fs::write(&path, text).unwrap();
+        let res = read_layout(&path, false);
+        std::fs::remove_file(&path).unwrap();
+        res
+    }
+
+    #[test]
+    fn reads_layouts_up_to_the_depth_limit() {
+        read_nested(MAX_LAYOUT_DEPTH).unwrap();
+        let e = read_nested(MAX_LAYOUT_DEPTH + 1).unwrap_err();
+        assert_eq!(
+            e.to_string(),
+            "layouts are nested 33 deep, at most 32 are supported"
+        );
+    }
+
+    #[test]
+    fn refuses_very_deep_layouts() {
+        let e = read_nested(10_000).unwrap_err();
+        assert!(
+            format!("{:#}", e).contains("layouts are nested too deep, at most 32 are supported"),
+            "{:#}",
+            e
+        );
+    }
+
+    /// Two workspaces, the first one with a nested layout and a floating app, none of them
+    /// with sizes to give.
+    fn nested() -> serde_json::Value {
+        json!({
+            "name": "OUT",
+            "workspaces": [
+                {
+                    "name": "1",
+                    "style": "tabbed",
+                    "layout": {
+                        "style": "tabbed",
+                        "slots": [
+                            { "content": "foot" },
+                            { "content": { "style": "tabbed", "slots": [
+                                { "content": "firefox" },
+                                { "content": { "app": "foot", "id": "aerc" } },
+                            ] } },
+                        ],
+                    },
+                    "floating": ["pavucontrol"],
+                },
+                {
+                    "name": "2",
+                    "style": "tabbed",
+                    "layout": { "style": "tabbed", "slots": [{ "content": "mpv" }] },
+                },
+            ],
+        })
+    }
+
+    /// The callbacks on [`nested`], in order.
+    const NESTED_CALLS: &[&str] = &[
+        "output OUT",
+        "workspace 1",
+        "slot foot",
+        "enter tabbed",
+        "slot tabbed",
+        "slot firefox",
+        "enter tabbed",
+        "slot aerc",
+        "exit tabbed",
+        "exit tabbed",
+        "floating pavucontrol",
+        "exit workspace 1",
+        "workspace 2",
+        "slot mpv",
+        "enter tabbed",
+        "exit tabbed",
+        "exit workspace 2",
+        "exit output OUT",
+    ];
+
+    /// The id of the app of `slot`, or the style of its layout.
+    fn describe(slot: &Slot) -> String {
+        match &slot.content {
+            SlotContent::Container(c) => c.style.to_string(),
+            SlotContent::App(id) | SlotContent::AppWithId { id, .. } => id.clone(),
+        }
+    }
+
+    /// What a recorder answers to the callbacks in `answers`, `Continue` to the others.
+    fn answer(answers: &HashMap<&str, VisitAction>, call: &str) -> VisitAction {
+        answers.get(call).copied().unwrap_or(VisitAction::Continue)
+    }
+
+    /// Records the callbacks of the traversal in order, and where each app sits.
+    #[derive(Default)]
+    struct Recorder {
+        calls: Vec<String>,
+        places: Vec<String>,
+        answers: HashMap<&'static str, VisitAction>,
+    }
+
+    impl LayoutVisitor for Recorder {
+        fn on_output(&mut self, output: &Output, _ctx: &VisitContext) -> Result<VisitAction> {
+            self.calls.push(format!("output {}", output.name));
+            Ok(VisitAction::Continue)
+        }
+        fn on_output_exit(&mut self, output: &Output, _ctx: &VisitContext) -> Result<()> {
+            self.calls.push(format!("exit output {}", output.name));
+            Ok(())
+        }
+        fn on_workspace(
+            &mut self,
+            workspace: &Workspace,
+            _ctx: &VisitContext,
+        ) -> Result<VisitAction> {
+            let call = format!("workspace {}", workspace.name);
+            let action = answer(&self.answers, &call);
+            self.calls.push(call);
+            Ok(action)
+        }
+        fn on_workspace_exit(&mut self, workspace: &Workspace, _ctx: &VisitContext) -> Result<()> {
+            self.calls
+                .push(format!("exit workspace {}", workspace.name));
+            Ok(())
+        }
+        fn on_layout_enter(&mut self, layout: &Layout, _ctx: &VisitContext) -> Result<()> {
+            self.calls.push(format!("enter {}", layout.style));
+            Ok(())
+        }
+        fn on_layout_exit(&mut self, layout: &Layout, _ctx: &VisitContext) -> Result<()> {
+            self.calls.push(format!("exit {}", layout.style));
+            Ok(())
+        }
+        fn on_slot(&mut self, slot: &Slot, _ctx: &VisitContext) -> Result<VisitAction> {
+            let call = format!("slot {}", describe(slot));
+            let action = answer(&self.answers, &call);
+            self.calls.push(call);
+            Ok(action)
+        }
+        fn on_app(&mut self, _app: &str, id: &str, ctx: &VisitContext) -> Result<()> {
+            let parent = ctx.parent.map(|p| p.to_string());
+            self.places.push(format!(
+                "{} on {}/{} in {} {}",
+                id,
+                ctx.output,
+                ctx.workspace.unwrap_or_default(),
+                parent.as_deref().unwrap_or("nothing"),
+                ctx.depth
+            ));
+            Ok(())
+        }
+        fn on_floating_app(&mut self, _app: &str, id: &str, ctx: &VisitContext) -> Result<()> {
+            self.calls.push(format!("floating {}", id));
+            self.places.push(format!(
+                "{} floating on {}/{} {}",
+                id,
+                ctx.output,
+                ctx.workspace.unwrap_or_default(),
+                ctx.depth
+            ));
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn callbacks_know_where_the_node_sits() {
+        let mut recorder = Recorder::default();
+        recorder.visit_output(&output(nested())).unwrap();
+        assert_eq!(
+            recorder.places,
+            [
+                "foot on OUT/1 in tabbed 1",
+                "firefox on OUT/1 in tabbed 2",
+                "aerc on OUT/1 in tabbed 2",
+                "pavucontrol floating on OUT/1 0",
+                "mpv on OUT/2 in tabbed 1",
+            ]
+        );
+        assert_eq!(recorder.calls, NESTED_CALLS);
+    }
+
+    #[test]
+    fn builder_reports_the_workspace_of_each_slot() {
+        let runner = MockRunner {
+            trees: vec![tree(Vec::new()), tree(Vec::new())].into(),
+            ..Default::default()
+        };
+        let events = placeholders(&["foot", "firefox", "aerc", "pavucontrol", "mpv"], 10);
+        let mut builder = builder(runner, events, true);
+        builder.visit_output(&output(nested())).unwrap();
+        let slots: Vec<(&str, &str)> = builder
+            .slots
+            .iter()
+            .map(|s| (s.workspace.as_str(), s.id.as_str()))
+            .collect();
+        assert_eq!(
+            slots,
+            [
+                ("1", "foot"),
+                ("1", "firefox"),
+                ("1", "aerc"),
+                ("1", "pavucontrol"),
+                ("2", "mpv"),
+            ]
+        );
+    }
+
+    /// Records the callbacks of the traversal like [`Recorder`], and with `rewrite`, turns the
+    /// slot `mpv` into a layout with `htop`, which gets `top` on entering and `late` on exiting.
+    #[derive(Default)]
+    struct MutRecorder {
+        calls: Vec<String>,
+        answers: HashMap<&'static str, VisitAction>,
+        rewrite: bool,
+    }
+
+    impl MutRecorder {
+        fn rewrites(layout: &Layout) -> bool {
+            layout.slots.first().is_some_and(|s| describe(s) == "htop")
+        }
+    }
+
+    fn app_slot(id: &str) -> Slot {
+        serde_json::from_value(json!({ "content": id })).unwrap()
+    }
+
+    impl LayoutVisitorMut for MutRecorder {
+        fn on_output(&mut self, output: &mut Output) -> Result<VisitAction> {
+            self.calls.push(format!("output {}", output.name));
+            Ok(VisitAction::Continue)
+        }
+        fn on_output_exit(&mut self, output: &mut Output) -> Result<()> {
+            self.calls.push(format!("exit output {}", output.name));
+            Ok(())
+        }
+        fn on_workspace(&mut self, workspace: &mut Workspace) -> Result<VisitAction> {
+            let call = format!("workspace {}", workspace.name);
+            let action = answer(&self.answers, &call);
+            self.calls.push(call);
+            Ok(action)
+        }
+        fn on_workspace_exit(&mut self, workspace: &mut Workspace) -> Result<()> {
+            self.calls
+                .push(format!("exit workspace {}", workspace.name));
+            Ok(())
+        }
+        fn on_layout_enter(&mut self, layout: &mut Layout) -> Result<()> {
+            self.calls.push(format!("enter {}", layout.style));
+            if self.rewrite && Self::rewrites(layout) {
+                layout.slots.push(app_slot("top"));
+            }
+            Ok(())
+        }
+        fn on_layout_exit(&mut self, layout: &mut Layout) -> Result<()> {
+            self.calls.push(format!("exit {}", layout.style));
+            if self.rewrite && Self::rewrites(layout) {
+                layout.slots.push(app_slot("late"));
+            }
+            Ok(())
+        }
+        fn on_slot(&mut self, slot: &mut Slot) -> Result<VisitAction> {
+            let call = format!("slot {}", describe(slot));
+            let action = answer(&self.answers, &call);
+            self.calls.push(call);
+            if self.rewrite && describe(slot) == "mpv" {
+                let layout = json!({ "style": "tabbed", "slots": [{ "content": "htop" }] });
+                slot.content = SlotContent::Container(serde_json::from_value(layout).unwrap());
+            }
+            Ok(action)
+        }
+        fn on_floating_app(&mut self, app: &mut FloatingApp) -> Result<()> {
+            let id = match app {
+                FloatingApp::App(id) | FloatingApp::AppWithId { id, .. } => id,
+            };
+            self.calls.push(format!("floating {}", id));
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn mutable_visitor_walks_in_the_same_order() {
+        let mut recorder = MutRecorder::default();
+        recorder.visit_output(&mut output(nested())).unwrap();
+        assert_eq!(recorder.calls, NESTED_CALLS);
+    }
+
+    #[test]
+    fn mutable_visitor_visits_what_is_added_before_the_children() {
+        let mut recorder = MutRecorder {
+            rewrite: true,
+            ..Default::default()
+        };
+        let mut output = output(nested());
+        recorder.visit_output(&mut output).unwrap();
+        let second = recorder
+            .calls
+            .iter()
+            .position(|c| c == "workspace 2")
+            .unwrap();
+        // The layout the slot became is visited, and so is the slot added on entering it, but
+        // not the one added on exiting it.
+        assert_eq!(
+            recorder.calls[second..],
+            [
+                "workspace 2",
+                "slot mpv",
+                "slot htop",
+                "enter tabbed",
+                "slot top",
+                "exit tabbed",
+                "enter tabbed",
+                "exit tabbed",
+                "exit workspace 2",
+                "exit output OUT",
+            ]
+        );
+        let SlotContent::Container(ref layout) = output.workspaces[1].layout.slots[0].content
+        else {
+            panic!("the slot is still an app");
+        };
+        let slots: Vec<String> = layout.slots.iter().map(describe).collect();
+        assert_eq!(slots, ["htop", "top", "late"]);
+    }
+
+    /// The callbacks both visitors make on [`nested`], answering `answers`.
+    fn answered(answers: &[(&'static str, VisitAction)]) -> Vec<String> {
+        let answers: HashMap<_, _> = answers.iter().copied().collect();
+        let mut recorder = Recorder {
+            answers: answers.clone(),
+            ..Default::default()
+        };
+        recorder.visit_output(&output(nested())).unwrap();
+        let mut mut_recorder = MutRecorder {
+            answers,
+            ..Default::default()
+        };
+        mut_recorder.visit_output(&mut output(nested())).unwrap();
+        assert_eq!(recorder.calls, mut_recorder.calls);
+        recorder.calls
+    }
+
+    #[test]
+    fn visitors_skip_the_children_they_are_told_to() {
+        let calls = answered(&[
+            ("slot tabbed", VisitAction::SkipChildren),
+            ("workspace 2", VisitAction::SkipChildren),
+        ]);
+        assert_eq!(
+            calls,
+            [
+                "output OUT",
+                "workspace 1",
+                "slot foot",
+                "enter tabbed",
+                "slot tabbed",
+                "exit tabbed",
+                "floating pavucontrol",
+                "exit workspace 1",
+                "workspace 2",
+                "exit output OUT",
+            ]
+        );
+    }
+
+    #[test]
+    fn visitors_stop_where_they_are_told_to() {
+        let calls = answered(&[("slot aerc", VisitAction::Stop)]);
+        let aerc = NESTED_CALLS.iter().position(|c| *c == "slot aerc").unwrap();
+        assert_eq!(calls, NESTED_CALLS[..=aerc]);
+        // On a workspace, it ends the traversal too, rather than skipping it.
+        let calls = answered(&[("workspace 1", VisitAction::Stop)]);
+        assert_eq!(calls, ["output OUT", "workspace 1"]);
+    }
+
+    #[test]
+    fn builder_skips_the_workspaces_left_out() {
+        let runner = MockRunner {
+            trees: vec![tree(Vec::new())].into(),
+            ..Default::default()
+        };
+        let commands = runner.commands.clone();
+        let mut builder = builder(runner, placeholders(&["mpv"], 10), true);
+        builder.workspaces = vec!["2".to_owned()];
+        builder.visit_output(&output(nested())).unwrap();
+        let slots: Vec<&str> = builder.slots.iter().map(|s| s.id.as_str()).collect();
+        assert_eq!(slots, ["mpv"]);
+        assert!(!taken(&commands).iter().any(|c| c == "workspace 1"));
+    }
+
+    /// The sizes of `slots`, given as sizes or `null` for none, once normalized.
+    fn normalized(sizes: &[Option<f64>], strict: bool) -> Result<Vec<f64>> {
+        let slots: Vec<_> = sizes
+            .iter()
+            .map(|size| match size {
+                Some(size) => json!({ "size": size, "content": "foot" }),
+                None => json!({ "content": "foot" }),
+            })
+            .collect();
+        let mut output: Output = serde_json::from_value(json!({
+            "name": "OUT",
+            "workspaces": [{
+                "name": "1",
+                "style": "splith",
+                "layout": { "style": "splith", "slots": slots },
+            }],
+        }))
+        .unwrap();
+        SizeNormalizer { strict }.visit_output(&mut output)?;
+        let sizes = output.workspaces[0].layout.slots.iter().map(|s| s.size);
+        // Rounded, to compare them whatever the floating point errors.
+        Ok(sizes.map(|s| (s * 1000.).round() / 1000.).collect())
+    }
+
+    #[test]
+    fn missing_sizes_share_what_is_left() {
+        assert_eq!(
+            normalized(&[None, None, None, None], false).unwrap(),
+            [0.25; 4]
+        );
+        assert_eq!(
+            normalized(&[Some(0.5), None, None], false).unwrap(),
+            [0.5, 0.25, 0.25]
+        );
+        // With nothing left, they count as 1 and everything is scaled down.
+        assert_eq!(
+            normalized(&[Some(1.5), Some(1.5), None], false).unwrap(),
+            [0.375, 0.375, 0.25]
+        );
+    }
+
+    #[test]
+    fn sizes_are_scaled_to_add_up_to_1() {
+        assert_eq!(
+            normalized(&[Some(2.), Some(6.)], false).unwrap(),
+            [0.25, 0.75]
+        );
+        assert_eq!(
+            normalized(&[Some(0.2), Some(0.2)], false).unwrap(),
+            [0.5, 0.5]
+        );
+    }
+
+    #[test]
+    fn strict_sizes_must_be_given_and_add_up_to_1() {
+        let e = normalized(&[Some(0.5), None], true).unwrap_err();
+        assert_eq!(
+            e.root_cause().to_string(),
+            "1 slots of a splith layout have no size"
+        );
+        let e = normalized(&[Some(0.5), Some(0.25)], true).unwrap_err();
+        assert_eq!(
+            e.root_cause().to_string(),
+            "the slot sizes of a splith layout add up to 0.75, not 1"
+        );
+        assert_eq!(
+            normalized(&[Some(0.5), Some(0.5)], true).unwrap(),
+            [0.5, 0.5]
+        );
+    }
+
+    #[test]
+    fn sizes_must_be_positive() {
+        let e = normalized(&[Some(0.), None], false).unwrap_err();
+        assert_eq!(
+            e.root_cause().to_string(),
+            "slot size must be a positive number, got 0"
+        );
+        assert!(normalized(&[Some(-0.5), Some(1.5)], false).is_err());
+    }
+
+    /// A window to match filters against, counting the lookups of its process.
+    #[derive(Default)]
+    struct Target {
+        title: Option<&'static str>,
+        role: Option<&'static str>,
+        marks: Vec<String>,
+        pid_lookups: std::cell::Cell<usize>,
+    }
+
+    impl MatchTarget for Target {
+        fn id(&self) -> i64 {
+            1
+        }
+        fn is_floating(&self) -> bool {
+            false
+        }
+        fn matcher(&self) -> Option<&str> {
+            Some("foot")
+        }
+        fn title(&self) -> Option<&str> {
+            self.title
+        }
+        fn role(&self) -> Option<&str> {
+            self.role
+        }
+        fn window_type(&self) -> Option<&str> {
+            None
+        }
+        fn pid(&self) -> Option<i32> {
+            self.pid_lookups.set(self.pid_lookups.get() + 1);
+            Some(std::process::id() as i32)
+        }
+        fn marks(&self) -> &[String] {
+            &self.marks
+        }
+        fn shell(&self) -> Shell {
+            Shell::XdgShell
+        }
+        fn is_transient(&self) -> bool {
+            false
+        }
+    }
+
+    fn filter(fields: serde_json::Value) -> WindowFilter {
+        serde_json::from_value(fields).unwrap()
+    }
+
+    #[test]
+    fn filter_needs_all_its_fields() {
+        let f = filter(json!({ "title_contains": "htop", "window_role": "term", "mark": "top" }));
+        let target = Target {
+            title: Some("htop - foot"),
+            role: Some("term"),
+            marks: vec!["top".to_owned()],
+            ..Default::default()
+        };
+        assert!(f.fits(&target));
+        let other_role = Target {
+            role: Some("other"),
+            ..target
+        };
+        assert!(!f.fits(&other_role));
+        let no_mark = Target {
+            marks: Vec::new(),
+            ..other_role
+        };
+        assert!(!f.fits(&no_mark));
+        // A window without a title can't have the title asked for.
+        assert!(!filter(json!({ "title_contains": "htop" })).fits(&Target::default()));
+        assert!(WindowFilter::default().fits(&Target::default()));
+    }
+
+    #[test]
+    fn filter_reads_the_command_line_last() {
+        let f = filter(json!({ "title_contains": "htop", "cmdline_contains": "swaystart" }));
+        let target = Target {
+            title: Some("vim"),
+            ..Default::default()
+        };
+        assert!(!f.fits(&target));
+        assert_eq!(target.pid_lookups.get(), 0);
+        let target = Target {
+            title: Some("htop"),
+            ..Default::default()
+        };
+        // The command line of the test itself.
+        assert!(f.fits(&target));
+        assert_eq!(target.pid_lookups.get(), 1);
+        let f = filter(json!({ "cmdline_contains": "no such command line" }));
+        assert!(!f.fits(&target));
+    }
+
+    #[test]
+    fn filter_leaves_out_what_it_excludes() {
+        let f = filter(json!({ "exclude": { "title_contains": "Private" } }));
+        let private = Target {
+            title: Some("Private Browsing"),
+            ..Default::default()
+        };
+        assert!(!f.fits(&private));
+        let public = Target {
+            title: Some("Mozilla Firefox"),
+            ..Default::default()
+        };
+        assert!(f.fits(&public));
+        // Without a title, the excluded title can't be there.
+        assert!(f.fits(&Target::default()));
+    }
+
+    #[test]
+    fn filter_finds_text_in_the_titles_of_windows() {
+        let window = |title: &str| node(json!({ "id": 20, "app_id": "foot", "name": title }));
+        let f = filter(json!({ "title_contains": "nvim" }));
+        assert!(f.fits(&window("nvim ~/projects/foo \u{2014} foot")));
+        assert!(f.fits(&window("nvim")));
+        assert!(!f.fits(&window("htop \u{2014} foot")));
+        // Case matters, as it does in sway's criteria.
+        assert!(!f.fits(&window("NVIM")));
+        assert!(!f.fits(&node(json!({ "id": 20, "app_id": "foot" }))));
+        // Combined with an excluded title, both must hold.
+        let f =
+            filter(json!({ "title_contains": "nvim", "exclude": { "title_contains": "scratch" } }));
+        assert!(f.fits(&window("nvim notes")));
+        assert!(!f.fits(&window("nvim scratch")));
+    }
+
+    /// Which of the placeholders `waiting` of the slot `foot`, with their `filters`, a new foot
+    /// window titled `title` takes, as with `--first-match` if `first_match`.
+    fn take(
+        waiting: &[i64],
+        filters: Vec<(i64, serde_json::Value)>,
+        title: &str,
+        first_match: bool,
+    ) -> Option<i64> {
+        let filters = filters.into_iter().map(|(c, f)| (c, filter(f))).collect();
+        let mut swapper = swapper(
+            MockRunner::default(),
+            Vec::new(),
+            &[("foot", waiting)],
+            filters,
+        );
+        swapper.first_match = first_match;
+        let window = node(json!({ "id": 20, "app_id": "foot", "name": title }));
+        swapper.take_waiting(&window, |_| true)
+    }
+
+    #[test]
+    fn highest_priority_takes_the_window() {
+        // Among equals, the last placeholder of the slot gets the window.
+        assert_eq!(take(&[10, 11], Vec::new(), "htop", false), Some(11));
+        let filters = vec![(10, json!({ "priority": 1 }))];
+        assert_eq!(take(&[10, 11], filters, "htop", false), Some(10));
+        // Even over a more specific slot.
+        let filters = vec![
+            (10, json!({ "priority": 1 })),
+            (11, json!({ "title_contains": "htop" })),
+        ];
+        assert_eq!(take(&[10, 11], filters, "htop", false), Some(10));
+        // But not if the window does not fit it.
+        let filters = vec![(10, json!({ "priority": 5, "title_contains": "vim" }))];
+        assert_eq!(take(&[10, 11], filters, "htop", false), Some(11));
+        let filters = vec![(11, json!({ "priority": -1 }))];
+        assert_eq!(take(&[10, 11], filters, "htop", false), Some(10));
+    }
+
+    #[test]
+    fn most_specific_slot_takes_the_window() {
+        let filters = || {
+            vec![
+                (10, json!({ "title_contains": "htop" })),
+                (11, json!({ "window_role": "term" })),
+            ]
+        };
+        // The title counts the most, and 12 asks for nothing.
+        let windows = [10, 11, 12];
+        assert_eq!(take(&windows, filters(), "htop", false), Some(10));
+        assert_eq!(take(&windows, filters(), "vim", false), Some(12));
+        // With --first-match, the order alone decides.
+        assert_eq!(take(&windows, filters(), "htop", true), Some(12));
+    }
+
+    #[test]
+    fn closest_title_takes_the_window() {
+        let fuzzy = |value| json!({ "title_fuzzy": { "value": value, "threshold": 0.3 } });
+        let filters = || {
+            vec![
+                (10, fuzzy("Inbox - Mozilla Thunderbird")),
+                (11, fuzzy("Calendar - Mozilla Thunderbird")),
+            ]
+        };
+        let title = "Inbox (3) - Mozilla Thunderbird";
+        assert_eq!(take(&[10, 11], filters(), title, false), Some(10));
+        let title = "Calendar - Mozilla Thunderbird";
+        assert_eq!(take(&[10, 11], filters(), title, false), Some(11));
+        // --first-match only leaves out the specificity, the closest title still wins.
+        let title = "Inbox (3) - Mozilla Thunderbird";
+        assert_eq!(take(&[10, 11], filters(), title, true), Some(10));
+    }
+
+    #[test]
+    fn transient_windows_only_fill_slots_that_allow_them() {
+        let dialog = node(json!({
+            "id": 21,
+            "type": "floating_con",
+            "window_properties": { "class": "firefox", "transient_for": 20 },
+        }));
+        let main = node(json!({ "id": 20, "window_properties": { "class": "firefox" } }));
+        assert!(dialog.is_transient());
+        assert!(!main.is_transient());
+        let f = WindowFilter::default();
+        assert!(!f.fits(&dialog));
+        assert!(f.fits(&main));
+        assert!(filter(json!({ "allow_transient": true })).fits(&dialog));
+
+        // Nor does a transient window kept from the workspace.
+        let detached = DetachedView {
+            id: 21,
+            matcher: Some("firefox".to_owned()),
+            title: None,
+            role: None,
+            window_type: None,
+            pid: None,
+            marks: Vec::new(),
+            shell: Shell::Xwayland,
+            transient: true,
+        };
+        assert!(!f.fits(&detached));
+        assert!(filter(json!({ "allow_transient": true })).fits(&detached));
+
+        // A waiting slot lets it go by, unless it allows it.
+        let take = |filters| {
+            let mut swapper = swapper(
+                MockRunner::default(),
+                Vec::new(),
+                &[("firefox", &[10])],
+                filters,
+            );
+            swapper.take_waiting(&dialog, |_| true)
+        };
+        assert_eq!(take(HashMap::new()), None);
+        let allowed = vec![(10, filter(json!({ "allow_transient": true })))];
+        let allowed = allowed.into_iter().collect();
+        assert_eq!(take(allowed), Some(10));
+    }
+}
@@ -0,0 +1,169 @@
+use crate::status::Locked;
+use anyhow::{Context, Result};
+use std::fs::{File, OpenOptions, TryLockError};
+use std::io::{Read, Seek, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+/// How often a locked lock is tried again while waiting for it.
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Held while restoring, so that two restores in the same sway session don't fight over the
+/// placeholders.
+///
+/// The lock is an advisory lock on a file, released when this is dropped or the process dies.
+#[derive(Debug)]
+pub struct InstanceLock {
+    _file: File,
+}
+
+/// The lock file for the sway session, named after its IPC socket.
+fn lock_path() -> PathBuf {
+    let session = std::env::var_os("SWAYSOCK")
+        .map(PathBuf::from)
+        .and_then(|s| s.file_name().map(|n| n.to_owned()));
+    let mut name = std::ffi::OsString::from("swaystart");
+    if let Some(session) = session {
+        name.push(".");
+        name.push(session);
+    }
+    name.push(".lock");
+    dirs::runtime_dir()
+        .unwrap_or_else(std::env::temp_dir)
+        .join(name)
+}
+
+fn open(path: &Path) -> Result<File> {
+    OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create(true)
+        .truncate(false)
+        .open(path)
+        .with_context(|| format!("opening the lock file {}", path.display()))
+}
+
+/// The pid recorded in the lock file by its holder.
+fn holder(file: &mut File) -> Option<u32> {
+    let mut pid = String::new();
+    file.rewind().ok()?;
+    file.read_to_string(&mut pid).ok()?;
+    pid.trim().parse().ok()
+}
+
+fn is_alive(pid: u32) -> bool {
+    std::path::Path::new(&format!("/proc/{}", pid)).exists()
+}
+
+/// The pid of the swaystart holding the lock, if one does.
+pub fn current_holder() -> Result<Option<u32>> {
+    holder_of(&lock_path())
+}
+
+/// The pid of the swaystart holding the lock file `path`, if one does.
+fn holder_of(path: &Path) -> Result<Option<u32>> {
+    if !path.exists() {
+        return Ok(None);
+    }
+    let mut file = open(path)?;
+    match file.try_lock() {
+        // Dropping the file releases the lock right away.
+        Ok(()) => Ok(None),
+        Err(TryLockError::WouldBlock) => Ok(holder(&mut file)),
+        Err(TryLockError::Error(e)) => {
+            Err(e).with_context(|| format!("locking {}", path.display()))
+        }
+    }
+}
+
+/// Takes the lock, waiting up to `wait` for another instance to release it.
+///
+/// With `force`, a lock whose recorded holder is dead is taken over, as happens when the lock
+/// file was inherited by a process that outlived swaystart.
+pub fn acquire(wait: Option<Duration>, force: bool) -> Result<InstanceLock> {
+    acquire_at(&lock_path(), wait, force)
+}
+
+/// Takes the lock file `path`, as [`acquire`] does.
+fn acquire_at(path: &Path, wait: Option<Duration>, force: bool) -> Result<InstanceLock> {
+    let mut file = open(path)?;
+    let deadline = wait.map(|w| Instant::now() + w);
+    loop {
+        match file.try_lock() {
+            Ok(()) => break,
+            Err(TryLockError::WouldBlock) => {}
+            Err(TryLockError::Error(e)) => {
+                return Err(e).with_context(|| format!("locking {}", path.display()))
+            }
+        }
+        let pid = holder(&mut file);
+        if force && pid.is_some_and(|p| !is_alive(p)) {
+            log::warn!(
+                "taking over the lock of dead process {}",
+                pid.unwrap_or_default()
+            );
+            // The holder keeps its lock on the old file, which nobody can open anymore.
+            std::fs::remove_file(path)
+                .with_context(|| format!("removing the lock file {}", path.display()))?;
+            file = open(path)?;
+            continue;
+        }
+        if deadline.is_none_or(|d| Instant::now() >= d) {
+            return Err(Locked { pid }.into());
+        }
+        std::thread::sleep(POLL_INTERVAL);
+    }
+    file.set_len(0)?;
+    file.rewind()?;
+    write!(file, "{}", std::process::id())?;
+    Ok(InstanceLock { _file: file })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A lock file named after `name` in a directory of its own.
+    fn lock_file(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("swaystart-{}-{}", std::process::id(), name));
+        std::fs::create_dir_all(&dir).unwrap();
+        dir.join("swaystart.lock")
+    }
+
+    #[test]
+    fn second_acquire_is_locked_out() {
+        let path = lock_file("locked");
+        let lock = acquire_at(&path, None, false).unwrap();
+        assert_eq!(holder_of(&path).unwrap(), Some(std::process::id()));
+        let e = acquire_at(&path, Some(Duration::from_millis(150)), false).unwrap_err();
+        let locked = e.downcast_ref::<Locked>().expect("a Locked error");
+        assert_eq!(locked.pid, Some(std::process::id()));
+        // A live holder is not taken over, even with force.
+        assert!(acquire_at(&path, None, true).is_err());
+        drop(lock);
+        assert_eq!(holder_of(&path).unwrap(), None);
+        acquire_at(&path, None, false).unwrap();
+        std::fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn force_takes_over_from_a_dead_holder() {
+        let path = lock_file("stale");
+        let stale = acquire_at(&path, None, false).unwrap();
+        // As if the lock had been inherited from a swaystart that died since.
+        let dead = u32::MAX;
+        std::fs::write(&path, dead.to_string()).unwrap();
+        let e = acquire_at(&path, None, false).unwrap_err();
+        assert_eq!(e.downcast_ref::<Locked>().unwrap().pid, Some(dead));
+
+        let _lock = acquire_at(&path, None, true).unwrap();
+        assert_eq!(
+            std::fs::read_to_string(&path).unwrap(),
+            std::process::id().to_string()
+        );
+        drop(stale);
+        // The lock is on the new file, which the stale holder never had.
+        assert_eq!(holder_of(&path).unwrap(), Some(std::process::id()));
+        std::fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+}
@@ -0,0 +1,92 @@
+use anyhow::{Context, Result};
+use log::LevelFilter;
+use std::fs::{File, OpenOptions};
+use std::path::Path;
+use std::sync::Mutex;
+use tracing_log::AsTrace;
+use tracing_subscriber::fmt::format::FmtSpan;
+use tracing_subscriber::prelude::*;
+
+/// The most detailed records written to the trace file.
+pub const TRACE_LEVEL: LevelFilter = LevelFilter::Debug;
+
+/// Where the log goes besides stderr, and how much of it.
+pub struct LogFile<'a> {
+    pub path: &'a Path,
+    pub level: LevelFilter,
+    /// Whether to empty the file first rather than append to it.
+    pub truncate: bool,
+}
+
+fn open(path: &Path, truncate: bool, what: &str) -> Result<File> {
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)
+            .with_context(|| format!("creating the directory {}", dir.display()))?;
+    }
+    OpenOptions::new()
+        .create(true)
+        .write(true)
+        .append(!truncate)
+        .truncate(truncate)
+        .open(path)
+        .with_context(|| format!("opening the {} {}", what, path.display()))
+}
+
+/// Sets up logging to stderr, at debug level if `debug` is set, to `log_file` if given, and to
+/// `trace_file` if given, as JSON lines recording the spans along with the records up to
+/// [`TRACE_LEVEL`].
+///
+/// The records of the `log` macros are passed on to `tracing`, and logged within its spans like
+/// the others. A file that can't be opened is reported on stderr and left out.
+pub fn init(debug: bool, log_file: Option<LogFile>, trace_file: Option<&Path>) {
+    let stderr_level = if debug {
+        LevelFilter::Debug
+    } else {
+        LevelFilter::Error
+    };
+    let mut max_level = stderr_level;
+    let stderr = tracing_subscriber::fmt::layer()
+        .with_writer(std::io::stderr)
+        .without_time()
+        .with_filter(stderr_level.as_trace());
+    let file =
+        log_file.and_then(
+            |log_file| match open(log_file.path, log_file.truncate, "log file") {
+                Ok(file) => {
+                    max_level = max_level.max(log_file.level);
+                    let layer = tracing_subscriber::fmt::layer()
+                        .with_writer(Mutex::new(file))
+                        .with_ansi(false)
+                        .with_filter(log_file.level.as_trace());
+                    Some(layer)
+                }
+                Err(e) => {
+                    eprintln!("warning: {:#}, logging to stderr only", e);
+                    None
+                }
+            },
+        );
+    let trace = trace_file.and_then(|path| match open(path, true, "trace file") {
+        Ok(file) => {
+            max_level = max_level.max(TRACE_LEVEL);
+            let layer = tracing_subscriber::fmt::layer()
+                .json()
+                .with_span_list(true)
+                .with_span_events(FmtSpan::NEW | FmtSpan::CLOSE)
+                .with_writer(Mutex::new(file))
+                .with_filter(TRACE_LEVEL.as_trace());
+            Some(layer)
+        }
+        Err(e) => {
+            eprintln!("warning: {:#}, not writing the trace", e);
+            None
+        }
+    });
+    let subscriber = tracing_subscriber::registry()
+        .with(stderr)
+        .with(file)
+        .with(trace);
+    if tracing::subscriber::set_global_default(subscriber).is_ok() {
+        let _ = tracing_log::LogTracer::init_with_filter(max_level);
+    }
+}
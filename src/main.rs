@@ -1,414 +1,267 @@
 use anyhow::Result;
-use clap::Parser;
-use gio::prelude::*;
-use placeholder::ClientHandle;
-use serde::Deserialize;
-use std::{collections::HashMap, path::PathBuf};
-use swayipc::{Connection, Event, EventStream, EventType, Node, WindowChange};
+use clap::{Parser, Subcommand};
+use std::{ffi::OsString, path::PathBuf, process::ExitCode};
+use swaystart::{config, logging, status, Format, RestoreOptions};
 
-mod placeholder;
-
-fn wait_new_window(events: &mut EventStream, app_id: &str) -> Result<Node> {
-    log::debug!("wait for window:");
-    while let Some(event) = events.next() {
-        match event? {
-            Event::Window(w) => match w.change {
-                WindowChange::New if w.container.app_id.as_deref() == Some(app_id) => {
-                    log::debug!(
-                        "new window id={} app_id={:?}",
-                        w.container.id,
-                        w.container.app_id
-                    );
-                    return Ok(w.container);
-                }
-                _ => {}
-            },
-            _ => {}
-        }
-    }
-    anyhow::bail!("Event stream ended");
+#[derive(Parser, Debug)]
+#[command(author, version, about, long_about = None, after_help = status::HELP)]
+struct Args {
+    #[arg(short, long, default_value = "false", global = true)]
+    debug: bool,
+    /// Read the defaults of the options from this file instead of
+    /// $XDG_CONFIG_HOME/swaystart/config.toml
+    #[arg(long, global = true)]
+    config: Option<PathBuf>,
+    /// Also write the log to this file, with timestamps
+    #[arg(long, global = true)]
+    log_file: Option<PathBuf>,
+    /// How much of the log goes to the file: error, warn, info, debug or trace [default: debug]
+    #[arg(long, global = true)]
+    log_level: Option<log::LevelFilter>,
+    /// Empty the log file first instead of appending to it
+    #[arg(long, default_value = "false", global = true)]
+    log_truncate: bool,
+    /// Write the spans of the run and the log records in them to this file, as JSON lines
+    #[arg(long, global = true)]
+    trace_file: Option<PathBuf>,
+    #[command(subcommand)]
+    command: Command,
 }
 
-fn wait_window_focus(events: &mut EventStream, id: i64) -> Result<Node> {
-    while let Some(event) = events.next() {
-        match event? {
-            Event::Window(w) => {
-                if w.container.id != id {
-                    continue;
-                }
-                match w.change {
-                    WindowChange::Focus => {
-                        log::debug!(
-                            "focus window id={} app_id={:?}",
-                            w.container.id,
-                            w.container.app_id
-                        );
-                        return Ok(w.container);
-                    }
-                    _ => {}
-                }
-            }
-            _ => {}
-        }
-    }
-    anyhow::bail!("Event stream ended");
+// Parsed once, the size of the restore options does not matter.
+#[allow(clippy::large_enum_variant)]
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Build the layout with placeholders and swap the windows in as they appear
+    Restore(RestoreOptions),
+    /// Check that a layout can be restored in the current session, without changing anything
+    Check(CheckArgs),
+    /// Show how the current session differs from a layout
+    Diff(DiffArgs),
+    /// Edit a layout in $VISUAL or $EDITOR, only saving it once it is valid
+    Edit(EditArgs),
+    /// Close the placeholders left open, by a restore that got stuck for instance
+    Clean(CleanArgs),
+    /// Print a layout, or the windows of the current session, as an indented tree
+    Show(ShowArgs),
+    /// Keep running, and restore the workspaces of the layouts as sway creates them
+    ///
+    /// The workspace is restored with the options of the [restore] table of the config, and
+    /// only if it has no windows.
+    Daemon(DaemonArgs),
+    /// Restore a layout in a headless sway of its own, and check the result
+    ///
+    /// Skipped, successfully, when sway or its headless backend is not available.
+    Selftest,
+    /// Go through the swap of a session recorded with `restore --record-session` again, without
+    /// sway, and print what became of each placeholder
+    Replay(ReplayArgs),
+    /// Open a single placeholder next to the focused window, and swap in the next window of the
+    /// app
+    Place(PlaceArgs),
 }
 
-fn spawn(app: &str) -> Result<()> {
-    log::debug!("spawn: '{}'", app);
-    let app = gio::DesktopAppInfo::new(app).ok_or_else(|| anyhow::anyhow!("no app: {app}"))?;
-    let ctx = gio::AppLaunchContext::new();
-    log::debug!("env: {:?}", ctx.environment());
-    app.launch_uris(&[], Some(&ctx))?;
-    Ok(())
+#[derive(clap::Args, Debug)]
+struct CheckArgs {
+    /// The layout file, `-` to read it from stdin
+    #[arg(short, long)]
+    layout_file: PathBuf,
+    /// Also fail on slots without a size, on slot sizes of a layout that don't add up to 1, and
+    /// on nodes of the session of a type unknown to swaystart
+    #[arg(long, default_value = "false")]
+    strict: bool,
 }
 
-#[derive(Debug, Deserialize)]
-struct Output {
-    name: String,
-    workspaces: Vec<Workspace>,
-}
-#[derive(Debug, Deserialize)]
-struct Workspace {
-    name: String,
-    style: LayoutStyle,
-    layout: Layout,
-}
-#[derive(Debug, Deserialize)]
-struct Layout {
-    style: LayoutStyle,
-    slots: Vec<Slot>,
-}
-#[derive(Debug, Deserialize)]
-#[serde(rename_all = "lowercase")]
-enum LayoutStyle {
-    Tabbed,
-    Splitv,
-    Splith,
-}
-impl std::fmt::Display for LayoutStyle {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        let style = match self {
-            LayoutStyle::Tabbed => "tabbed",
-            LayoutStyle::Splitv => "splitv",
-            LayoutStyle::Splith => "splith",
-        };
-        write!(f, "{}", style)
-    }
+#[derive(clap::Args, Debug)]
+struct EditArgs {
+    /// The layout file
+    #[arg(short, long)]
+    layout_file: PathBuf,
 }
 
-const fn f64_one() -> f64 {
-    1.
-}
-#[derive(Debug, Deserialize)]
-struct Slot {
-    #[serde(default = "f64_one")]
-    size: f64,
-    content: SlotContent,
-}
-#[derive(Debug, Deserialize)]
-#[serde(untagged)]
-enum SlotContent {
-    Container(Layout),
-    App(String),
-    AppWithId { app: String, id: String },
+#[derive(clap::Args, Debug)]
+struct DaemonArgs {
+    /// A layout file whose workspaces are restored, can be repeated
+    #[arg(short, long = "layout-file")]
+    layout_files: Vec<PathBuf>,
+    #[arg(short, long, default_value = "false")]
+    spawn: bool,
 }
 
-trait LayoutVisitor {
-    fn visit_output(&mut self, output: &Output) -> Result<()> {
-        self.on_output(output)?;
-        for w in &output.workspaces {
-            self.visit_workspace(w)?;
-        }
-        Ok(())
-    }
-    fn visit_workspace(&mut self, workspace: &Workspace) -> Result<()> {
-        self.on_workspace(workspace)?;
-        self.visit_layout(&workspace.layout)?;
-        Ok(())
-    }
-    fn visit_layout(&mut self, layout: &Layout) -> Result<()> {
-        if let [first, rest @ ..] = layout.slots.as_slice() {
-            self.visit_slot(first)?;
-            self.on_layout_enter(layout)?;
-            for s in rest {
-                self.visit_slot(s)?;
-            }
-            self.on_layout_exit(layout)?;
+impl DaemonArgs {
+    /// Takes the options missing from the command line from the `[daemon]` table of the config.
+    fn apply(&mut self, config: config::Daemon) {
+        if self.layout_files.is_empty() {
+            self.layout_files = config.layout_files;
         }
-        Ok(())
-    }
-    fn visit_slot(&mut self, slot: &Slot) -> Result<()> {
-        self.on_slot(slot)?;
-        match slot.content {
-            SlotContent::Container(ref c) => {
-                self.visit_layout(c)?;
-            }
-            SlotContent::App(ref a) => {
-                self.visit_app(a, a)?;
-            }
-            SlotContent::AppWithId { ref app, ref id } => {
-                self.visit_app(&app, &id)?;
-            }
-        }
-        Ok(())
-    }
-    fn visit_app(&mut self, app: &str, id: &str) -> Result<()> {
-        self.on_app(app, id)?;
-        Ok(())
-    }
-    fn on_slot(&mut self, _slot: &Slot) -> Result<()> {
-        Ok(())
-    }
-    fn on_app(&mut self, _app: &str, _id: &str) -> Result<()> {
-        Ok(())
-    }
-    fn on_layout_enter(&mut self, _layout: &Layout) -> Result<()> {
-        Ok(())
-    }
-    fn on_layout_exit(&mut self, _layout: &Layout) -> Result<()> {
-        Ok(())
-    }
-    fn on_workspace(&mut self, _workspace: &Workspace) -> Result<()> {
-        Ok(())
-    }
-    fn on_output(&mut self, _output: &Output) -> Result<()> {
-        Ok(())
+        self.spawn |= config.spawn;
     }
 }
 
-struct LayoutBuilder {
-    conn: Connection,
-    events: EventStream,
-    placeholder: placeholder::ClientHandle,
-    mapping: HashMap<String, Vec<i64>>,
-}
-
-impl LayoutBuilder {
-    fn new() -> Result<LayoutBuilder> {
-        let builder = LayoutBuilder {
-            conn: Connection::new()?,
-            events: Connection::new()?.subscribe(&[EventType::Window])?,
-            placeholder: ClientHandle::new(),
-            mapping: HashMap::new(),
-        };
-        Ok(builder)
-    }
-    fn run(&mut self, cmd: &str) -> Result<()> {
-        log::debug!("cmd: '{}'", cmd);
-        for res in self.conn.run_command(cmd)? {
-            res?;
-        }
-        Ok(())
-    }
-}
-impl LayoutVisitor for LayoutBuilder {
-    fn on_output(&mut self, output: &Output) -> Result<()> {
-        self.run(&format!("focus output {}", output.name))?;
-        Ok(())
-    }
-    fn on_workspace(&mut self, workspace: &Workspace) -> Result<()> {
-        self.run(&format!(
-            "workspace {}; layout {}",
-            workspace.name, workspace.style
-        ))?;
-        Ok(())
-    }
-    fn on_layout_enter(&mut self, layout: &Layout) -> Result<()> {
-        self.run("splith")?;
-        self.run(&format!("layout {}", layout.style))?;
-        Ok(())
-    }
-    fn on_layout_exit(&mut self, layout: &Layout) -> Result<()> {
-        let dim = match layout.style {
-            LayoutStyle::Splitv => "height",
-            LayoutStyle::Splith => "width",
-            LayoutStyle::Tabbed => {
-                self.run("focus parent")?;
-                return Ok(());
-            }
-        };
-        let denom = layout.slots.iter().fold(0., |acc, el| acc + el.size);
-        let mut nodes = Vec::new();
-        for s in layout.slots.iter().rev() {
-            let node = self
-                .conn
-                .get_tree()
-                .unwrap()
-                .find_focused(|n| n.focused)
-                .ok_or_else(|| anyhow::anyhow!("no focused window"))?;
-            let size_px = match layout.style {
-                LayoutStyle::Splitv => node.rect.height,
-                LayoutStyle::Splith => node.rect.width,
-                LayoutStyle::Tabbed => unreachable!(),
-            } as f64;
-            nodes.push((node.id, size_px, s.size));
-            self.run("focus prev sibling")?;
-        }
-        let tot_size_px = nodes.iter().fold(0., |acc, el| acc + el.1);
-        for n in nodes.iter().rev() {
-            let size_px = ((n.2 / denom) * tot_size_px) as i32;
-            self.run(&format!(
-                "[con_id={}] focus; resize set {} {} px",
-                n.0, dim, size_px
-            ))?;
-        }
-        self.run("focus parent")?;
-        Ok(())
-    }
-    fn on_app(&mut self, app: &str, id: &str) -> Result<()> {
-        let app_info = gio::DesktopAppInfo::new(&format!("{app}.desktop"))
-            .ok_or_else(|| anyhow::anyhow!("no app: {}", app))?;
-        let placeholder_app_id = format!("swaystart-{}", id);
-        self.placeholder
-            .new_window(app_info.display_name().as_str(), &placeholder_app_id);
-        let node = wait_new_window(&mut self.events, &placeholder_app_id)?;
-        self.mapping.entry(id.to_owned()).or_default().push(node.id);
-        wait_window_focus(&mut self.events, node.id)?;
-        Ok(())
-    }
+#[derive(clap::Args, Debug)]
+struct CleanArgs {
+    /// Don't ask before closing the placeholders
+    #[arg(short, long, default_value = "false")]
+    yes: bool,
+    /// Also close the placeholders of the restore running in the session, if any
+    #[arg(long, default_value = "false")]
+    all: bool,
 }
 
-struct Spawner {}
-impl LayoutVisitor for Spawner {
-    fn on_app(&mut self, app: &str, _id: &str) -> Result<()> {
-        spawn(&format!("{}.desktop", app))?;
-        Ok(())
-    }
+#[derive(clap::Args, Debug)]
+struct ReplayArgs {
+    /// The directory given to --record-session
+    dir: PathBuf,
+    /// Wait for Enter before each event
+    #[arg(long, default_value = "false")]
+    step: bool,
 }
 
-struct Swapper {
-    conn: Connection,
-    events: EventStream,
-    mapping: HashMap<String, Vec<i64>>,
+#[derive(clap::Args, Debug)]
+struct PlaceArgs {
+    /// The app_id of the window to wait for, or its class on i3
+    #[arg(long)]
+    app_id: String,
+    /// Run this shell command once the placeholder is open
+    #[arg(long)]
+    exec: Option<String>,
+    /// Seconds to wait for the window [default: 30]
+    #[arg(long)]
+    timeout: Option<u64>,
+    /// The window manager to open the placeholder in [default: the one of the session, from the
+    /// environment]
+    #[arg(long, value_enum)]
+    backend: Option<swaystart::Backend>,
 }
 
-impl Swapper {
-    fn new(mapping: HashMap<String, Vec<i64>>) -> Result<Self> {
-        let swapper = Swapper {
-            conn: Connection::new()?,
-            events: Connection::new()?.subscribe(&[EventType::Window])?,
-            mapping,
-        };
-        Ok(swapper)
-    }
-    fn run(&mut self, cmd: &str) -> Result<()> {
-        log::debug!("cmd: '{}'", cmd);
-        for res in self.conn.run_command(cmd)? {
-            res?;
-        }
-        Ok(())
-    }
-    fn swap(&mut self) -> Result<()> {
-        let mut count = 0;
-        for v in self.mapping.values() {
-            count += v.len();
-        }
-        while let Some(event) = self.events.next() {
-            log::debug!("{:?}", event);
-            match event? {
-                Event::Window(w) => match w.change {
-                    WindowChange::Close => {
-                        if let Some(app_id) = w.container.app_id.as_deref() {
-                            if let Some(id) = app_id.strip_prefix("swaystart-") {
-                                if let Some(v) = self.mapping.get_mut(id) {
-                                    let idx = v.iter().position(|i| *i == w.container.id);
-                                    if let Some(idx) = idx {
-                                        v.swap_remove(idx);
-                                        count -= 1;
-                                        if count == 0 {
-                                            break;
-                                        }
-                                    }
-                                }
-                            }
-                        }
-                    }
-                    WindowChange::New => {
-                        println!("{:?}", w);
-                        let matcher = if w
-                            .container
-                            .window_properties
-                            .as_ref()
-                            .is_some_and(|p| p.window_type.as_deref() != Some("normal"))
-                        {
-                            None
-                        } else if let Some(props) = w.container.window_properties.as_ref() {
-                            props.class.as_deref()
-                        } else {
-                            w.container.app_id.as_deref()
-                        };
-                        if let Some(m) = matcher {
-                            if let Some(v) = self.mapping.get_mut(m) {
-                                if let Some(con_id) = v.pop() {
-                                    self.run(&format!(
-                                        "[con_id={con_id}] swap container with con_id {}",
-                                        w.container.id
-                                    ))?;
-                                    self.run(&format!("[con_id={con_id}] kill"))?;
-                                    count -= 1;
-                                    if count == 0 {
-                                        break;
-                                    }
-                                    continue;
-                                }
-                            }
-                        }
-                        self.run(&format!("[con_id={}] floating enable", w.container.id))?;
-                    }
-                    _ => {}
-                },
-                _ => {}
-            }
-        }
-
-        Ok(())
-    }
+#[derive(clap::Args, Debug)]
+struct ShowArgs {
+    /// The layout file, `-` to read it from stdin, the current session if not given
+    #[arg(short, long)]
+    layout_file: Option<PathBuf>,
+    /// Leave out what is nested more than this many levels below the outputs
+    #[arg(long)]
+    depth: Option<usize>,
+    /// Cut the lines to this many characters, by default the width of the terminal as given by
+    /// $COLUMNS
+    #[arg(long)]
+    width: Option<usize>,
 }
 
-#[derive(Parser, Debug)]
-#[command(author, version, about, long_about = None)]
-struct Args {
-    #[arg(short, long, default_value = "false")]
-    debug: bool,
-    #[arg(short, long, default_value = "false")]
-    spawn: bool,
+#[derive(clap::Args, Debug)]
+struct DiffArgs {
+    /// The layout file, `-` to read it from stdin
     #[arg(short, long)]
     layout_file: PathBuf,
+    #[arg(long, value_enum, default_value_t = Format::Text)]
+    format: Format,
+    /// How far the share of its container a window takes may be from its slot size, from 0 to 1
+    /// [default: 0.05]
+    #[arg(long)]
+    threshold: Option<f64>,
 }
 
-fn main() -> Result<()> {
-    let args = Args::parse();
-    let mut log_builder = pretty_env_logger::formatted_builder();
-    if args.debug {
-        log_builder.filter_level(log::LevelFilter::Debug);
+impl DiffArgs {
+    /// Takes the options missing from the command line from the `[diff]` table of the config.
+    fn apply(&mut self, config: config::Diff) {
+        self.threshold = self.threshold.or(config.threshold);
     }
-    log_builder.init();
-
-    let conf = std::fs::read_to_string(args.layout_file)?;
-    let output: Output = serde_json::from_str(&conf)?;
+}
 
-    if let Some(home) = dirs::home_dir() {
-        std::env::set_current_dir(home)?;
+/// Parses the command line, also accepting the options of `restore` without the subcommand as
+/// before there were subcommands.
+fn parse_args() -> Args {
+    let argv: Vec<OsString> = std::env::args_os().collect();
+    let e = match Args::try_parse_from(&argv) {
+        Ok(args) => return args,
+        Err(e) => e,
+    };
+    let mut legacy = argv;
+    legacy.insert(1.min(legacy.len()), "restore".into());
+    match Args::try_parse_from(legacy) {
+        Ok(args) => {
+            eprintln!(
+                "warning: options without a subcommand are deprecated, use `swaystart restore`"
+            );
+            args
+        }
+        Err(_) => e.exit(),
     }
+}
 
-    let mut builder = LayoutBuilder::new()?;
-    builder.visit_output(&output)?;
-
-    let LayoutBuilder {
-        placeholder,
-        mapping,
-        ..
-    } = builder;
+/// Sets up logging and runs the command, once the config is loaded.
+fn run(args: Args, config: config::Config) -> Result<(), swaystart::Error> {
+    let log_path = args.log_file.or(config.log.file);
+    let log_level = args.log_level.or(config.log.level);
+    let truncate = args.log_truncate || config.log.truncate;
+    let log_file = log_path.as_deref().map(|path| logging::LogFile {
+        path,
+        level: log_level.unwrap_or(log::LevelFilter::Debug),
+        truncate,
+    });
+    let trace_file = args.trace_file.or(config.log.trace_file);
+    logging::init(args.debug, log_file, trace_file.as_deref());
 
-    if args.spawn {
-        let mut spawner = Spawner {};
-        spawner.visit_output(&output)?;
+    match args.command {
+        Command::Restore(mut args) => {
+            args.apply(config.restore)?;
+            let workspaces = config::group_workspaces(&config.groups, &args.groups)?;
+            for w in workspaces {
+                if !args.workspaces.contains(&w) {
+                    args.workspaces.push(w);
+                }
+            }
+            if args.detach && swaystart::detach()? {
+                return Ok(());
+            }
+            if args.auto {
+                let layout_file = swaystart::choose_layout(args.layout_dir.as_deref())?;
+                if args.print_choice {
+                    println!("{}", layout_file.display());
+                    return Ok(());
+                }
+                log::info!("restoring {}", layout_file.display());
+                args.layout_files = vec![layout_file];
+            }
+            swaystart::restore(args).and_then(|(_, res)| res)
+        }
+        Command::Check(args) => swaystart::check(&args.layout_file, args.strict),
+        Command::Diff(mut args) => {
+            args.apply(config.diff);
+            swaystart::diff(&args.layout_file, args.threshold, args.format)
+        }
+        Command::Edit(args) => swaystart::edit(&args.layout_file),
+        Command::Show(args) => swaystart::show(args.layout_file.as_deref(), args.depth, args.width),
+        Command::Clean(args) => swaystart::clean(args.yes, args.all),
+        Command::Selftest => swaystart::selftest(),
+        Command::Replay(args) => swaystart::replay(&args.dir, args.step),
+        Command::Place(args) => swaystart::place(
+            &args.app_id,
+            args.exec.as_deref(),
+            args.timeout,
+            args.backend,
+        ),
+        Command::Daemon(mut args) => {
+            args.apply(config.daemon);
+            swaystart::daemon(&args.layout_files, args.spawn, config.restore)
+        }
     }
-    let mut swapper = Swapper::new(mapping)?;
-    swapper.swap()?;
-
-    placeholder.wait_until_idle();
+}
 
-    Ok(())
+fn main() -> ExitCode {
+    let args = parse_args();
+    let res = config::load(args.config.as_deref()).and_then(|config| run(args, config));
+    match res {
+        Ok(()) => ExitCode::SUCCESS,
+        // By now the placeholder client has been shut down, there is nothing left to clean up.
+        Err(e @ status::Error::CompositorGone(_)) => {
+            log::error!("{}, giving up on the restore", status::describe(&e));
+            status::of(&e)
+        }
+        Err(e) => {
+            let code = status::of(&e);
+            eprintln!("Error: {:?}", anyhow::Error::from(e));
+            code
+        }
+    }
 }
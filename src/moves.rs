@@ -0,0 +1,238 @@
+use crate::{FloatingApp, Layout, LayoutStyle, Output, Slot, SlotContent, Workspace};
+
+/// A `--move` of the apps with an id to another workspace than the one of the layout.
+#[derive(Debug, Clone)]
+pub struct Move {
+    pub id: String,
+    pub workspace: String,
+}
+
+impl std::fmt::Display for Move {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "app_id={}:workspace={}", self.id, self.workspace)
+    }
+}
+
+/// A `--move`, written `ID:WORKSPACE` or `app_id=ID:workspace=WORKSPACE`.
+pub(crate) fn parse(arg: &str) -> Result<Move, String> {
+    let (id, workspace) = arg
+        .split_once(':')
+        .ok_or_else(|| "expected ID:WORKSPACE".to_owned())?;
+    let id = id.strip_prefix("app_id=").unwrap_or(id);
+    let workspace = workspace.strip_prefix("workspace=").unwrap_or(workspace);
+    if id.is_empty() {
+        return Err("the app id is empty".to_owned());
+    }
+    if workspace.is_empty() {
+        return Err("the workspace is empty".to_owned());
+    }
+    Ok(Move {
+        id: id.to_owned(),
+        workspace: workspace.to_owned(),
+    })
+}
+
+/// Moves the slots and the floating apps matched by `moves` to the end of their workspace,
+/// which is added if the layout has none with that name, returning the moves that matched
+/// nothing.
+///
+/// The layouts and workspaces left empty are dropped, the sizes are left for
+/// `SizeNormalizer` to fix.
+pub(crate) fn apply<'a>(output: &mut Output, moves: &'a [Move]) -> Vec<&'a Move> {
+    let mut matched = vec![false; moves.len()];
+    let mut slots: Vec<(usize, Slot)> = Vec::new();
+    let mut floating: Vec<(usize, FloatingApp)> = Vec::new();
+    for workspace in &mut output.workspaces {
+        let name = workspace.name.clone();
+        // The apps already on their workspace stay where they are.
+        let target = |id: &str| {
+            moves
+                .iter()
+                .position(|m| m.id == id)
+                .map(|i| (i, moves[i].workspace != name))
+        };
+        take_slots(&mut workspace.layout, &target, &mut matched, &mut slots);
+        for f in std::mem::take(&mut workspace.floating) {
+            let (FloatingApp::App(id) | FloatingApp::AppWithId { id, .. }) = &f;
+            match target(id) {
+                Some((i, true)) => {
+                    matched[i] = true;
+                    floating.push((i, f));
+                }
+                Some((i, false)) => {
+                    matched[i] = true;
+                    workspace.floating.push(f);
+                }
+                None => workspace.floating.push(f),
+            }
+        }
+    }
+    for (i, slot) in slots {
+        workspace(output, &moves[i].workspace)
+            .layout
+            .slots
+            .push(slot);
+    }
+    for (i, app) in floating {
+        workspace(output, &moves[i].workspace).floating.push(app);
+    }
+    output
+        .workspaces
+        .retain(|w| !w.layout.slots.is_empty() || !w.floating.is_empty());
+    moves
+        .iter()
+        .zip(matched)
+        .filter_map(|(m, matched)| (!matched).then_some(m))
+        .collect()
+}
+
+/// Takes the slots of the apps that `target` moves out of `layout`, dropping the nested
+/// layouts they leave empty.
+fn take_slots(
+    layout: &mut Layout,
+    target: &impl Fn(&str) -> Option<(usize, bool)>,
+    matched: &mut [bool],
+    taken: &mut Vec<(usize, Slot)>,
+) {
+    for mut slot in std::mem::take(&mut layout.slots) {
+        let id = match slot.content {
+            SlotContent::Container(ref mut inner) => {
+                take_slots(inner, target, matched, taken);
+                if !inner.slots.is_empty() {
+                    layout.slots.push(slot);
+                }
+                continue;
+            }
+            SlotContent::App(ref id) | SlotContent::AppWithId { ref id, .. } => id,
+        };
+        match target(id) {
+            Some((i, true)) => {
+                matched[i] = true;
+                taken.push((i, slot));
+            }
+            Some((i, false)) => {
+                matched[i] = true;
+                layout.slots.push(slot);
+            }
+            None => layout.slots.push(slot),
+        }
+    }
+}
+
+/// The workspace `name` of `output`, added as a horizontal split if there is none.
+fn workspace<'a>(output: &'a mut Output, name: &str) -> &'a mut Workspace {
+    let idx = match output.workspaces.iter().position(|w| w.name == name) {
+        Some(idx) => idx,
+        None => {
+            output.workspaces.push(Workspace {
+                name: name.to_owned(),
+                style: LayoutStyle::Splith,
+                layout: Layout {
+                    style: LayoutStyle::Splith,
+                    slots: Vec::new(),
+                },
+                floating: Vec::new(),
+                source: None,
+            });
+            output.workspaces.len() - 1
+        }
+    };
+    &mut output.workspaces[idx]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    /// The workspace `1`, with `foot` and a nested layout of `firefox` and `aerc`, and
+    /// `pavucontrol` floating.
+    fn layout() -> Output {
+        serde_json::from_value(json!({
+            "name": "OUT",
+            "workspaces": [{
+                "name": "1",
+                "style": "splith",
+                "layout": {
+                    "style": "splith",
+                    "slots": [
+                        { "content": "foot" },
+                        { "content": { "style": "splitv", "slots": [
+                            { "content": "firefox" },
+                            { "content": { "app": "foot", "id": "aerc" } },
+                        ] } },
+                    ],
+                },
+                "floating": ["pavucontrol"],
+            }],
+        }))
+        .unwrap()
+    }
+
+    /// The ids of the apps of `layout`, nested ones in brackets.
+    fn ids(layout: &Layout) -> Vec<String> {
+        layout
+            .slots
+            .iter()
+            .map(|s| match &s.content {
+                SlotContent::Container(inner) => format!("{:?}", ids(inner)),
+                SlotContent::App(id) | SlotContent::AppWithId { id, .. } => id.clone(),
+            })
+            .collect()
+    }
+
+    fn floating(workspace: &Workspace) -> Vec<&str> {
+        workspace
+            .floating
+            .iter()
+            .map(|(FloatingApp::App(id) | FloatingApp::AppWithId { id, .. })| id.as_str())
+            .collect()
+    }
+
+    fn moves(args: &[&str]) -> Vec<Move> {
+        args.iter().map(|a| parse(a).unwrap()).collect()
+    }
+
+    #[test]
+    fn leaves_apps_already_in_place() {
+        let mut output = layout();
+        let moves = moves(&["aerc:1", "pavucontrol:1"]);
+        assert!(apply(&mut output, &moves).is_empty());
+        assert_eq!(output.workspaces.len(), 1);
+        let workspace = &output.workspaces[0];
+        assert_eq!(ids(&workspace.layout), ["foot", r#"["firefox", "aerc"]"#]);
+        assert_eq!(floating(workspace), ["pavucontrol"]);
+    }
+
+    #[test]
+    fn moves_slots_to_another_workspace() {
+        let mut output = layout();
+        let moves = moves(&["app_id=firefox:workspace=web", "aerc:web", "htop:web"]);
+        let unmatched: Vec<String> = apply(&mut output, &moves)
+            .iter()
+            .map(|m| m.to_string())
+            .collect();
+        assert_eq!(unmatched, ["app_id=htop:workspace=web"]);
+        let names: Vec<&str> = output.workspaces.iter().map(|w| w.name.as_str()).collect();
+        assert_eq!(names, ["1", "web"]);
+        // The nested layout left empty is dropped.
+        assert_eq!(ids(&output.workspaces[0].layout), ["foot"]);
+        let web = &output.workspaces[1];
+        assert!(matches!(web.layout.style, LayoutStyle::Splith));
+        assert_eq!(ids(&web.layout), ["firefox", "aerc"]);
+    }
+
+    #[test]
+    fn moves_floating_apps_as_floating() {
+        let mut output = layout();
+        assert!(apply(&mut output, &moves(&["pavucontrol:2", "foot:2"])).is_empty());
+        let second = &output.workspaces[1];
+        assert!(floating(&output.workspaces[0]).is_empty());
+        assert_eq!(floating(second), ["pavucontrol"]);
+        assert_eq!(ids(&second.layout), ["foot"]);
+        // A workspace left without any app is dropped.
+        assert!(apply(&mut output, &moves(&["firefox:2", "aerc:2"])).is_empty());
+        let names: Vec<&str> = output.workspaces.iter().map(|w| w.name.as_str()).collect();
+        assert_eq!(names, ["2"]);
+    }
+}
@@ -0,0 +1,74 @@
+use std::os::unix::net::{SocketAddr, UnixDatagram};
+use std::time::{Duration, Instant};
+
+/// Tells systemd how swaystart is doing, with the sd_notify protocol.
+///
+/// Everything is a no-op unless swaystart runs as a service with `NOTIFY_SOCKET` set. Failing to
+/// notify is logged and otherwise ignored, as it must not get in the way of the restore.
+pub struct Notifier {
+    socket: Option<(UnixDatagram, SocketAddr)>,
+    /// How often systemd expects a watchdog ping, if it does.
+    watchdog: Option<Duration>,
+    last_ping: Instant,
+}
+
+impl Notifier {
+    pub fn from_env() -> Notifier {
+        let socket = std::env::var_os("NOTIFY_SOCKET").and_then(|path| {
+            let res = socket_addr(&path).and_then(|addr| Ok((UnixDatagram::unbound()?, addr)));
+            res.map_err(|e| log::warn!("can't notify systemd: {}", e))
+                .ok()
+        });
+        // The watchdog is meant for the main process of the service only.
+        let pid = std::env::var("WATCHDOG_PID").ok();
+        let watchdog = std::env::var("WATCHDOG_USEC")
+            .ok()
+            .and_then(|usec| usec.parse().ok())
+            .filter(|_| pid.is_none_or(|p| p == std::process::id().to_string()))
+            .map(Duration::from_micros);
+        Notifier {
+            socket,
+            watchdog,
+            last_ping: Instant::now(),
+        }
+    }
+    fn send(&self, state: &str) {
+        let Some((socket, addr)) = &self.socket else {
+            return;
+        };
+        if let Err(e) = socket.send_to_addr(state.as_bytes(), addr) {
+            log::warn!("can't notify systemd of {:?}: {}", state, e);
+        }
+    }
+    /// Tells that swaystart is done starting up.
+    pub fn ready(&self) {
+        self.send("READY=1");
+    }
+    /// Sets the status shown by `systemctl status`.
+    pub fn status(&self, status: &str) {
+        self.send(&format!("STATUS={}", status));
+    }
+    /// When the next watchdog ping is due, if systemd expects them.
+    pub fn next_ping(&self) -> Option<Instant> {
+        // Pinging twice per interval leaves room for a late wake up.
+        self.watchdog.map(|w| self.last_ping + w / 2)
+    }
+    /// Pings the watchdog, if a ping is due.
+    pub fn watchdog(&mut self) {
+        if self.socket.is_none() || self.next_ping().is_none_or(|t| Instant::now() < t) {
+            return;
+        }
+        self.send("WATCHDOG=1");
+        self.last_ping = Instant::now();
+    }
+}
+
+/// The address of the socket at `path`, which is in the abstract namespace if it starts with `@`.
+fn socket_addr(path: &std::ffi::OsStr) -> std::io::Result<SocketAddr> {
+    use std::os::linux::net::SocketAddrExt;
+    use std::os::unix::ffi::OsStrExt;
+    match path.as_bytes() {
+        [b'@', name @ ..] => SocketAddr::from_abstract_name(name),
+        _ => SocketAddr::from_pathname(path),
+    }
+}
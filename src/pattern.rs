@@ -0,0 +1,126 @@
+use anyhow::{Context, Result};
+use regex::Regex;
+use std::collections::HashMap;
+
+/// The prefix of the slot ids that are regular expressions the windows are matched against,
+/// rather than their app_id or class.
+const REGEX_PREFIX: &str = "regex:";
+/// The prefix of the slot ids that are glob patterns, where `*` stands for any text, `?` for
+/// any character, and `\` makes the next character stand for itself.
+const GLOB_PREFIX: &str = "glob:";
+
+/// The regular expression of the slot id `id`, if it is a pattern.
+fn regex(id: &str) -> Option<Result<Regex>> {
+    if let Some(pattern) = id.strip_prefix(REGEX_PREFIX) {
+        return Some(Regex::new(pattern).with_context(|| format!("invalid regex in id {:?}", id)));
+    }
+    let glob = id.strip_prefix(GLOB_PREFIX)?;
+    let mut pattern = String::from("^");
+    let mut chars = glob.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '*' => pattern.push_str(".*"),
+            '?' => pattern.push('.'),
+            '\\' => match chars.next() {
+                Some(c) => pattern.push_str(&regex::escape(&c.to_string())),
+                None => {
+                    return Some(Err(anyhow::anyhow!(
+                        "invalid glob in id {:?}: it ends with a lone \\",
+                        id
+                    )))
+                }
+            },
+            c => pattern.push_str(&regex::escape(&c.to_string())),
+        }
+    }
+    pattern.push('$');
+    Some(Regex::new(&pattern).with_context(|| format!("invalid glob in id {:?}", id)))
+}
+
+/// Fails if the slot id `id` would match any window or none by mistake: if it is blank, or a
+/// pattern that is empty or does not compile.
+pub(crate) fn check(id: &str) -> Result<()> {
+    if id.trim().is_empty() {
+        anyhow::bail!("the id {:?} is blank", id);
+    }
+    let pattern = id
+        .strip_prefix(REGEX_PREFIX)
+        .or_else(|| id.strip_prefix(GLOB_PREFIX));
+    if pattern.is_some_and(|p| p.is_empty()) {
+        anyhow::bail!("the id {:?} has an empty pattern", id);
+    }
+    regex(id).transpose()?;
+    Ok(())
+}
+
+/// Matches windows against slot ids, compiling the patterns among them once.
+#[derive(Default)]
+pub(crate) struct Patterns {
+    compiled: HashMap<String, Option<Regex>>,
+}
+
+impl Patterns {
+    /// Whether a window with the app_id or class `matcher` can fill the slots of `id`.
+    pub fn matches(&mut self, id: &str, matcher: &str) -> bool {
+        if !id.starts_with(REGEX_PREFIX) && !id.starts_with(GLOB_PREFIX) {
+            return id == matcher;
+        }
+        // The ids of a layout are checked when it is loaded, only a recording can get here
+        // with a broken one.
+        let regex = self
+            .compiled
+            .entry(id.to_owned())
+            .or_insert_with(|| regex(id)?.map_err(|e| log::warn!("{:#}", e)).ok());
+        regex.as_ref().is_some_and(|r| r.is_match(matcher))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn matches(id: &str, matcher: &str) -> bool {
+        Patterns::default().matches(id, matcher)
+    }
+
+    #[test]
+    fn glob_wildcards() {
+        assert!(matches("glob:org.gnome.*", "org.gnome.Nautilus"));
+        assert!(!matches("glob:org.gnome.*", "org.kde.dolphin"));
+        assert!(matches("glob:foot?", "foot2"));
+        assert!(!matches("glob:foot?", "foot"));
+        // The whole app_id has to match, not a part of it.
+        assert!(!matches("glob:foot", "footclient"));
+    }
+
+    #[test]
+    fn glob_escapes_regex_characters() {
+        assert!(matches("glob:org.gnome.Nautilus", "org.gnome.Nautilus"));
+        assert!(!matches("glob:org.gnome.Nautilus", "orgXgnomeXNautilus"));
+        assert!(matches("glob:[app]+(1)", "[app]+(1)"));
+        assert!(!matches("glob:[app]+(1)", "a"));
+        assert!(matches("glob:^app$|other", "^app$|other"));
+    }
+
+    #[test]
+    fn glob_backslash_makes_wildcards_literal() {
+        assert!(matches(r"glob:what\?", "what?"));
+        assert!(!matches(r"glob:what\?", "whats"));
+        assert!(matches(r"glob:\*star\*", "*star*"));
+        assert!(!matches(r"glob:\*star\*", "a star!"));
+        assert!(matches(r"glob:back\\slash", r"back\slash"));
+    }
+
+    #[test]
+    fn checks_patterns() {
+        check("glob:*").unwrap();
+        check("regex:^foot$").unwrap();
+        assert!(check(r"glob:foot\").is_err());
+        assert!(check("regex:(foot").is_err());
+        assert!(check("glob:").is_err());
+        assert!(check("  ").is_err());
+        // Without a prefix, the id is matched as it is.
+        check("(foot").unwrap();
+        assert!(matches("(foot", "(foot"));
+    }
+}
@@ -1,126 +1,531 @@
-use calloop::channel::{channel, Event, Sender};
-use std::mem::ManuallyDrop;
+use crate::backend::Backend;
+use crate::canvas::{text_width, truncate_to_width, Canvas, GLYPH_HEIGHT};
+use crate::theme::Theme;
+use anyhow::Result;
+use calloop::channel::{channel, Channel, Event, Sender};
+use std::cell::Cell;
+use std::sync::mpsc;
 use std::thread::{spawn, JoinHandle};
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
 use smithay_client_toolkit::reexports::calloop::{EventLoop, LoopHandle};
 use smithay_client_toolkit::reexports::calloop_wayland_source::WaylandSource;
 use smithay_client_toolkit::{
-    compositor::{CompositorHandler, CompositorState},
-    delegate_compositor, delegate_output, delegate_registry, delegate_shm, delegate_xdg_shell,
+    activation::{ActivationHandler, ActivationState, RequestDataExt},
+    compositor::{CompositorHandler, CompositorState, Region},
+    delegate_activation, delegate_compositor, delegate_layer, delegate_output, delegate_pointer,
+    delegate_registry, delegate_seat, delegate_shm, delegate_simple, delegate_xdg_shell,
     delegate_xdg_window,
     output::{OutputHandler, OutputState},
-    registry::{ProvidesRegistryState, RegistryState},
+    reexports::protocols::wp::{
+        single_pixel_buffer::v1::client::wp_single_pixel_buffer_manager_v1::WpSinglePixelBufferManagerV1,
+        viewporter::client::{wp_viewport::WpViewport, wp_viewporter::WpViewporter},
+    },
+    registry::{ProvidesRegistryState, RegistryState, SimpleGlobal},
     registry_handlers,
+    seat::{
+        pointer::{PointerEvent, PointerEventKind, PointerHandler, BTN_LEFT},
+        Capability, SeatHandler, SeatState,
+    },
     shell::{
+        wlr_layer::{
+            Anchor, KeyboardInteractivity, Layer, LayerShell, LayerShellHandler, LayerSurface,
+            LayerSurfaceConfigure,
+        },
         xdg::{
-            window::{Window, WindowConfigure, WindowDecorations, WindowHandler},
+            window::{DecorationMode, Window, WindowConfigure, WindowDecorations, WindowHandler},
             XdgShell,
         },
         WaylandSurface,
     },
-    shm::{slot::SlotPool, Shm, ShmHandler},
+    shm::{
+        slot::{Buffer, SlotPool},
+        Shm, ShmHandler,
+    },
 };
 use wayland_client::{
     globals::registry_queue_init,
-    protocol::{wl_output, wl_shm, wl_surface},
-    Connection, QueueHandle,
+    protocol::{wl_buffer, wl_output, wl_pointer, wl_seat, wl_shm, wl_surface},
+    Connection, Dispatch, Proxy, QueueHandle,
 };
 
+/// Height of the title bar drawn when the compositor asks for client side decorations.
+const TITLEBAR_HEIGHT: u32 = 24;
+const TITLEBAR_COLOR: u32 = 0xff303030;
+const TITLE_COLOR: u32 = 0xffe0e0e0;
+const TITLE_SCALE: u32 = 2;
+const TITLE_PADDING: u32 = 6;
+/// Duration of one pulse of the placeholder animation.
+const PULSE_PERIOD_MS: u32 = 2000;
+/// Opacity of the placeholder at the peak of a pulse.
+const PULSE_MAX_ALPHA: f32 = 64.;
+/// Granularity of the pulse opacity, frames that would change it less are skipped.
+const PULSE_STEP: u8 = 4;
+/// Opacity of the tint of launched and timed out placeholders.
+const STATE_ALPHA: u8 = 64;
+/// Premultiplied black darkening skipped placeholders.
+const SKIPPED_SHADE: u32 = 0x80000000;
+/// How long a skipped placeholder stays on screen.
+const SKIP_DELAY: Duration = Duration::from_millis(300);
+/// Distance of the progress overlay from the corner of the screen.
+const OVERLAY_MARGIN: i32 = 16;
+/// Pending slots listed by the progress overlay, the others are only counted.
+const OVERLAY_MAX_PENDING: usize = 8;
+
 struct Client {
     registry_state: RegistryState,
     output_state: OutputState,
+    seat_state: SeatState,
     shm: Shm,
     compositor: CompositorState,
     xdg_shell: XdgShell,
+    layer_shell: Option<LayerShell>,
+    activation: Option<ActivationState>,
+    /// Solid color placeholders are drawn with a stretched single pixel buffer when the
+    /// compositor supports it, instead of a full size shm buffer.
+    single_pixel: Option<(
+        SimpleGlobal<WpSinglePixelBufferManagerV1, 1>,
+        SimpleGlobal<WpViewporter, 1>,
+    )>,
     queue_handle: QueueHandle<Client>,
+    pointer: Option<wl_pointer::WlPointer>,
 
     exit: bool,
-    exit_on_idle: bool,
+    idle_deadline: Option<Instant>,
+    animate: bool,
+    theme: Theme,
     pool: SlotPool,
-    windows: Vec<Window>,
+    windows: Vec<Placeholder>,
+    overlay: Option<Overlay>,
+    events: mpsc::Sender<ClientEvent>,
+}
+
+/// Layer surface in the top right corner of the screen showing how far the restore got.
+struct Overlay {
+    layer: LayerSurface,
+    lines: Vec<String>,
+    size: (u32, u32),
+    configured: bool,
+}
+
+struct Placeholder {
+    window: Window,
+    token: u64,
+    title: String,
+    /// Summary of the windows that will replace the placeholder, shown in its xdg title.
+    matcher: String,
+    state: PlaceholderState,
+    /// When a skipped placeholder gets destroyed.
+    close_at: Option<Instant>,
+    mapped: bool,
+    csd: bool,
+    size: (u32, u32),
+    buffer: Option<Buffer>,
+    viewport: Option<WpViewport>,
+    drawn: Option<Frame>,
+}
+
+impl Drop for Placeholder {
+    fn drop(&mut self) {
+        if let Some(viewport) = self.viewport.take() {
+            viewport.destroy();
+        }
+    }
 }
 
-enum ClientMsg {
-    NewWindow { title: String, app_id: String },
-    ExitOnIdle,
+/// What a placeholder is waiting for, reflected in its appearance.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PlaceholderState {
+    Waiting,
+    /// The application has been started.
+    Launched,
+    /// The application did not show up in time.
+    TimedOut,
+    /// The placeholder was closed and is about to go away.
+    Skipped,
 }
 
+impl PlaceholderState {
+    fn status(self) -> &'static str {
+        match self {
+            PlaceholderState::Waiting => "",
+            PlaceholderState::Launched => "starting...",
+            PlaceholderState::TimedOut => "timed out",
+            PlaceholderState::Skipped => "skipped",
+        }
+    }
+}
+
+impl PlaceholderState {
+    /// Title shown by the window manager in title bars and tabs for a placeholder in this
+    /// state, of the app `title` and waiting for the windows described by `matcher`.
+    pub(crate) fn window_title(self, title: &str, matcher: &str) -> String {
+        match self {
+            PlaceholderState::Waiting => format!("\u{23f3} {}", matcher),
+            PlaceholderState::Launched => format!("\u{1f680} launching {}\u{2026}", title),
+            PlaceholderState::TimedOut => format!("\u{26a0} {} timed out", matcher),
+            PlaceholderState::Skipped => format!("{} skipped", matcher),
+        }
+    }
+}
+
+impl Placeholder {
+    /// Title shown by the compositor in title bars and tabs once the placeholder is mapped.
+    fn xdg_title(&self) -> String {
+        self.state.window_title(&self.title, &self.matcher)
+    }
+}
+
+pub(crate) enum ClientMsg {
+    NewWindow {
+        token: u64,
+        title: String,
+        app_id: String,
+        matcher: String,
+    },
+    SetState {
+        token: u64,
+        state: PlaceholderState,
+    },
+    RequestActivation {
+        token: u64,
+    },
+    ExitOnIdle {
+        timeout: Duration,
+    },
+    Progress {
+        restored: usize,
+        total: usize,
+        pending: Vec<String>,
+    },
+    HideProgress,
+    Shutdown,
+}
+
+/// Notifications sent back from the client thread.
+pub(crate) enum ClientEvent {
+    /// The first configure of the window created with `token` has been acked and a buffer
+    /// committed in response, or its X11 window was mapped.
+    Mapped { token: u64, surface_id: u32 },
+    /// Answer to an activation token request on behalf of the placeholder with `token`.
+    Activation {
+        token: u64,
+        activation: Option<String>,
+    },
+    /// The windows with these tokens were still open when the idle timeout expired, and have
+    /// been destroyed.
+    Stuck { tokens: Vec<u64> },
+}
+
+/// How long dropping a `ClientHandle` waits for the client thread to exit.
+const SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// Error returned when the client thread is gone, or does not go away.
+#[derive(Debug)]
+pub enum ClientFailed {
+    Exited,
+    Stuck { timeout: Duration },
+    Panicked,
+}
+
+impl std::fmt::Display for ClientFailed {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ClientFailed::Exited => write!(f, "placeholder client exited"),
+            ClientFailed::Stuck { timeout } => {
+                write!(f, "placeholder client did not exit within {:?}", timeout)
+            }
+            ClientFailed::Panicked => write!(f, "placeholder client panicked"),
+        }
+    }
+}
+
+impl std::error::Error for ClientFailed {}
+
 pub struct ClientHandle {
-    chan: ManuallyDrop<Sender<ClientMsg>>,
-    thread: ManuallyDrop<JoinHandle<()>>,
-    wait: bool,
+    chan: Sender<ClientMsg>,
+    thread: Option<JoinHandle<()>>,
+    events: mpsc::Receiver<ClientEvent>,
+    next_token: Cell<u64>,
 }
 impl ClientHandle {
-    pub fn new() -> Self {
+    /// Starts the placeholder client of `backend` in a thread of its own: a Wayland client on
+    /// sway, an X11 one on i3.
+    pub fn new(backend: Backend, animate: bool, theme: Theme) -> Self {
+        Self::start(move |receiver, event_sender| match backend {
+            Backend::Sway => run(receiver, event_sender, animate, theme),
+            Backend::I3 => crate::x11::run(receiver, event_sender, theme),
+        })
+    }
+    /// Starts a client that maps every placeholder at once, without a window manager.
+    #[cfg(test)]
+    pub fn mock() -> Self {
+        Self::start(run_mock)
+    }
+    fn start(
+        run: impl FnOnce(Channel<ClientMsg>, mpsc::Sender<ClientEvent>) + Send + 'static,
+    ) -> Self {
         let (sender, receiver) = channel();
+        let (event_sender, event_receiver) = mpsc::channel();
         let handle = spawn(move || {
-            let mut event_loop: EventLoop<Client> =
-                EventLoop::try_new().expect("Failed to initialize the event loop!");
-            let loop_handle = event_loop.handle();
-            loop_handle
-                .insert_source(receiver, |ev, _, client| match ev {
-                    Event::Closed => {
-                        client.exit = true;
-                    }
-                    Event::Msg(ClientMsg::NewWindow { title, app_id }) => {
-                        client.new_window(&title, &app_id);
-                    }
-                    Event::Msg(ClientMsg::ExitOnIdle) => {
-                        client.exit_on_idle = true;
-                    }
-                })
-                .expect("failed to register channel source");
-
-            let mut client = Client::new(loop_handle);
-
-            loop {
-                event_loop
-                    .dispatch(Duration::from_millis(16), &mut client)
-                    .unwrap();
-
-                if client.exit {
-                    break;
-                }
-                if client.exit_on_idle && client.windows.is_empty() {
-                    break;
-                }
-            }
+            let _span = tracing::info_span!("placeholder").entered();
+            run(receiver, event_sender)
         });
         Self {
-            chan: ManuallyDrop::new(sender),
-            thread: ManuallyDrop::new(handle),
-            wait: false,
+            chan: sender,
+            thread: Some(handle),
+            events: event_receiver,
+            next_token: Cell::new(0),
         }
     }
-    pub fn new_window(&self, title: &str, app_id: &str) {
+    /// Creates a placeholder window, returning a token identifying it.
+    ///
+    /// `matcher` describes the windows that will take its place, and becomes the title of the
+    /// placeholder once it is mapped.
+    pub fn new_window(&self, title: &str, app_id: &str, matcher: &str) -> u64 {
+        let token = self.next_token.get();
+        self.next_token.set(token + 1);
         self.chan
             .send(ClientMsg::NewWindow {
+                token,
                 title: title.to_owned(),
                 app_id: app_id.to_owned(),
+                matcher: matcher.to_owned(),
+            })
+            .expect("failed to send");
+        token
+    }
+    pub fn set_state(&self, token: u64, state: PlaceholderState) {
+        self.chan
+            .send(ClientMsg::SetState { token, state })
+            .expect("failed to send");
+    }
+    /// Gets an xdg activation token from the placeholder with `token`, to be handed to the
+    /// application that replaces it so that it can take focus.
+    pub fn activation_token(&self, token: u64, timeout: Duration) -> Option<String> {
+        self.chan
+            .send(ClientMsg::RequestActivation { token })
+            .expect("failed to send");
+        let deadline = Instant::now() + timeout;
+        loop {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            match self.events.recv_timeout(remaining) {
+                Ok(ClientEvent::Activation {
+                    token: t,
+                    activation,
+                }) if t == token => return activation,
+                Ok(_) => {}
+                Err(e) => {
+                    log::warn!("no activation token for placeholder {}: {:?}", token, e);
+                    return None;
+                }
+            }
+        }
+    }
+    /// Shows or updates the progress overlay, listing the slots still `pending`.
+    pub fn show_progress(&self, restored: usize, total: usize, pending: Vec<String>) {
+        self.chan
+            .send(ClientMsg::Progress {
+                restored,
+                total,
+                pending,
             })
             .expect("failed to send");
     }
-    pub fn wait_until_idle(mut self) {
-        self.chan.send(ClientMsg::ExitOnIdle).expect("failed to send");
-        self.wait = true;
+    pub fn hide_progress(&self) {
+        self.chan
+            .send(ClientMsg::HideProgress)
+            .expect("failed to send");
+    }
+    /// Waits until the window created with `token` has been configured and drawn, returning the
+    /// protocol id of its `wl_surface`, or of its X11 window.
+    pub fn wait_mapped(&self, token: u64, timeout: Duration) -> Result<u32> {
+        let deadline = Instant::now() + timeout;
+        loop {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            match self.events.recv_timeout(remaining) {
+                Ok(ClientEvent::Mapped {
+                    token: t,
+                    surface_id,
+                }) if t == token => return Ok(surface_id),
+                Ok(ClientEvent::Mapped { token: t, .. }) => {
+                    log::debug!("ignoring stale map notification for placeholder {}", t);
+                }
+                Ok(ClientEvent::Stuck { .. } | ClientEvent::Activation { .. }) => {}
+                Err(mpsc::RecvTimeoutError::Timeout) => {
+                    anyhow::bail!(
+                        "placeholder {} was not configured within {:?}",
+                        token,
+                        timeout
+                    )
+                }
+                Err(mpsc::RecvTimeoutError::Disconnected) => {
+                    return Err(ClientFailed::Exited.into())
+                }
+            }
+        }
+    }
+    /// Waits until all the placeholders are closed and the client thread exits.
+    ///
+    /// Placeholders still open after `timeout` are destroyed, and their tokens returned.
+    pub fn wait_until_idle(mut self, timeout: Duration) -> Result<Vec<u64>> {
+        self.chan
+            .send(ClientMsg::ExitOnIdle { timeout })
+            .map_err(|_| ClientFailed::Exited)?;
+        self.join(timeout + SHUTDOWN_TIMEOUT)
+    }
+    /// Waits at most `timeout` for the client thread to exit, detaching it otherwise.
+    fn join(&mut self, timeout: Duration) -> Result<Vec<u64>> {
+        let Some(thread) = self.thread.take() else {
+            return Ok(Vec::new());
+        };
+        let deadline = Instant::now() + timeout;
+        let mut stuck = Vec::new();
+        // The event channel disconnects when the client thread exits.
+        loop {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            match self.events.recv_timeout(remaining) {
+                Ok(ClientEvent::Stuck { tokens }) => stuck = tokens,
+                Ok(ClientEvent::Mapped { .. } | ClientEvent::Activation { .. }) => {}
+                Err(mpsc::RecvTimeoutError::Disconnected) => break,
+                Err(mpsc::RecvTimeoutError::Timeout) => {
+                    return Err(ClientFailed::Stuck { timeout }.into())
+                }
+            }
+        }
+        if thread.join().is_err() {
+            return Err(ClientFailed::Panicked.into());
+        }
+        Ok(stuck)
     }
 }
 impl Drop for ClientHandle {
     fn drop(&mut self) {
-        unsafe {
-            if !self.wait {
-                let c = ManuallyDrop::take(&mut self.chan);
-                drop(c);
+        if self.thread.is_none() {
+            return;
+        }
+        let _ = self.chan.send(ClientMsg::Shutdown);
+        if let Err(e) = self.join(SHUTDOWN_TIMEOUT) {
+            log::warn!("{}, leaving it behind", e);
+        }
+    }
+}
+
+/// Runs the Wayland client until it is shut down, or it is idle after `ClientMsg::ExitOnIdle`.
+/// Answers the messages of the handle as if each placeholder was mapped as soon as it is made,
+/// until it is shut down or told to exit once idle.
+#[cfg(test)]
+fn run_mock(receiver: Channel<ClientMsg>, events: mpsc::Sender<ClientEvent>) {
+    let mut event_loop: EventLoop<bool> =
+        EventLoop::try_new().expect("Failed to initialize the event loop!");
+    event_loop
+        .handle()
+        .insert_source(receiver, move |ev, _, exit| {
+            let reply = match ev {
+                Event::Msg(ClientMsg::NewWindow { token, .. }) => ClientEvent::Mapped {
+                    token,
+                    surface_id: token as u32,
+                },
+                Event::Msg(ClientMsg::RequestActivation { token }) => ClientEvent::Activation {
+                    token,
+                    activation: None,
+                },
+                Event::Msg(ClientMsg::ExitOnIdle { .. } | ClientMsg::Shutdown) | Event::Closed => {
+                    *exit = true;
+                    return;
+                }
+                Event::Msg(_) => return,
+            };
+            let _ = events.send(reply);
+        })
+        .expect("failed to register channel source");
+    let mut exit = false;
+    while !exit {
+        event_loop
+            .dispatch(None, &mut exit)
+            .expect("failed to dispatch the event loop");
+    }
+}
+
+fn run(
+    receiver: Channel<ClientMsg>,
+    event_sender: mpsc::Sender<ClientEvent>,
+    animate: bool,
+    theme: Theme,
+) {
+    let mut event_loop: EventLoop<Client> =
+        EventLoop::try_new().expect("Failed to initialize the event loop!");
+    let loop_handle = event_loop.handle();
+    loop_handle
+        .insert_source(receiver, |ev, _, client| match ev {
+            Event::Closed | Event::Msg(ClientMsg::Shutdown) => {
+                client.exit = true;
+            }
+            Event::Msg(ClientMsg::NewWindow {
+                token,
+                title,
+                app_id,
+                matcher,
+            }) => {
+                client.new_window(token, &title, &app_id, matcher);
+            }
+            Event::Msg(ClientMsg::SetState { token, state }) => {
+                client.set_state(token, state);
+            }
+            Event::Msg(ClientMsg::RequestActivation { token }) => {
+                client.request_activation(token);
+            }
+            Event::Msg(ClientMsg::ExitOnIdle { timeout }) => {
+                client.idle_deadline = Some(Instant::now() + timeout);
+            }
+            Event::Msg(ClientMsg::Progress {
+                restored,
+                total,
+                pending,
+            }) => {
+                client.show_progress(restored, total, &pending);
+            }
+            Event::Msg(ClientMsg::HideProgress) => {
+                client.overlay = None;
+            }
+        })
+        .expect("failed to register channel source");
+
+    let mut client = Client::new(loop_handle, event_sender, animate, theme);
+
+    loop {
+        // The connection breaks when the compositor goes away.
+        if let Err(e) = event_loop.dispatch(Duration::from_millis(16), &mut client) {
+            log::warn!("placeholder client: {}", e);
+            break;
+        }
+
+        if client.exit {
+            break;
+        }
+        let now = Instant::now();
+        client
+            .windows
+            .retain(|p| !matches!(p.close_at, Some(t) if t <= now));
+        if let Some(deadline) = client.idle_deadline {
+            if client.windows.is_empty() {
+                break;
+            }
+            if Instant::now() >= deadline {
+                client.destroy_stuck_windows();
+                break;
             }
-            let t = ManuallyDrop::take(&mut self.thread);
-            let _ = t.join();
         }
     }
 }
 
 impl Client {
-    fn new(loop_handle: LoopHandle<Client>) -> Self {
+    fn new(
+        loop_handle: LoopHandle<Client>,
+        events: mpsc::Sender<ClientEvent>,
+        animate: bool,
+        theme: Theme,
+    ) -> Self {
         // All Wayland apps start by connecting the compositor (server).
         let conn = Connection::connect_to_env().unwrap();
 
@@ -141,6 +546,17 @@ impl Client {
         // Since we are not using the GPU in this example, we use wl_shm to allow software rendering to a buffer
         // we share with the compositor process.
         let shm = Shm::bind(&globals, &queue_handle).expect("wl shm is not available.");
+        // The progress overlay is optional, without layer shell it is just not shown.
+        let layer_shell = LayerShell::bind(&globals, &queue_handle)
+            .map_err(|e| log::debug!("layer shell not available: {}", e))
+            .ok();
+        let activation = ActivationState::bind(&globals, &queue_handle)
+            .map_err(|e| log::debug!("xdg activation not available: {}", e))
+            .ok();
+        let single_pixel = SimpleGlobal::bind(&globals, &queue_handle)
+            .and_then(|manager| Ok((manager, SimpleGlobal::bind(&globals, &queue_handle)?)))
+            .map_err(|e| log::debug!("single pixel buffers not available: {}", e))
+            .ok();
 
         // We don't know how large the window will be yet, so lets assume the minimum size we suggested for the
         // initial memory allocation.
@@ -151,18 +567,157 @@ impl Client {
             // listen for seats and outputs.
             registry_state: RegistryState::new(&globals),
             output_state: OutputState::new(&globals, &queue_handle),
+            seat_state: SeatState::new(&globals, &queue_handle),
             shm,
             compositor,
             xdg_shell,
+            layer_shell,
+            activation,
+            single_pixel,
             queue_handle,
+            pointer: None,
 
             exit: false,
-            exit_on_idle: false,
+            idle_deadline: None,
+            animate,
+            theme,
             pool,
             windows: vec![],
+            overlay: None,
+            events,
+        }
+    }
+    fn set_state(&mut self, token: u64, state: PlaceholderState) {
+        let Some(idx) = self.windows.iter().position(|p| p.token == token) else {
+            return;
+        };
+        let p = &mut self.windows[idx];
+        if p.state == state {
+            return;
+        }
+        log::debug!("placeholder {}: {:?} -> {:?}", token, p.state, state);
+        p.state = state;
+        if p.mapped {
+            p.window.set_title(p.xdg_title());
+            let queue_handle = self.queue_handle.clone();
+            self.draw(&queue_handle, idx, 0);
+        }
+    }
+    /// Shows the placeholder as skipped for a moment, then destroys it.
+    fn skip(&mut self, idx: usize) {
+        let token = self.windows[idx].token;
+        self.set_state(token, PlaceholderState::Skipped);
+        self.windows[idx].close_at = Some(Instant::now() + SKIP_DELAY);
+    }
+    fn request_activation(&mut self, token: u64) {
+        let surface = self
+            .windows
+            .iter()
+            .find(|p| p.token == token)
+            .map(|p| p.window.wl_surface().clone());
+        match (&self.activation, surface) {
+            (Some(activation), Some(surface)) => activation
+                .request_token_with_data(&self.queue_handle, ActivationRequest { token, surface }),
+            _ => {
+                let _ = self.events.send(ClientEvent::Activation {
+                    token,
+                    activation: None,
+                });
+            }
+        }
+    }
+    fn show_progress(&mut self, restored: usize, total: usize, pending: &[String]) {
+        let Some(layer_shell) = &self.layer_shell else {
+            return;
+        };
+        let mut lines = vec![format!("{}/{} windows restored", restored, total)];
+        lines.extend(pending.iter().take(OVERLAY_MAX_PENDING).cloned());
+        if pending.len() > OVERLAY_MAX_PENDING {
+            lines.push(format!("and {} more", pending.len() - OVERLAY_MAX_PENDING));
+        }
+        let line_height = (GLYPH_HEIGHT + 2) * TITLE_SCALE;
+        let width = lines
+            .iter()
+            .map(|l| text_width(l, TITLE_SCALE))
+            .max()
+            .unwrap_or(0);
+        let size = (
+            width + 2 * TITLE_PADDING,
+            lines.len() as u32 * line_height + 2 * TITLE_PADDING,
+        );
+
+        let (compositor, queue_handle) = (&self.compositor, &self.queue_handle);
+        let overlay = self.overlay.get_or_insert_with(|| {
+            let surface = compositor.create_surface(queue_handle);
+            // The overlay must not get in the way of clicks.
+            if let Ok(region) = Region::new(compositor) {
+                surface.set_input_region(Some(region.wl_region()));
+            }
+            let layer = layer_shell.create_layer_surface(
+                queue_handle,
+                surface,
+                Layer::Overlay,
+                Some("swaystart"),
+                None,
+            );
+            layer.set_anchor(Anchor::TOP | Anchor::RIGHT);
+            layer.set_margin(OVERLAY_MARGIN, OVERLAY_MARGIN, 0, 0);
+            layer.set_keyboard_interactivity(KeyboardInteractivity::None);
+            Overlay {
+                layer,
+                lines: Vec::new(),
+                size: (0, 0),
+                configured: false,
+            }
+        });
+        overlay.lines = lines;
+        if overlay.size != size {
+            // Redrawn once the compositor acknowledges the new size.
+            overlay.size = size;
+            overlay.layer.set_size(size.0, size.1);
+            overlay.layer.commit();
+        } else if overlay.configured {
+            self.draw_overlay();
+        }
+    }
+    fn draw_overlay(&mut self) {
+        let Some(overlay) = &self.overlay else {
+            return;
+        };
+        let (width, height) = overlay.size;
+        let (buffer, data) = self
+            .pool
+            .create_buffer(
+                width as i32,
+                height as i32,
+                width as i32 * 4,
+                wl_shm::Format::Argb8888,
+            )
+            .expect("create buffer");
+        let mut canvas = Canvas::new(data, width, height);
+        canvas.fill(TITLEBAR_COLOR);
+        let line_height = (GLYPH_HEIGHT + 2) * TITLE_SCALE;
+        for (i, line) in overlay.lines.iter().enumerate() {
+            let y = TITLE_PADDING + i as u32 * line_height;
+            canvas.draw_text(
+                TITLE_PADDING as i32,
+                y as i32,
+                TITLE_SCALE,
+                TITLE_COLOR,
+                line,
+            );
         }
+        let surface = overlay.layer.wl_surface();
+        surface.damage_buffer(0, 0, width as i32, height as i32);
+        buffer.attach_to(surface).expect("buffer attach");
+        overlay.layer.commit();
+    }
+    fn destroy_stuck_windows(&mut self) {
+        let tokens: Vec<u64> = self.windows.drain(..).map(|p| p.token).collect();
+        log::warn!("destroying placeholders still open at exit: {:?}", tokens);
+        let _ = self.events.send(ClientEvent::Stuck { tokens });
     }
-    fn new_window(&mut self, title: &str, app_id: &str) {
+    fn new_window(&mut self, token: u64, title: &str, app_id: &str, matcher: String) {
         // A window is created from a surface.
         let surface = self.compositor.create_surface(&self.queue_handle);
         // And then we can create the window.
@@ -185,36 +740,221 @@ impl Client {
         // the correct options.
         window.commit();
 
-        self.windows.push(window);
+        self.windows.push(Placeholder {
+            window,
+            token,
+            title: title.to_owned(),
+            matcher,
+            state: PlaceholderState::Waiting,
+            close_at: None,
+            mapped: false,
+            csd: false,
+            size: (256, 256),
+            buffer: None,
+            viewport: None,
+            drawn: None,
+        });
     }
-    pub fn draw(
-        &mut self,
-        _conn: &Connection,
-        queue_handle: &QueueHandle<Self>,
-        surface: &wl_surface::WlSurface,
-        width: u32,
-        height: u32,
-    ) {
-        let stride = width as i32 * 4;
+    fn draw(&mut self, queue_handle: &QueueHandle<Self>, idx: usize, time: u32) {
+        let p = &mut self.windows[idx];
+        let (width, height) = p.size;
+        let surface = p.window.wl_surface().clone();
 
-        let buffer = self
-            .pool
-            .create_buffer(
+        // While waiting the accent color slowly pulses so that it is clear swaystart is alive.
+        let animate = self.animate && p.state == PlaceholderState::Waiting;
+        let pulse = if animate {
+            let phase = (time % PULSE_PERIOD_MS) as f32 / PULSE_PERIOD_MS as f32;
+            let level = (1. - (phase * std::f32::consts::TAU).cos()) / 2.;
+            (level * PULSE_MAX_ALPHA) as u8 / PULSE_STEP * PULSE_STEP
+        } else {
+            0
+        };
+        let frame = Frame {
+            size: p.size,
+            state: p.state,
+            csd: p.csd,
+            pulse,
+        };
+
+        // Request our next frame
+        if animate {
+            surface.frame(queue_handle, surface.clone());
+        }
+        if p.drawn == Some(frame) {
+            // Nothing to redraw, but the frame request still needs a commit.
+            if animate {
+                surface.commit();
+            }
+            return;
+        }
+
+        // Translucent placeholders must let the compositor draw what is behind them.
+        if p.drawn.map(|f| f.size) != Some(p.size) && self.theme.is_opaque() {
+            if let Ok(region) = Region::new(&self.compositor) {
+                region.add(0, 0, width as i32, height as i32);
+                surface.set_opaque_region(Some(region.wl_region()));
+            }
+        }
+        if self.draw_solid(idx, pulse) {
+            self.windows[idx].drawn = Some(frame);
+            return;
+        }
+        let p = &mut self.windows[idx];
+        if let Some(viewport) = &p.viewport {
+            viewport.set_destination(-1, -1);
+        }
+
+        // Reuse the buffer of the previous frame, unless the size changed or the compositor is
+        // still reading from it.
+        let stride = width as i32 * 4;
+        if p.buffer
+            .as_ref()
+            .is_some_and(|b| b.height() != height as i32 || b.stride() != stride)
+        {
+            p.buffer = None;
+        }
+        let pool = &mut self.pool;
+        let new_buffer = |pool: &mut SlotPool| {
+            pool.create_buffer(
                 width as i32,
                 height as i32,
                 stride,
                 wl_shm::Format::Argb8888,
             )
             .expect("create buffer")
-            .0;
+            .0
+        };
+        let buffer = p.buffer.get_or_insert_with(|| new_buffer(pool));
+        if buffer.canvas(pool).is_none() {
+            *buffer = new_buffer(pool);
+        }
+        let data = buffer.canvas(pool).expect("fresh buffer is not busy");
+        let mut canvas = Canvas::new(data, width, height);
 
-        // Request our next frame
-        surface.frame(queue_handle, surface.clone());
+        let theme = &self.theme;
+        canvas.fill(theme.background.map_or(0, |c| c.pixel(0xff)));
+        canvas.blend(tint(theme, p.state, pulse));
+        if let Some(color) = theme.border_color {
+            canvas.draw_border(theme.border_width, color.pixel(0xff));
+        }
+        if let Some(color) = theme.foreground {
+            let (border, scale, color) =
+                (theme.border_width, theme.font_scale(), color.pixel(0xff));
+            draw_label(&mut canvas, border, scale, color, &p.title, 0);
+            draw_label(&mut canvas, border, scale, color, p.state.status(), 1);
+        }
+        if p.csd {
+            draw_decorations(&mut canvas, &p.title);
+        }
+        canvas.clip_corners(theme.corner_radius);
 
-        // Attach and commit to present.
-        buffer.attach_to(surface).expect("buffer attach");
+        // The tint covers the whole surface, so any change damages all of it.
+        surface.damage_buffer(0, 0, width as i32, height as i32);
+        buffer.attach_to(&surface).expect("buffer attach");
         surface.commit();
+        p.drawn = Some(frame);
     }
+    /// Draws the placeholder as a single pixel buffer stretched to its size, if it is a plain
+    /// color and the compositor supports it.
+    fn draw_solid(&mut self, idx: usize, pulse: u8) -> bool {
+        let Some((manager, viewporter)) = &self.single_pixel else {
+            return false;
+        };
+        let (Ok(manager), Ok(viewporter)) = (manager.get(), viewporter.get()) else {
+            return false;
+        };
+        let (theme, queue_handle) = (&self.theme, &self.queue_handle);
+        let p = &mut self.windows[idx];
+        if p.csd || theme.foreground.is_some() || theme.border_width > 0 || theme.corner_radius > 0
+        {
+            return false;
+        }
+
+        let mut pixel = [0; 4];
+        let mut canvas = Canvas::new(&mut pixel, 1, 1);
+        canvas.fill(theme.background.map_or(0, |c| c.pixel(0xff)));
+        canvas.blend(tint(theme, p.state, pulse));
+        let [b, g, r, a] = pixel.map(|c| c as u32 * 0x01010101);
+        let buffer = manager.create_u32_rgba_buffer(r, g, b, a, queue_handle, ());
+
+        let surface = p.window.wl_surface();
+        let viewport = p
+            .viewport
+            .get_or_insert_with(|| viewporter.get_viewport(surface, queue_handle, ()));
+        let (width, height) = p.size;
+        viewport.set_destination(width as i32, height as i32);
+        // The shm buffer is not needed anymore.
+        p.buffer = None;
+        surface.attach(Some(&buffer), 0, 0);
+        surface.damage_buffer(0, 0, 1, 1);
+        surface.commit();
+        true
+    }
+}
+
+/// Color blended over the background of a placeholder in the given state.
+fn tint(theme: &Theme, state: PlaceholderState, pulse: u8) -> u32 {
+    match state {
+        PlaceholderState::Waiting => theme.accent.pixel(pulse),
+        PlaceholderState::Launched => theme.accent.pixel(STATE_ALPHA),
+        PlaceholderState::TimedOut => theme.error.pixel(STATE_ALPHA),
+        PlaceholderState::Skipped => SKIPPED_SHADE,
+    }
+}
+
+/// What was last drawn on a placeholder.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Frame {
+    size: (u32, u32),
+    state: PlaceholderState,
+    csd: bool,
+    pulse: u8,
+}
+
+/// Draws `label` centered in the canvas, inside the border, on the given line counting from the
+/// center.
+fn draw_label(canvas: &mut Canvas, border: u32, scale: u32, color: u32, label: &str, line: u32) {
+    let room = canvas.width().saturating_sub(2 * (border + TITLE_PADDING));
+    let label = truncate_to_width(label, room, scale);
+    let line_height = (GLYPH_HEIGHT + 2) * scale;
+    let x = canvas.width().saturating_sub(text_width(label, scale)) / 2;
+    let y = canvas.height().saturating_sub(GLYPH_HEIGHT * scale) / 2 + line * line_height;
+    canvas.draw_text(x as i32, y as i32, scale, color, label);
+}
+
+/// Draws a minimal title bar with a close button, for compositors that don't decorate the
+/// placeholders themselves.
+fn draw_decorations(canvas: &mut Canvas, title: &str) {
+    let width = canvas.width();
+    canvas.fill_rect(0, 0, width, TITLEBAR_HEIGHT, TITLEBAR_COLOR);
+
+    let text_y = (TITLEBAR_HEIGHT - GLYPH_HEIGHT * TITLE_SCALE) / 2;
+    let room = width.saturating_sub(TITLEBAR_HEIGHT + TITLE_PADDING * 2);
+    let title = truncate_to_width(title, room, TITLE_SCALE);
+    canvas.draw_text(
+        TITLE_PADDING as i32,
+        text_y as i32,
+        TITLE_SCALE,
+        TITLE_COLOR,
+        title,
+    );
+
+    // The close button is a cross in the right end of the title bar.
+    let inset = TITLEBAR_HEIGHT / 3;
+    let x0 = width.saturating_sub(TITLEBAR_HEIGHT) + inset;
+    let len = TITLEBAR_HEIGHT - inset * 2;
+    for i in 0..len {
+        let (x, y) = ((x0 + i) as i32, (inset + i) as i32);
+        canvas.fill_rect(x, y, 2, 2, TITLE_COLOR);
+        let x = (x0 + len - 1 - i) as i32;
+        canvas.fill_rect(x, y, 2, 2, TITLE_COLOR);
+    }
+}
+
+fn in_close_button(size: (u32, u32), position: (f64, f64)) -> bool {
+    let (width, _) = size;
+    let (x, y) = position;
+    x >= width.saturating_sub(TITLEBAR_HEIGHT) as f64 && y < TITLEBAR_HEIGHT as f64
 }
 
 impl CompositorHandler for Client {
@@ -241,10 +981,18 @@ impl CompositorHandler for Client {
     fn frame(
         &mut self,
         _conn: &Connection,
-        _queue_handle: &QueueHandle<Self>,
-        _surface: &wl_surface::WlSurface,
-        _time: u32,
+        queue_handle: &QueueHandle<Self>,
+        surface: &wl_surface::WlSurface,
+        time: u32,
     ) {
+        // Closed placeholders are gone from `windows`, so their animation simply stops here.
+        if let Some(idx) = self
+            .windows
+            .iter()
+            .position(|p| p.window.wl_surface() == surface)
+        {
+            self.draw(queue_handle, idx, time);
+        }
     }
 
     fn surface_enter(
@@ -300,22 +1048,164 @@ impl OutputHandler for Client {
 
 impl WindowHandler for Client {
     fn request_close(&mut self, _: &Connection, _: &QueueHandle<Self>, w: &Window) {
-        if let Some(idx) = self.windows.iter().position(|ow| ow == w) {
-            self.windows.swap_remove(idx);
+        if let Some(idx) = self.windows.iter().position(|p| p.window == *w) {
+            self.skip(idx);
         }
     }
 
     fn configure(
         &mut self,
-        conn: &Connection,
+        _conn: &Connection,
         queue_handle: &QueueHandle<Self>,
         window: &Window,
         configure: WindowConfigure,
         _serial: u32,
     ) {
+        let Some(idx) = self.windows.iter().position(|p| p.window == *window) else {
+            return;
+        };
         let width = configure.new_size.0.map(|v| v.get()).unwrap_or(256);
         let height = configure.new_size.1.map(|v| v.get()).unwrap_or(256);
-        self.draw(conn, queue_handle, window.wl_surface(), width, height);
+        let p = &mut self.windows[idx];
+        p.size = (width, height);
+        p.csd = configure.decoration_mode == DecorationMode::Client;
+        self.draw(queue_handle, idx, 0);
+
+        let p = &mut self.windows[idx];
+        if !p.mapped {
+            p.mapped = true;
+            // Until now the title was the application name, the one the window was created with.
+            p.window.set_title(p.xdg_title());
+            if p.csd {
+                log::debug!("no server side decorations for placeholder {}", p.token);
+            }
+            let _ = self.events.send(ClientEvent::Mapped {
+                token: p.token,
+                surface_id: window.wl_surface().id().protocol_id(),
+            });
+        }
+    }
+}
+
+/// Activation token request made on behalf of a placeholder.
+struct ActivationRequest {
+    token: u64,
+    surface: wl_surface::WlSurface,
+}
+
+impl RequestDataExt for ActivationRequest {
+    fn app_id(&self) -> Option<&str> {
+        None
+    }
+    fn seat_and_serial(&self) -> Option<(&wl_seat::WlSeat, u32)> {
+        None
+    }
+    fn surface(&self) -> Option<&wl_surface::WlSurface> {
+        Some(&self.surface)
+    }
+}
+
+impl ActivationHandler for Client {
+    type RequestData = ActivationRequest;
+    fn new_token(&mut self, activation: String, data: &ActivationRequest) {
+        let _ = self.events.send(ClientEvent::Activation {
+            token: data.token,
+            activation: Some(activation),
+        });
+    }
+}
+
+impl LayerShellHandler for Client {
+    fn closed(&mut self, _: &Connection, _: &QueueHandle<Self>, layer: &LayerSurface) {
+        if self.overlay.as_ref().is_some_and(|o| o.layer == *layer) {
+            self.overlay = None;
+        }
+    }
+
+    fn configure(
+        &mut self,
+        _conn: &Connection,
+        _queue_handle: &QueueHandle<Self>,
+        layer: &LayerSurface,
+        _configure: LayerSurfaceConfigure,
+        _serial: u32,
+    ) {
+        let Some(overlay) = self.overlay.as_mut().filter(|o| o.layer == *layer) else {
+            return;
+        };
+        overlay.configured = true;
+        self.draw_overlay();
+    }
+}
+
+impl SeatHandler for Client {
+    fn seat_state(&mut self) -> &mut SeatState {
+        &mut self.seat_state
+    }
+
+    fn new_seat(&mut self, _: &Connection, _: &QueueHandle<Self>, _: wl_seat::WlSeat) {}
+
+    fn new_capability(
+        &mut self,
+        _conn: &Connection,
+        queue_handle: &QueueHandle<Self>,
+        seat: wl_seat::WlSeat,
+        capability: Capability,
+    ) {
+        // The pointer is only needed to click the close button of client side decorations.
+        if capability == Capability::Pointer && self.pointer.is_none() {
+            match self.seat_state.get_pointer(queue_handle, &seat) {
+                Ok(pointer) => self.pointer = Some(pointer),
+                Err(e) => log::warn!("failed to get pointer: {}", e),
+            }
+        }
+    }
+
+    fn remove_capability(
+        &mut self,
+        _conn: &Connection,
+        _: &QueueHandle<Self>,
+        _: wl_seat::WlSeat,
+        capability: Capability,
+    ) {
+        if capability == Capability::Pointer {
+            if let Some(pointer) = self.pointer.take() {
+                pointer.release();
+            }
+        }
+    }
+
+    fn remove_seat(&mut self, _: &Connection, _: &QueueHandle<Self>, _: wl_seat::WlSeat) {}
+}
+
+impl PointerHandler for Client {
+    fn pointer_frame(
+        &mut self,
+        _conn: &Connection,
+        _queue_handle: &QueueHandle<Self>,
+        _pointer: &wl_pointer::WlPointer,
+        events: &[PointerEvent],
+    ) {
+        for event in events {
+            if !matches!(
+                event.kind,
+                PointerEventKind::Press {
+                    button: BTN_LEFT,
+                    ..
+                }
+            ) {
+                continue;
+            }
+            let idx = self.windows.iter().position(|p| {
+                p.csd
+                    && p.window.wl_surface() == &event.surface
+                    && in_close_button(p.size, event.position)
+            });
+            // Same as a close request from the compositor.
+            if let Some(idx) = idx {
+                self.skip(idx);
+            }
+        }
     }
 }
 
@@ -328,15 +1218,50 @@ impl ShmHandler for Client {
 delegate_compositor!(Client);
 delegate_output!(Client);
 delegate_shm!(Client);
+delegate_seat!(Client);
+delegate_pointer!(Client);
 
 delegate_xdg_shell!(Client);
+delegate_layer!(Client);
+delegate_activation!(Client, ActivationRequest);
 delegate_xdg_window!(Client);
 
+delegate_simple!(Client, WpSinglePixelBufferManagerV1, 1);
+delegate_simple!(Client, WpViewporter, 1);
+
 delegate_registry!(Client);
 
+impl Dispatch<WpViewport, ()> for Client {
+    fn event(
+        _: &mut Self,
+        _: &WpViewport,
+        _: <WpViewport as Proxy>::Event,
+        _: &(),
+        _: &Connection,
+        _: &QueueHandle<Self>,
+    ) {
+    }
+}
+
+/// Single pixel buffers are attached only once, so they are destroyed when released.
+impl Dispatch<wl_buffer::WlBuffer, ()> for Client {
+    fn event(
+        _: &mut Self,
+        buffer: &wl_buffer::WlBuffer,
+        event: wl_buffer::Event,
+        _: &(),
+        _: &Connection,
+        _: &QueueHandle<Self>,
+    ) {
+        if let wl_buffer::Event::Release = event {
+            buffer.destroy();
+        }
+    }
+}
+
 impl ProvidesRegistryState for Client {
     fn registry(&mut self) -> &mut RegistryState {
         &mut self.registry_state
     }
-    registry_handlers![OutputState,];
+    registry_handlers![OutputState, SeatState,];
 }
@@ -0,0 +1,192 @@
+use crate::eventlog::FORMAT_VERSION;
+use serde::Serialize;
+use std::path::PathBuf;
+use std::time::Duration;
+
+/// What became of a slot.
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "outcome", rename_all = "snake_case")]
+pub enum Outcome {
+    /// Its window took the place of the placeholder.
+    Filled { window: i64 },
+    /// The placeholder was closed before its window showed up.
+    Closed,
+    /// Its window did not show up in time.
+    TimedOut,
+    /// No placeholder could be made for it.
+    Failed { reason: String },
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Slot {
+    pub workspace: String,
+    pub id: String,
+    /// The layout file of its workspace, when several were merged.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub layout_file: Option<PathBuf>,
+    pub placeholder: Option<i64>,
+    /// Whether its app was spawned.
+    pub launched: bool,
+    #[serde(flatten)]
+    pub outcome: Outcome,
+    /// How long its placeholder waited for the window, if it came.
+    #[serde(serialize_with = "as_secs_opt")]
+    pub wait: Option<Duration>,
+}
+
+#[derive(Debug, Default, Serialize)]
+struct Counts {
+    created: usize,
+    filled: usize,
+    launched: usize,
+    closed: usize,
+    timed_out: usize,
+    failed: usize,
+}
+
+/// How long building a workspace took.
+#[derive(Debug, Serialize)]
+pub struct WorkspaceDuration {
+    pub name: String,
+    #[serde(serialize_with = "as_secs")]
+    pub build: Duration,
+}
+
+/// How long each phase of the restore took.
+#[derive(Debug, Default, Serialize)]
+pub struct Durations {
+    #[serde(serialize_with = "as_secs")]
+    pub build: Duration,
+    /// The part of `build` spent floating the windows already on the workspaces out of the way.
+    #[serde(serialize_with = "as_secs")]
+    pub detach: Duration,
+    /// The part of `build` spent on each workspace, in the order they were built.
+    pub workspaces: Vec<WorkspaceDuration>,
+    #[serde(serialize_with = "as_secs")]
+    pub spawn: Duration,
+    #[serde(serialize_with = "as_secs")]
+    pub swap: Duration,
+    #[serde(serialize_with = "as_secs")]
+    pub idle: Duration,
+}
+
+fn as_secs<S: serde::Serializer>(d: &Duration, s: S) -> Result<S::Ok, S::Error> {
+    s.serialize_f64(d.as_secs_f64())
+}
+
+fn as_secs_opt<S: serde::Serializer>(d: &Option<Duration>, s: S) -> Result<S::Ok, S::Error> {
+    match d {
+        Some(d) => s.serialize_some(&d.as_secs_f64()),
+        None => s.serialize_none(),
+    }
+}
+
+/// The outcome of a restore, printed as JSON with `--format json`.
+#[derive(Debug, Serialize)]
+pub struct Summary {
+    version: u32,
+    /// The exit status of swaystart.
+    status: u8,
+    counts: Counts,
+    slots: Vec<Slot>,
+    /// The steps that failed and were skipped.
+    failures: Vec<String>,
+    durations: Durations,
+}
+
+impl std::fmt::Display for Outcome {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Outcome::Filled { window } => write!(f, "filled by window {}", window),
+            Outcome::Closed => write!(f, "closed"),
+            Outcome::TimedOut => write!(f, "timed out"),
+            Outcome::Failed { reason } => write!(f, "failed: {}", reason),
+        }
+    }
+}
+
+impl Summary {
+    pub fn new(status: u8, slots: Vec<Slot>, failures: Vec<String>, durations: Durations) -> Self {
+        let mut counts = Counts::default();
+        for slot in &slots {
+            counts.created += slot.placeholder.is_some() as usize;
+            counts.launched += slot.launched as usize;
+            match slot.outcome {
+                Outcome::Filled { .. } => counts.filled += 1,
+                Outcome::Closed => counts.closed += 1,
+                Outcome::TimedOut => counts.timed_out += 1,
+                Outcome::Failed { .. } => counts.failed += 1,
+            }
+        }
+        Summary {
+            version: FORMAT_VERSION,
+            status,
+            counts,
+            slots,
+            failures,
+            durations,
+        }
+    }
+    /// Says in a few words how many windows were restored, and what became of the others.
+    pub fn headline(&self) -> String {
+        let mut parts = vec![format!(
+            "{}/{} windows",
+            self.counts.filled,
+            self.slots.len()
+        )];
+        for (count, what) in [
+            (self.counts.timed_out, "timed out"),
+            (self.counts.closed, "closed"),
+            (self.counts.failed, "failed"),
+        ] {
+            if count > 0 {
+                parts.push(format!("{} {}", count, what));
+            }
+        }
+        parts.join(", ")
+    }
+    /// How many slots got their window.
+    pub fn filled(&self) -> usize {
+        self.counts.filled
+    }
+    pub fn total(&self) -> usize {
+        self.slots.len()
+    }
+    /// A table of how long each phase of the restore took, and each slot waited, for
+    /// `--timings`.
+    pub fn timings(&self) -> String {
+        let d = &self.durations;
+        let mut rows = vec![
+            ("build".to_owned(), d.build),
+            ("  detach".to_owned(), d.detach),
+        ];
+        for w in &d.workspaces {
+            rows.push((format!("  workspace {}", w.name), w.build));
+        }
+        rows.push(("spawn".to_owned(), d.spawn));
+        rows.push(("swap".to_owned(), d.swap));
+        for slot in &self.slots {
+            if let Some(wait) = slot.wait {
+                rows.push((format!("  {} on {}", slot.id, slot.workspace), wait));
+            }
+        }
+        rows.push(("idle".to_owned(), d.idle));
+        let width = rows.iter().map(|(name, _)| name.len()).max().unwrap_or(0);
+        let mut table = String::new();
+        for (name, duration) in rows {
+            table.push_str(&format!(
+                "{:<width$}  {:>8.3}s\n",
+                name,
+                duration.as_secs_f64(),
+                width = width
+            ));
+        }
+        table
+    }
+    /// The slots that did not get their window.
+    pub fn unfilled(&self) -> impl Iterator<Item = &Slot> {
+        self.slots
+            .iter()
+            .filter(|s| !matches!(s.outcome, Outcome::Filled { .. }))
+    }
+}
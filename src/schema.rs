@@ -0,0 +1,360 @@
+use crate::screen::Screen;
+use crate::{ExecVia, FuzzyTitle, LayoutStyle, Output, Shell, Theme, MAX_LAYOUT_DEPTH};
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+use serde_json::Value;
+use std::collections::BTreeMap;
+
+/// Where a layout goes wrong: the JSON pointer to the value, and what is wrong with it.
+type Located = (String, String);
+
+/// Finds the part of `value` that can't be read as a layout, which serde can only tell about
+/// the whole layout because of the untagged enums.
+///
+/// Returns `None` if nothing more precise than serde's own error could be found.
+pub fn locate(value: &Value) -> Option<Located> {
+    output(value, "").err()
+}
+
+/// Checks that `value` reads as a `T`.
+fn check<T: DeserializeOwned>(value: &Value, pointer: &str) -> Result<(), Located> {
+    T::deserialize(value)
+        .map(|_| ())
+        .map_err(|e| (pointer.to_owned(), e.to_string()))
+}
+
+/// Fields a window filter can have, in a slot or a floating app.
+const FILTER_FIELDS: [&str; 13] = [
+    "title_contains",
+    "title_fuzzy",
+    "window_role",
+    "window_type",
+    "cmdline_contains",
+    "mark",
+    "shell",
+    "exclude",
+    "priority",
+    "fallback",
+    "fallback_after",
+    "same_workspace",
+    "allow_transient",
+];
+
+/// Fails on a field of the object `value` that is not among the `known` ones, which serde
+/// skips over.
+fn known_fields(value: &Value, pointer: &str, known: &[&[&str]]) -> Result<(), Located> {
+    let Some(object) = value.as_object() else {
+        return Ok(());
+    };
+    let known: Vec<&str> = known.iter().flat_map(|k| k.iter().copied()).collect();
+    match object.keys().find(|key| !known.contains(&key.as_str())) {
+        Some(key) => {
+            let expected: Vec<String> = known.iter().map(|k| format!("`{}`", k)).collect();
+            Err((
+                pointer.to_owned(),
+                format!(
+                    "unknown field `{}`, expected one of {}",
+                    key,
+                    expected.join(", ")
+                ),
+            ))
+        }
+        None => Ok(()),
+    }
+}
+
+/// The field `key` of the object `value`, if it is there.
+fn optional<'a>(value: &'a Value, pointer: &str, key: &str) -> Result<Option<&'a Value>, Located> {
+    match value.as_object() {
+        Some(object) => Ok(object.get(key)),
+        None => Err((
+            pointer.to_owned(),
+            format!("expected an object, found {}", value),
+        )),
+    }
+}
+
+/// The field `key` of the object `value`.
+fn required<'a>(value: &'a Value, pointer: &str, key: &str) -> Result<&'a Value, Located> {
+    optional(value, pointer, key)?
+        .ok_or_else(|| (pointer.to_owned(), format!("missing field `{}`", key)))
+}
+
+/// The elements of the array `value`, with their pointers.
+fn elements<'a>(
+    value: &'a Value,
+    pointer: &str,
+) -> Result<impl Iterator<Item = (String, &'a Value)>, Located> {
+    let Some(array) = value.as_array() else {
+        return Err((
+            pointer.to_owned(),
+            format!("expected an array, found {}", value),
+        ));
+    };
+    let pointer = pointer.to_owned();
+    Ok(array
+        .iter()
+        .enumerate()
+        .map(move |(i, v)| (format!("{}/{}", pointer, i), v)))
+}
+
+fn output(value: &Value, pointer: &str) -> Result<(), Located> {
+    known_fields(
+        value,
+        pointer,
+        &[&["name", "workspaces", "theme", "screen"]],
+    )?;
+    let name = required(value, pointer, "name")?;
+    check::<String>(name, &format!("{}/name", pointer))?;
+    if let Some(theme) = optional(value, pointer, "theme")? {
+        check::<Theme>(theme, &format!("{}/theme", pointer))?;
+    }
+    if let Some(screen) = optional(value, pointer, "screen")? {
+        check::<Screen>(screen, &format!("{}/screen", pointer))?;
+    }
+    let workspaces = required(value, pointer, "workspaces")?;
+    for (pointer, w) in elements(workspaces, &format!("{}/workspaces", pointer))? {
+        workspace(w, &pointer)?;
+    }
+    Ok(())
+}
+
+fn workspace(value: &Value, pointer: &str) -> Result<(), Located> {
+    known_fields(value, pointer, &[&["name", "style", "layout", "floating"]])?;
+    let name = required(value, pointer, "name")?;
+    check::<String>(name, &format!("{}/name", pointer))?;
+    let style = required(value, pointer, "style")?;
+    check::<LayoutStyle>(style, &format!("{}/style", pointer))?;
+    let layout = required(value, pointer, "layout")?;
+    self::layout(layout, &format!("{}/layout", pointer))?;
+    if let Some(floating) = optional(value, pointer, "floating")? {
+        for (pointer, app) in elements(floating, &format!("{}/floating", pointer))? {
+            match app {
+                Value::String(_) => {}
+                Value::Object(_) => {
+                    known_fields(app, &pointer, &[&APP_FIELDS, &FILTER_FIELDS])?;
+                    app_with_id(app, &pointer)?;
+                    window_filter(app, &pointer)?;
+                }
+                _ => {
+                    let msg = "expected an app name, or an object with app and id";
+                    return Err((pointer, msg.to_owned()));
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+fn layout(value: &Value, pointer: &str) -> Result<(), Located> {
+    known_fields(value, pointer, &[&["style", "slots"]])?;
+    let style = required(value, pointer, "style")?;
+    check::<LayoutStyle>(style, &format!("{}/style", pointer))?;
+    let slots = required(value, pointer, "slots")?;
+    for (pointer, s) in elements(slots, &format!("{}/slots", pointer))? {
+        slot(s, &pointer)?;
+    }
+    Ok(())
+}
+
+fn slot(value: &Value, pointer: &str) -> Result<(), Located> {
+    let slot = ["size", "content", "exec_via", "env"];
+    known_fields(value, pointer, &[&slot, &FILTER_FIELDS])?;
+    if let Some(size) = optional(value, pointer, "size")? {
+        check::<f64>(size, &format!("{}/size", pointer))?;
+    }
+    if let Some(exec_via) = optional(value, pointer, "exec_via")? {
+        check::<Option<ExecVia>>(exec_via, &format!("{}/exec_via", pointer))?;
+    }
+    if let Some(env) = optional(value, pointer, "env")? {
+        check::<BTreeMap<String, String>>(env, &format!("{}/env", pointer))?;
+    }
+    window_filter(value, pointer)?;
+    let content = required(value, pointer, "content")?;
+    let pointer = format!("{}/content", pointer);
+    match content {
+        Value::String(_) => Ok(()),
+        Value::Object(o) if o.contains_key("style") || o.contains_key("slots") => {
+            layout(content, &pointer)
+        }
+        Value::Object(o) if o.contains_key("app") || o.contains_key("id") => {
+            known_fields(content, &pointer, &[&APP_FIELDS])?;
+            app_with_id(content, &pointer)
+        }
+        _ => Err((
+            pointer,
+            "expected an app name, an object with app and id, or a layout with style and slots"
+                .to_owned(),
+        )),
+    }
+}
+
+/// Fields of an app given with the id its windows are matched on.
+const APP_FIELDS: [&str; 3] = ["app", "id", "exec_via"];
+
+/// An app given with the id its windows are matched on.
+fn app_with_id(value: &Value, pointer: &str) -> Result<(), Located> {
+    let app = required(value, pointer, "app")?;
+    check::<String>(app, &format!("{}/app", pointer))?;
+    let id = required(value, pointer, "id")?;
+    check::<String>(id, &format!("{}/id", pointer))?;
+    if let Some(exec_via) = optional(value, pointer, "exec_via")? {
+        check::<Option<ExecVia>>(exec_via, &format!("{}/exec_via", pointer))?;
+    }
+    Ok(())
+}
+
+fn window_filter(value: &Value, pointer: &str) -> Result<(), Located> {
+    for key in [
+        "title_contains",
+        "window_role",
+        "window_type",
+        "cmdline_contains",
+        "mark",
+    ] {
+        if let Some(v) = optional(value, pointer, key)? {
+            check::<Option<String>>(v, &format!("{}/{}", pointer, key))?;
+        }
+    }
+    if let Some(fuzzy) = optional(value, pointer, "title_fuzzy")? {
+        check::<Option<FuzzyTitle>>(fuzzy, &format!("{}/title_fuzzy", pointer))?;
+    }
+    if let Some(shell) = optional(value, pointer, "shell")? {
+        check::<Option<Shell>>(shell, &format!("{}/shell", pointer))?;
+    }
+    if let Some(priority) = optional(value, pointer, "priority")? {
+        check::<i32>(priority, &format!("{}/priority", pointer))?;
+    }
+    if let Some(fallback) = optional(value, pointer, "fallback")? {
+        check::<Vec<String>>(fallback, &format!("{}/fallback", pointer))?;
+    }
+    if let Some(after) = optional(value, pointer, "fallback_after")? {
+        check::<Option<u64>>(after, &format!("{}/fallback_after", pointer))?;
+    }
+    for key in ["same_workspace", "allow_transient"] {
+        if let Some(v) = optional(value, pointer, key)? {
+            check::<bool>(v, &format!("{}/{}", pointer, key))?;
+        }
+    }
+    if let Some(exclude) = optional(value, pointer, "exclude")? {
+        if !exclude.is_null() {
+            let pointer = format!("{}/exclude", pointer);
+            known_fields(exclude, &pointer, &[&FILTER_FIELDS])?;
+            window_filter(exclude, &pointer)?;
+        }
+    }
+    Ok(())
+}
+
+/// Reads the layout `text`, pointing at the part of it that is wrong if it can't be read.
+///
+/// Unknown fields, which serde would skip over, are refused as the typos they likely are.
+pub fn parse(text: &str) -> anyhow::Result<Output> {
+    let output = serde_json::from_str(text).map_err(|e| explain(text, e))?;
+    // The layout was read, so the fields are all that can be wrong.
+    if let Some((pointer, msg)) = locate(&serde_json::from_str(text)?) {
+        anyhow::bail!(
+            "at {}: {}",
+            if pointer.is_empty() { "/" } else { &pointer },
+            msg
+        );
+    }
+    Ok(output)
+}
+
+/// Makes `err`, from parsing the layout `text`, point at the part of the layout that is wrong.
+pub fn explain(text: &str, err: serde_json::Error) -> anyhow::Error {
+    // serde_json gives up on deep nesting before the layouts are deep enough to be refused.
+    if err.to_string().starts_with("recursion limit exceeded") {
+        return anyhow::anyhow!(
+            "layouts are nested too deep, at most {} are supported (line {} column {})",
+            MAX_LAYOUT_DEPTH,
+            err.line(),
+            err.column()
+        );
+    }
+    if !err.is_data() {
+        return err.into();
+    }
+    let located = Value::deserialize(&mut serde_json::Deserializer::from_str(text))
+        .ok()
+        .and_then(|value| locate(&value));
+    match located {
+        Some((pointer, msg)) => anyhow::anyhow!(
+            "at {}: {} (line {} column {})",
+            if pointer.is_empty() { "/" } else { &pointer },
+            msg,
+            err.line(),
+            err.column()
+        ),
+        None => err.into(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    /// The error reading a layout of the workspace `1` with the slots `slots`.
+    fn error(slots: Value) -> String {
+        let layout = json!({
+            "name": "OUT",
+            "workspaces": [{
+                "name": "1",
+                "style": "splith",
+                "layout": { "style": "splith", "slots": slots },
+            }],
+        });
+        // Pretty, for the errors to be on lines of their own.
+        let text = serde_json::to_string_pretty(&layout).unwrap();
+        parse(&text).unwrap_err().to_string()
+    }
+
+    #[test]
+    fn refuses_unknown_fields() {
+        let e = error(json!([{ "content": "foot", "sise": 0.5 }]));
+        assert!(
+            e.starts_with(
+                "at /workspaces/0/layout/slots/0: unknown field `sise`, expected one of `size`, "
+            ),
+            "{}",
+            e
+        );
+        let e = error(json!([{ "content": "foot", "exclude": { "title": "vim" } }]));
+        assert!(
+            e.starts_with("at /workspaces/0/layout/slots/0/exclude: unknown field `title`"),
+            "{}",
+            e
+        );
+    }
+
+    #[test]
+    fn points_at_values_of_the_wrong_type() {
+        let e = error(json!([{ "content": "foot" }, { "content": "mpv", "size": "half" }]));
+        assert!(
+            e.starts_with("at /workspaces/0/layout/slots/1/size: invalid type: string \"half\""),
+            "{}",
+            e
+        );
+        assert!(e.ends_with(')'), "{}", e);
+        let e = error(json!([{ "content": { "style": "diagonal", "slots": [] } }]));
+        assert!(
+            e.starts_with(
+                "at /workspaces/0/layout/slots/0/content/style: unknown variant `diagonal`"
+            ),
+            "{}",
+            e
+        );
+    }
+
+    #[test]
+    fn points_at_slots_missing_their_content() {
+        let e = error(json!([{ "content": "foot" }, { "size": 0.5 }]));
+        assert!(
+            e.starts_with("at /workspaces/0/layout/slots/1: missing field `content` (line "),
+            "{}",
+            e
+        );
+    }
+}
@@ -0,0 +1,186 @@
+use crate::Output;
+use serde::Deserialize;
+
+/// What the screen of an output of a layout is like, to find it again under another name.
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct Screen {
+    pub make: Option<String>,
+    pub model: Option<String>,
+    pub serial: Option<String>,
+    /// The resolution of its mode, in pixels.
+    pub width: Option<i32>,
+    pub height: Option<i32>,
+    /// Its position among the outputs.
+    pub x: Option<i32>,
+    pub y: Option<i32>,
+}
+
+/// How much `output` looks like `screen`, 0 if not at all, and why.
+fn score(screen: &Screen, output: &swayipc::Output) -> (u32, Vec<&'static str>) {
+    let same = |a: &Option<String>, b: &str| a.as_deref().is_some_and(|a| !a.is_empty() && a == b);
+    let model = same(&screen.make, &output.make) && same(&screen.model, &output.model);
+    let mode = output.current_mode.as_ref();
+    let mut score = 0;
+    let mut reasons = Vec::new();
+    if model && same(&screen.serial, &output.serial) {
+        score += 8;
+        reasons.push("serial");
+    }
+    if model {
+        score += 4;
+        reasons.push("make and model");
+    }
+    if screen.width.is_some()
+        && screen.width == mode.map(|m| m.width)
+        && screen.height == mode.map(|m| m.height)
+    {
+        score += 2;
+        reasons.push("resolution");
+    }
+    if screen.x.is_some() && screen.x == Some(output.rect.x) && screen.y == Some(output.rect.y) {
+        score += 1;
+        reasons.push("position");
+    }
+    (score, reasons)
+}
+
+/// The active output to restore the layout of `output` on, when none goes by its name: the one
+/// that looks the most like its screen, if a single one does.
+pub(crate) fn resolve(output: &Output, outputs: &[swayipc::Output]) -> Option<String> {
+    let active: Vec<&swayipc::Output> = outputs.iter().filter(|o| o.active).collect();
+    // The name can also be the make, model and serial of the output, as in sway commands.
+    let named = active.iter().any(|o| {
+        o.name == output.name || format!("{} {} {}", o.make, o.model, o.serial) == output.name
+    });
+    if named {
+        return None;
+    }
+    let screen = output.screen.as_ref()?;
+    let mut scores: Vec<(u32, Vec<&str>, &swayipc::Output)> = active
+        .into_iter()
+        .map(|o| {
+            let (score, reasons) = score(screen, o);
+            (score, reasons, o)
+        })
+        .filter(|(score, _, _)| *score > 0)
+        .collect();
+    scores.sort_by_key(|(score, _, _)| std::cmp::Reverse(*score));
+    match scores.as_slice() {
+        [] => None,
+        [(best, ..), (second, _, o), ..] if best == second => {
+            log::warn!(
+                "output {} is not connected, and {} and others look as much like it",
+                output.name,
+                o.name
+            );
+            None
+        }
+        [(_, reasons, o), ..] => {
+            log::debug!(
+                "{} matches the screen of {} by {:?}",
+                o.name,
+                output.name,
+                reasons
+            );
+            eprintln!(
+                "warning: output {} is not connected, restoring its layout on {} (same {})",
+                output.name,
+                o.name,
+                reasons.join(", ")
+            );
+            Some(o.name.clone())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    /// The layout of the output `DP-1`, whose screen was like `screen`.
+    fn layout(screen: serde_json::Value) -> Output {
+        serde_json::from_value(json!({ "name": "DP-1", "workspaces": [], "screen": screen }))
+            .unwrap()
+    }
+
+    /// An active Dell output `name` with the serial `serial`, in `mode` at `(x, y)`, turned by
+    /// `transform`.
+    fn connected(
+        name: &str,
+        serial: &str,
+        mode: (i32, i32),
+        (x, y): (i32, i32),
+        transform: &str,
+    ) -> swayipc::Output {
+        let (width, height) = match transform {
+            "90" | "270" => (mode.1, mode.0),
+            _ => mode,
+        };
+        serde_json::from_value(json!({
+            "name": name,
+            "make": "Dell",
+            "model": "U2720Q",
+            "serial": serial,
+            "active": true,
+            "dpms": true,
+            "primary": false,
+            "transform": transform,
+            "current_mode": { "width": mode.0, "height": mode.1, "refresh": 60000 },
+            "rect": { "x": x, "y": y, "width": width, "height": height },
+        }))
+        .unwrap()
+    }
+
+    fn dell(width: i32, height: i32) -> serde_json::Value {
+        json!({ "make": "Dell", "model": "U2720Q", "width": width, "height": height })
+    }
+
+    #[test]
+    fn resolution_tells_apart_screens_of_the_same_model() {
+        let outputs = [
+            connected("DP-2", "A", (2560, 1440), (0, 0), "normal"),
+            connected("DP-3", "B", (3840, 2160), (2560, 0), "normal"),
+        ];
+        assert_eq!(
+            resolve(&layout(dell(3840, 2160)), &outputs).as_deref(),
+            Some("DP-3")
+        );
+        // With a resolution neither has, both look as much like it.
+        assert_eq!(resolve(&layout(dell(1920, 1080)), &outputs), None);
+        // Unless the serial gives it away.
+        let mut screen = dell(1920, 1080);
+        screen["serial"] = json!("A");
+        assert_eq!(resolve(&layout(screen), &outputs).as_deref(), Some("DP-2"));
+    }
+
+    #[test]
+    fn rotated_screen_keeps_the_resolution_of_its_mode() {
+        let outputs = [
+            connected("DP-2", "A", (2560, 1440), (0, 0), "normal"),
+            connected("DP-3", "B", (3840, 2160), (2560, 0), "90"),
+        ];
+        assert_eq!(outputs[1].rect.width, 2160);
+        assert_eq!(
+            resolve(&layout(dell(3840, 2160)), &outputs).as_deref(),
+            Some("DP-3")
+        );
+    }
+
+    #[test]
+    fn finds_nothing_for_a_screen_that_is_not_there() {
+        let mut outputs = vec![connected("DP-2", "A", (2560, 1440), (0, 0), "normal")];
+        let other = json!({ "make": "LG", "model": "27UK850", "width": 3840, "height": 2160 });
+        assert_eq!(resolve(&layout(other), &outputs), None);
+        // Nor without knowing what the screen was like.
+        let unknown: Output =
+            serde_json::from_value(json!({ "name": "DP-1", "workspaces": [] })).unwrap();
+        assert_eq!(resolve(&unknown, &outputs), None);
+        // An output that is off is not a candidate.
+        outputs[0].active = false;
+        assert_eq!(resolve(&layout(dell(2560, 1440)), &outputs), None);
+        // And an output that goes by the name of the layout needs no other.
+        let outputs = [connected("DP-1", "A", (2560, 1440), (0, 0), "normal")];
+        assert_eq!(resolve(&layout(dell(1920, 1080)), &outputs), None);
+    }
+}
@@ -0,0 +1,229 @@
+use crate::placeholder::ClientHandle;
+use crate::status::Error;
+use crate::{connect, diff, load_layout, restore, theme, Backend, LayoutVisitor, RestoreOptions};
+use crate::{DIFF_THRESHOLD, EVENT_TIMEOUT};
+use anyhow::{Context, Result};
+use serde_json::json;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command, Stdio};
+use std::sync::mpsc::channel;
+use std::time::{Duration, Instant};
+
+/// How long sway may take to start, and the windows to show up.
+const STARTUP_TIMEOUT: Duration = Duration::from_secs(10);
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+/// The desktop file of the apps of the layout, which are never launched.
+const DESKTOP_FILE: &str = "[Desktop Entry]
+Type=Application
+Name=swaystart selftest
+Exec=true
+NoDisplay=true
+";
+/// The app ids of the slots, played by windows of a placeholder client of their own.
+const APPS: [&str; 2] = ["selftest-a", "selftest-b"];
+
+/// A sway with the headless backend, stopped along with its files when dropped.
+struct Compositor {
+    child: Child,
+    dir: PathBuf,
+}
+
+impl Drop for Compositor {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+        let _ = std::fs::remove_dir_all(&self.dir);
+    }
+}
+
+/// Why the selftest could not run, as opposed to it failing.
+struct Skip(String);
+
+/// Starts sway with the headless backend, its IPC socket and wayland display in `dir`.
+///
+/// Returns the compositor and the name of its wayland display.
+fn start(dir: PathBuf) -> Result<(Compositor, String), Skip> {
+    let config = dir.join("config");
+    let display = dir.join("display");
+    let stderr = dir.join("stderr");
+    let socket = dir.join("sway.sock");
+    let write = |path: &Path, text: String| {
+        std::fs::write(path, text).map_err(|e| Skip(format!("writing {}: {}", path.display(), e)))
+    };
+    write(
+        &config,
+        format!(
+            "output HEADLESS-1 resolution 1280x720\nexec printf %s \"$WAYLAND_DISPLAY\" > {}\n",
+            display.display()
+        ),
+    )?;
+    let log = std::fs::File::create(&stderr)
+        .map_err(|e| Skip(format!("creating {}: {}", stderr.display(), e)))?;
+    let child = Command::new("sway")
+        .arg("--config")
+        .arg(&config)
+        .env("WLR_BACKENDS", "headless")
+        .env("WLR_LIBINPUT_NO_DEVICES", "1")
+        // sway takes the socket path from SWAYSOCK as long as nothing is there.
+        .env("SWAYSOCK", &socket)
+        .env_remove("WAYLAND_DISPLAY")
+        .env_remove("DISPLAY")
+        .stdout(Stdio::null())
+        .stderr(log)
+        .spawn();
+    let child = match child {
+        Ok(child) => child,
+        Err(e) if e.kind() == ErrorKind::NotFound => {
+            return Err(Skip("sway is not installed".to_owned()))
+        }
+        Err(e) => return Err(Skip(format!("sway could not be started: {}", e))),
+    };
+    let mut compositor = Compositor { child, dir };
+    let deadline = Instant::now() + STARTUP_TIMEOUT;
+    loop {
+        let name = std::fs::read_to_string(&display).unwrap_or_default();
+        if socket.exists() && !name.is_empty() {
+            std::env::set_var("SWAYSOCK", &socket);
+            std::env::set_var("WAYLAND_DISPLAY", &name);
+            return Ok((compositor, name));
+        }
+        let exited = matches!(compositor.child.try_wait(), Ok(Some(_)));
+        if exited || Instant::now() >= deadline {
+            let output = std::fs::read_to_string(&stderr).unwrap_or_default();
+            let last = output.lines().last().unwrap_or("no output");
+            return Err(Skip(format!(
+                "sway did not start with the headless backend: {}",
+                last
+            )));
+        }
+        std::thread::sleep(POLL_INTERVAL);
+    }
+}
+
+/// Waits for the placeholders of all the `APPS` to show up.
+fn wait_placeholders() -> Result<()> {
+    let mut conn = connect()?;
+    let deadline = Instant::now() + STARTUP_TIMEOUT;
+    loop {
+        let tree = conn.get_tree()?;
+        let all = APPS.iter().all(|app| {
+            let app_id = format!("swaystart-{}", app);
+            tree.find_as_ref(|n| n.app_id.as_deref() == Some(app_id.as_str()))
+                .is_some()
+        });
+        if all {
+            return Ok(());
+        }
+        if Instant::now() >= deadline {
+            anyhow::bail!(
+                "the placeholders did not show up within {:?}",
+                STARTUP_TIMEOUT
+            );
+        }
+        std::thread::sleep(POLL_INTERVAL);
+    }
+}
+
+/// Restores a layout in a sway of its own with the headless backend, and checks the result with
+/// `diff`.
+///
+/// Without sway or its headless backend, the selftest is skipped and reported as such.
+pub fn run() -> Result<(), Error> {
+    let dir = std::env::temp_dir().join(format!("swaystart-selftest-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).with_context(|| format!("creating {}", dir.display()))?;
+    let (compositor, display) = match start(dir.clone()) {
+        Ok(started) => started,
+        Err(Skip(reason)) => {
+            let _ = std::fs::remove_dir_all(&dir);
+            eprintln!("selftest skipped: {}", reason);
+            return Ok(());
+        }
+    };
+    log::info!("headless sway running on {}", display);
+
+    // The placeholders are titled after the desktop file of their app, which has to be found.
+    let applications = compositor.dir.join("applications");
+    std::fs::create_dir_all(&applications)?;
+    std::fs::write(
+        applications.join("swaystart-selftest.desktop"),
+        DESKTOP_FILE,
+    )?;
+    let data_dirs =
+        std::env::var("XDG_DATA_DIRS").unwrap_or_else(|_| "/usr/local/share:/usr/share".to_owned());
+    std::env::set_var(
+        "XDG_DATA_DIRS",
+        format!("{}:{}", compositor.dir.display(), data_dirs),
+    );
+
+    let layout_file = compositor.dir.join("layout.json");
+    let app = |id: &str| json!({ "app": "swaystart-selftest", "id": id });
+    let layout = json!({
+        "name": "HEADLESS-1",
+        "workspaces": [{
+            "name": "1",
+            "style": "splith",
+            "layout": {
+                "style": "splith",
+                "slots": [
+                    { "size": 2, "content": app(APPS[0]) },
+                    { "size": 1, "content": app(APPS[1]) },
+                ],
+            },
+        }],
+    });
+    std::fs::write(&layout_file, serde_json::to_string_pretty(&layout)?)
+        .with_context(|| format!("writing {}", layout_file.display()))?;
+
+    // The apps open their windows once their placeholders are there, and keep them open until
+    // the result is checked.
+    let (close, closed) = channel::<()>();
+    let apps = std::thread::spawn(move || -> Result<()> {
+        wait_placeholders()?;
+        let client = ClientHandle::new(Backend::Sway, false, theme::Theme::default());
+        for app_id in APPS {
+            let token = client.new_window(app_id, app_id, app_id);
+            client.wait_mapped(token, EVENT_TIMEOUT)?;
+        }
+        let _ = closed.recv();
+        Ok(())
+    });
+
+    let res = restore(RestoreOptions {
+        layout_files: vec![layout_file.clone()],
+        no_animation: true,
+        backend: Some(Backend::Sway),
+        no_overlay: true,
+        fail_fast: true,
+        strict_timeouts: true,
+        swap_timeout: Some(STARTUP_TIMEOUT.as_secs()),
+        ..Default::default()
+    })
+    .and_then(|(_, res)| res)
+    .context("restoring the layout");
+    let differences = res.and_then(|()| {
+        let mut differ = diff::Differ::new(connect()?.get_tree()?, DIFF_THRESHOLD);
+        differ.visit_output(&load_layout(&layout_file)?)?;
+        Ok(differ.differences)
+    });
+    let _ = close.send(());
+    let apps = apps
+        .join()
+        .map_err(|_| anyhow::anyhow!("the app client panicked"))?;
+    let differences = differences?;
+    apps.context("opening the app windows")?;
+    drop(compositor);
+
+    if !differences.is_empty() {
+        for d in &differences {
+            eprintln!("{}", d);
+        }
+        return Err(anyhow::anyhow!(
+            "the restored session differs from the layout in {} ways",
+            differences.len()
+        )
+        .into());
+    }
+    println!("selftest passed");
+    Ok(())
+}
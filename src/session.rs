@@ -0,0 +1,490 @@
+use crate::eventlog::FORMAT_VERSION;
+use crate::events::{CompositorGone, EventSource};
+use crate::ipc::{CommandFailed, CommandRunner};
+use crate::WindowFilter;
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::{HashMap, VecDeque};
+use std::fs::File;
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+use swayipc::{Event, Node, Workspace};
+
+/// The tree when the recording started.
+const TREE: &str = "tree.json";
+/// What went between swaystart and sway, one [`Line`] per line.
+const SESSION: &str = "session.jsonl";
+
+/// Something that went between swaystart and sway.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+enum Entry {
+    Command {
+        command: String,
+        /// What sway replied, if it refused the command.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        failed: Option<CommandFailed>,
+        /// Why the command could not be sent.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        error: Option<String>,
+    },
+    Tree {
+        reply: Value,
+    },
+    Workspaces {
+        reply: Value,
+    },
+    Event {
+        event: Value,
+    },
+    /// No event came before the deadline.
+    Timeout,
+    /// The subscription was established again, events may have been missed.
+    Reconnected,
+    /// The layout is built, the windows are swapped in from here on.
+    Swap {
+        mapping: HashMap<String, Vec<i64>>,
+        /// The filters of the placeholders that have one.
+        #[serde(default)]
+        filters: HashMap<i64, WindowFilter>,
+        /// Whether windows went to the first slot that could take them.
+        #[serde(default)]
+        first_match: bool,
+        claimed: Vec<Claim>,
+        /// The events received while building, and not handled yet.
+        backlog: Vec<Value>,
+    },
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct Line {
+    /// Seconds since the recording started.
+    time: f64,
+    version: u32,
+    #[serde(flatten)]
+    entry: Entry,
+}
+
+/// A placeholder set aside by the builder for a window that was on its workspace.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Claim {
+    pub placeholder: i64,
+    pub window: i64,
+    pub matcher: Option<String>,
+}
+
+/// Removes the titles of the windows from `value`, a node or an event as sent by sway, and
+/// from the nodes in it.
+fn scrub(value: &mut Value) {
+    match value {
+        Value::Object(object) => {
+            let view = matches!(
+                object.get("type").and_then(Value::as_str),
+                Some("con" | "floating_con")
+            );
+            if view {
+                object.insert("name".to_owned(), Value::Null);
+                if let Some(Value::Object(props)) = object.get_mut("window_properties") {
+                    props.insert("title".to_owned(), Value::Null);
+                }
+            }
+            object.values_mut().for_each(scrub);
+        }
+        Value::Array(array) => array.iter_mut().for_each(scrub),
+        _ => {}
+    }
+}
+
+struct Writer {
+    out: BufWriter<File>,
+    failed: bool,
+}
+
+/// Writes the commands swaystart sends to sway and the events it gets back to a directory,
+/// for `swaystart replay`.
+///
+/// Every line is flushed as it is written, so that the recording is complete even if swaystart
+/// gets killed.
+#[derive(Clone)]
+pub struct Recorder {
+    writer: Arc<Mutex<Writer>>,
+    start: Instant,
+    no_titles: bool,
+}
+
+impl Recorder {
+    /// Starts a recording in `dir`, along with `tree` as it is before anything is done.
+    ///
+    /// With `no_titles`, the titles of the windows are left out.
+    pub fn create(dir: &Path, tree: &Node, no_titles: bool) -> Result<Self> {
+        std::fs::create_dir_all(dir).with_context(|| format!("creating {}", dir.display()))?;
+        let path = dir.join(SESSION);
+        let out = File::create(&path).with_context(|| format!("creating {}", path.display()))?;
+        let recorder = Recorder {
+            writer: Arc::new(Mutex::new(Writer {
+                out: BufWriter::new(out),
+                failed: false,
+            })),
+            start: Instant::now(),
+            no_titles,
+        };
+        let path = dir.join(TREE);
+        std::fs::write(&path, serde_json::to_string_pretty(&recorder.value(tree))?)
+            .with_context(|| format!("writing {}", path.display()))?;
+        Ok(recorder)
+    }
+
+    fn value(&self, value: &impl Serialize) -> Value {
+        let mut value = serde_json::to_value(value).unwrap_or_default();
+        if self.no_titles {
+            scrub(&mut value);
+        }
+        value
+    }
+
+    fn record(&self, entry: Entry) {
+        let line = Line {
+            time: self.start.elapsed().as_secs_f64(),
+            version: FORMAT_VERSION,
+            entry,
+        };
+        let mut writer = self.writer.lock().unwrap_or_else(|e| e.into_inner());
+        if writer.failed {
+            return;
+        }
+        let res = serde_json::to_writer(&mut writer.out, &line)
+            .map_err(std::io::Error::from)
+            .and_then(|_| writeln!(writer.out))
+            .and_then(|_| writer.out.flush());
+        if let Err(e) = res {
+            log::warn!("failed to record the session: {}", e);
+            writer.failed = true;
+        }
+    }
+
+    /// Records the commands sent to `inner`, and its replies.
+    pub fn runner(&self, inner: Box<dyn CommandRunner>) -> Box<dyn CommandRunner> {
+        Box::new(Recording {
+            inner,
+            recorder: self.clone(),
+        })
+    }
+
+    /// Records the events received from `inner`.
+    pub fn source(&self, inner: Box<dyn EventSource>) -> Box<dyn EventSource> {
+        Box::new(Recording {
+            inner,
+            recorder: self.clone(),
+        })
+    }
+
+    /// Records the start of the swap, with what the swapper starts from.
+    pub fn swap<'a>(
+        &self,
+        mapping: &HashMap<String, Vec<i64>>,
+        filters: &HashMap<i64, WindowFilter>,
+        first_match: bool,
+        claimed: Vec<Claim>,
+        backlog: impl Iterator<Item = &'a Event>,
+    ) {
+        self.record(Entry::Swap {
+            mapping: mapping.clone(),
+            filters: filters.clone(),
+            first_match,
+            claimed,
+            backlog: backlog.map(|e| self.value(e)).collect(),
+        });
+    }
+}
+
+/// A command runner or an event source, recording what goes through it.
+struct Recording<T: ?Sized> {
+    inner: Box<T>,
+    recorder: Recorder,
+}
+
+impl CommandRunner for Recording<dyn CommandRunner> {
+    fn run_command(&mut self, cmd: &str) -> Result<()> {
+        let res = self.inner.run_command(cmd);
+        let (failed, error) = match &res {
+            Ok(()) => (None, None),
+            Err(e) => match e.downcast_ref::<CommandFailed>() {
+                Some(failed) => (Some(failed.clone()), None),
+                None => (None, Some(format!("{:#}", e))),
+            },
+        };
+        self.recorder.record(Entry::Command {
+            command: cmd.to_owned(),
+            failed,
+            error,
+        });
+        res
+    }
+    fn get_tree(&mut self) -> Result<Node> {
+        let tree = self.inner.get_tree()?;
+        let reply = self.recorder.value(&tree);
+        self.recorder.record(Entry::Tree { reply });
+        Ok(tree)
+    }
+    fn get_workspaces(&mut self) -> Result<Vec<Workspace>> {
+        let workspaces = self.inner.get_workspaces()?;
+        let reply = self.recorder.value(&workspaces);
+        self.recorder.record(Entry::Workspaces { reply });
+        Ok(workspaces)
+    }
+}
+
+impl EventSource for Recording<dyn EventSource> {
+    fn recv_until(&mut self, deadline: Option<Instant>) -> Result<Option<Event>> {
+        let event = self.inner.recv_until(deadline)?;
+        let entry = match &event {
+            Some(event) => Entry::Event {
+                event: self.recorder.value(event),
+            },
+            None => Entry::Timeout,
+        };
+        self.recorder.record(entry);
+        Ok(event)
+    }
+    fn take_reconnected(&mut self) -> bool {
+        let reconnected = self.inner.take_reconnected();
+        if reconnected {
+            self.recorder.record(Entry::Reconnected);
+        }
+        reconnected
+    }
+}
+
+/// The swap of a recorded session, to go through again without sway.
+pub struct Replay {
+    pub mapping: HashMap<String, Vec<i64>>,
+    pub filters: HashMap<i64, WindowFilter>,
+    pub first_match: bool,
+    pub claimed: Vec<Claim>,
+    pub backlog: Vec<Event>,
+    /// Answers the requests of the swapper as sway did.
+    pub runner: Box<dyn CommandRunner>,
+    /// Gives the events of the recording, in order.
+    pub source: Box<dyn EventSource>,
+    /// Whether the swap gave up waiting for windows.
+    pub timed_out: bool,
+}
+
+/// Reads the session recorded in `dir` for a replay.
+///
+/// With `step`, the replay waits for Enter before each event.
+pub fn load(dir: &Path, step: bool) -> Result<Replay> {
+    let path = dir.join(SESSION);
+    let file = File::open(&path).with_context(|| format!("opening {}", path.display()))?;
+    let mut swap = None;
+    let mut requests = VecDeque::new();
+    let mut events = VecDeque::new();
+    for (i, line) in BufReader::new(file).lines().enumerate() {
+        let line = line.with_context(|| format!("reading {}", path.display()))?;
+        let line: Line = serde_json::from_str(&line)
+            .with_context(|| format!("{}: line {}", path.display(), i + 1))?;
+        if line.version != FORMAT_VERSION {
+            anyhow::bail!(
+                "{} was recorded with version {} of the format, this swaystart reads version {}",
+                path.display(),
+                line.version,
+                FORMAT_VERSION
+            );
+        }
+        match line.entry {
+            entry @ Entry::Swap { .. } => swap = Some(entry),
+            // Only what happened during the swap is replayed.
+            _ if swap.is_none() => {}
+            entry @ (Entry::Command { .. } | Entry::Tree { .. } | Entry::Workspaces { .. }) => {
+                requests.push_back(entry)
+            }
+            entry => events.push_back(entry),
+        }
+    }
+    let Some(Entry::Swap {
+        mapping,
+        filters,
+        first_match,
+        claimed,
+        backlog,
+    }) = swap
+    else {
+        anyhow::bail!("the session in {} did not get to the swap", dir.display());
+    };
+    // Waking up without an event only ends the swap when it runs out of time, which can only
+    // be the last thing it waited for.
+    let timed_out = matches!(events.back(), Some(Entry::Timeout));
+    events.retain(|e| !matches!(e, Entry::Timeout));
+    if timed_out {
+        events.push_back(Entry::Timeout);
+    }
+    let backlog = backlog
+        .into_iter()
+        .map(serde_json::from_value)
+        .collect::<Result<_, _>>()
+        .context("reading the events received while building")?;
+    Ok(Replay {
+        mapping,
+        filters,
+        first_match,
+        claimed,
+        backlog,
+        runner: Box::new(ReplayRunner { requests }),
+        source: Box::new(ReplaySource {
+            events,
+            reconnected: false,
+            step,
+            count: 0,
+        }),
+        timed_out,
+    })
+}
+
+/// Error returned when the replay does not go as the recording.
+#[derive(Debug)]
+pub struct Diverged {
+    pub expected: String,
+    pub got: String,
+}
+
+impl std::fmt::Display for Diverged {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "the replay diverged from the recording: {} instead of {}",
+            self.got, self.expected
+        )
+    }
+}
+
+impl std::error::Error for Diverged {}
+
+fn describe(entry: Option<&Entry>) -> String {
+    match entry {
+        Some(Entry::Command { command, .. }) => format!("command '{}'", command),
+        Some(Entry::Tree { .. }) => "getting the tree".to_owned(),
+        Some(Entry::Workspaces { .. }) => "getting the workspaces".to_owned(),
+        Some(_) => unreachable!("events are not requests"),
+        None => "nothing more".to_owned(),
+    }
+}
+
+struct ReplayRunner {
+    requests: VecDeque<Entry>,
+}
+
+impl ReplayRunner {
+    fn next(&mut self, got: String, expected: impl Fn(&Entry) -> bool) -> Result<Entry> {
+        match self.requests.pop_front() {
+            Some(entry) if expected(&entry) => Ok(entry),
+            entry => Err(Diverged {
+                expected: describe(entry.as_ref()),
+                got,
+            }
+            .into()),
+        }
+    }
+}
+
+impl CommandRunner for ReplayRunner {
+    fn run_command(&mut self, cmd: &str) -> Result<()> {
+        let entry = self.next(
+            format!("command '{}'", cmd),
+            |e| matches!(e, Entry::Command { command, .. } if command == cmd),
+        )?;
+        let Entry::Command { failed, error, .. } = entry else {
+            unreachable!("checked by next");
+        };
+        match (failed, error) {
+            (Some(failed), _) => {
+                log::debug!("cmd: '{}': {}", failed.command, failed.message);
+                Err(failed.into())
+            }
+            (None, Some(error)) => Err(anyhow::Error::msg(error)),
+            (None, None) => {
+                log::debug!("cmd: '{}': ok", cmd);
+                Ok(())
+            }
+        }
+    }
+    fn get_tree(&mut self) -> Result<Node> {
+        let got = "getting the tree".to_owned();
+        let Entry::Tree { reply } = self.next(got, |e| matches!(e, Entry::Tree { .. }))? else {
+            unreachable!("checked by next");
+        };
+        Ok(serde_json::from_value(reply)?)
+    }
+    fn get_workspaces(&mut self) -> Result<Vec<Workspace>> {
+        let got = "getting the workspaces".to_owned();
+        let Entry::Workspaces { reply } =
+            self.next(got, |e| matches!(e, Entry::Workspaces { .. }))?
+        else {
+            unreachable!("checked by next");
+        };
+        Ok(serde_json::from_value(reply)?)
+    }
+}
+
+struct ReplaySource {
+    events: VecDeque<Entry>,
+    reconnected: bool,
+    step: bool,
+    /// How many events were given so far.
+    count: usize,
+}
+
+impl EventSource for ReplaySource {
+    /// Returns the next event of the recording, whatever the deadline.
+    ///
+    /// Fails with [`CompositorGone`] once the recording is over, as it ends where sway went away
+    /// if the swap did not get to its end.
+    fn recv_until(&mut self, _deadline: Option<Instant>) -> Result<Option<Event>> {
+        loop {
+            match self.events.pop_front() {
+                Some(Entry::Event { event }) => {
+                    let event: Event = serde_json::from_value(event)?;
+                    self.count += 1;
+                    if self.step {
+                        step(self.count, &event)?;
+                    }
+                    return Ok(Some(event));
+                }
+                Some(Entry::Reconnected) => self.reconnected = true,
+                Some(Entry::Timeout) => return Ok(None),
+                Some(_) => unreachable!("requests are not events"),
+                None => return Err(CompositorGone.into()),
+            }
+        }
+    }
+    fn take_reconnected(&mut self) -> bool {
+        std::mem::take(&mut self.reconnected)
+    }
+}
+
+/// Shows the event about to be handled, and waits for Enter.
+fn step(count: usize, event: &Event) -> Result<()> {
+    match event {
+        Event::Window(w) => eprint!(
+            "event {}: window {:?} con_id={} app_id={:?}",
+            count, w.change, w.container.id, w.container.app_id
+        ),
+        event => eprint!("event {}: {:?}", count, event),
+    }
+    let mut line = String::new();
+    std::io::stdin().read_line(&mut line)?;
+    Ok(())
+}
+
+/// The slots of `mapping` with their placeholders, sorted, for printing the outcome of a
+/// replay.
+pub fn placeholders(mapping: &HashMap<String, Vec<i64>>) -> Vec<(&str, i64)> {
+    let mut placeholders: Vec<(&str, i64)> = mapping
+        .iter()
+        .flat_map(|(id, v)| v.iter().map(move |c| (id.as_str(), *c)))
+        .collect();
+    placeholders.sort();
+    placeholders
+}
@@ -0,0 +1,126 @@
+use crate::{
+    matcher, tree, LayoutVisitor, Output, Slot, SlotContent, VisitAction, VisitContext, Workspace,
+};
+use anyhow::Result;
+use swayipc::{Node, NodeType};
+
+/// Renders a layout, or the sway tree with [`Renderer::render_tree`], as indented lines of text.
+pub struct Renderer {
+    /// Nodes nested deeper than this are left out.
+    max_depth: Option<usize>,
+    /// Lines are cut to this many characters.
+    width: Option<usize>,
+    pub lines: Vec<String>,
+}
+
+impl Renderer {
+    pub fn new(max_depth: Option<usize>, width: Option<usize>) -> Renderer {
+        Renderer {
+            max_depth,
+            width,
+            lines: Vec::new(),
+        }
+    }
+    fn too_deep(&self, depth: usize) -> bool {
+        self.max_depth.is_some_and(|max| depth > max)
+    }
+    fn push(&mut self, depth: usize, text: &str) {
+        if self.too_deep(depth) {
+            return;
+        }
+        let mut line = format!("{}{}", "  ".repeat(depth), text);
+        if let Some(width) = self.width {
+            if line.chars().count() > width {
+                line = line.chars().take(width.saturating_sub(1)).collect();
+                line.push('…');
+            }
+        }
+        self.lines.push(line);
+    }
+    /// Renders the outputs under `root` and everything on them.
+    pub fn render_tree(&mut self, root: &Node) {
+        for (node, path, _) in tree::iter_nodes(root, tree::Order::Document) {
+            // The root itself is not worth a line.
+            let Some(depth) = path.len().checked_sub(1) else {
+                continue;
+            };
+            let name = node.name.as_deref().unwrap_or("");
+            let text = match node.node_type {
+                NodeType::Output => format!("output {}", name),
+                NodeType::Workspace => match node.num {
+                    Some(num) if num.to_string() != name => {
+                        format!("workspace {} (number {}) [{}]", name, num, layout(node))
+                    }
+                    _ => format!("workspace {} [{}]", name, layout(node)),
+                },
+                NodeType::Con | NodeType::FloatingCon => {
+                    let percent = node
+                        .percent
+                        .map_or(String::new(), |p| format!("{:.0}% ", p * 100.));
+                    let floating = if node.node_type == NodeType::FloatingCon {
+                        "floating "
+                    } else {
+                        ""
+                    };
+                    if tree::is_view(node) {
+                        format!(
+                            "{}{}{} \"{}\"",
+                            percent,
+                            floating,
+                            matcher(node).unwrap_or("?"),
+                            name
+                        )
+                    } else {
+                        format!("{}{}{}", percent, floating, layout(node))
+                    }
+                }
+                other => format!("{:?}", other).to_lowercase(),
+            };
+            self.push(depth, &text);
+        }
+    }
+}
+
+/// The layout of the children of `node`, as sway names it.
+fn layout(node: &Node) -> String {
+    format!("{:?}", node.layout).to_lowercase()
+}
+
+impl LayoutVisitor for Renderer {
+    fn on_output(&mut self, output: &Output, _ctx: &VisitContext) -> Result<VisitAction> {
+        self.push(0, &format!("output {}", output.name));
+        Ok(VisitAction::Continue)
+    }
+    fn on_workspace(&mut self, workspace: &Workspace, _ctx: &VisitContext) -> Result<VisitAction> {
+        self.push(
+            1,
+            &format!("workspace {} [{}]", workspace.name, workspace.style),
+        );
+        self.push(2, &workspace.layout.style.to_string());
+        Ok(VisitAction::Continue)
+    }
+    fn on_slot(&mut self, slot: &Slot, ctx: &VisitContext) -> Result<VisitAction> {
+        // Slots are under the output, the workspace and the layouts containing them.
+        let depth = ctx.depth + 2;
+        let percent = slot.size * 100.;
+        let text = match &slot.content {
+            SlotContent::Container(layout) => format!("{:.0}% {}", percent, layout.style),
+            SlotContent::App(app) => format!("{:.0}% {}", percent, app),
+            SlotContent::AppWithId { app, id } => format!("{:.0}% {} ({})", percent, id, app),
+        };
+        self.push(depth, &text);
+        if self.too_deep(depth + 1) {
+            return Ok(VisitAction::SkipChildren);
+        }
+        Ok(VisitAction::Continue)
+    }
+    fn on_floating_app(&mut self, app: &str, id: &str, _ctx: &VisitContext) -> Result<()> {
+        let text = if app == id {
+            format!("floating {}", app)
+        } else {
+            format!("floating {} ({})", id, app)
+        };
+        self.push(2, &text);
+        Ok(())
+    }
+}
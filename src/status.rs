@@ -0,0 +1,268 @@
+use crate::events::{CompositorGone, TimedOut};
+use crate::ipc::CommandFailed;
+use crate::placeholder::ClientFailed;
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+
+/// Explanation of the exit statuses, shown in the help.
+pub const HELP: &str = "Exit status:
+  0  success
+  1  unexpected error
+  2  invalid command line or config
+  3  sway went away during the restore
+  4  sway could not be reached
+  5  the layout is invalid, or does not fit the session according to check
+  6  the restore finished, but some of its steps failed
+  7  another swaystart is restoring in the same session
+  8  the restore was cancelled
+  9  the restore finished, but some slots were left without their window
+  10 a window or an event from sway did not come in time
+  11 the placeholder client failed";
+
+/// Error returned when the restore went through, skipping some failed steps.
+#[derive(Debug)]
+pub struct Incomplete {
+    pub failed: usize,
+}
+
+impl std::fmt::Display for Incomplete {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} steps of the restore failed", self.failed)
+    }
+}
+
+impl std::error::Error for Incomplete {}
+
+/// Error returned when the restore went through, but the windows of some slots never showed up.
+#[derive(Debug)]
+pub struct Unmatched {
+    pub slots: usize,
+}
+
+impl std::fmt::Display for Unmatched {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} slots were left without their window", self.slots)
+    }
+}
+
+impl std::error::Error for Unmatched {}
+
+/// Error returned when another instance holds the lock for the session.
+#[derive(Debug)]
+pub struct Locked {
+    pub pid: Option<u32>,
+}
+
+impl std::fmt::Display for Locked {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.pid {
+            Some(pid) => write!(f, "swaystart is already running as process {}", pid),
+            None => write!(f, "swaystart is already running"),
+        }
+    }
+}
+
+impl std::error::Error for Locked {}
+
+/// Error returned when the restore is cancelled, from the terminal or over D-Bus.
+#[derive(Debug)]
+pub struct Cancelled;
+
+impl std::fmt::Display for Cancelled {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "the restore was cancelled")
+    }
+}
+
+impl std::error::Error for Cancelled {}
+
+/// An error with its causes, for the variants of [`Error`] that can come from anywhere.
+pub type Source = Box<dyn std::error::Error + Send + Sync>;
+
+/// What went wrong, as returned by the functions of the crate.
+///
+/// The errors of the crate come out as they were raised, the context added on their way up is
+/// only logged.
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error(transparent)]
+    CompositorGone(#[from] CompositorGone),
+    #[error("could not connect to sway")]
+    SwayUnreachable(#[source] swayipc::Error),
+    /// A request to sway failed.
+    #[error(transparent)]
+    Ipc(#[from] swayipc::Error),
+    #[error(transparent)]
+    CommandFailed(#[from] CommandFailed),
+    /// The layout file at `path`, `-` for stdin, can't be read or is not valid.
+    #[error("invalid layout {}", path.display())]
+    InvalidLayout {
+        path: PathBuf,
+        #[source]
+        source: Source,
+    },
+    /// The config can't be read or is not valid, or the options don't go together.
+    #[error(transparent)]
+    InvalidConfig(Source),
+    #[error(transparent)]
+    Incomplete(#[from] Incomplete),
+    #[error(transparent)]
+    Unmatched(#[from] Unmatched),
+    #[error(transparent)]
+    Locked(#[from] Locked),
+    #[error(transparent)]
+    Placeholder(#[from] ClientFailed),
+    /// An awaited event did not arrive.
+    #[error(transparent)]
+    TimedOut(#[from] TimedOut),
+    #[error(transparent)]
+    Cancelled(#[from] Cancelled),
+    #[error(transparent)]
+    Other(Source),
+}
+
+impl Error {
+    /// The error for a layout file that can't be read or is not valid, for `reason`.
+    pub(crate) fn invalid_layout(path: &Path, reason: impl Into<Source>) -> Error {
+        Error::InvalidLayout {
+            path: path.to_owned(),
+            source: reason.into(),
+        }
+    }
+}
+
+impl From<anyhow::Error> for Error {
+    fn from(err: anyhow::Error) -> Self {
+        let err = match err.downcast::<Error>() {
+            Ok(e) => return e,
+            Err(err) => err,
+        };
+        // The errors of the crate are kept whole, the context around them is only logged.
+        let context = format!("{:#}", err);
+        let res = err
+            .downcast::<CompositorGone>()
+            .map(Error::from)
+            .or_else(|err| err.downcast::<Incomplete>().map(Error::from))
+            .or_else(|err| err.downcast::<Unmatched>().map(Error::from))
+            .or_else(|err| err.downcast::<Locked>().map(Error::from))
+            .or_else(|err| err.downcast::<ClientFailed>().map(Error::from))
+            .or_else(|err| err.downcast::<TimedOut>().map(Error::from))
+            .or_else(|err| err.downcast::<Cancelled>().map(Error::from))
+            .or_else(|err| err.downcast::<CommandFailed>().map(Error::from))
+            .or_else(|err| err.downcast::<swayipc::Error>().map(Error::from));
+        match res {
+            Ok(e) => {
+                log::debug!("{}", context);
+                e
+            }
+            Err(err) => Error::Other(err.into()),
+        }
+    }
+}
+
+impl From<std::io::Error> for Error {
+    fn from(err: std::io::Error) -> Self {
+        Error::Other(err.into())
+    }
+}
+
+impl From<serde_json::Error> for Error {
+    fn from(err: serde_json::Error) -> Self {
+        Error::Other(err.into())
+    }
+}
+
+/// `err` followed by its causes, separated by colons.
+pub fn describe(err: &Error) -> String {
+    let mut text = err.to_string();
+    let mut source = std::error::Error::source(err);
+    while let Some(e) = source {
+        text.push_str(": ");
+        text.push_str(&e.to_string());
+        source = e.source();
+    }
+    text
+}
+
+/// The exit status for a run that ended with `err`.
+pub fn of(err: &Error) -> ExitCode {
+    ExitCode::from(code(err))
+}
+
+/// The number of the exit status for `err`, as listed in [`HELP`].
+pub fn code(err: &Error) -> u8 {
+    match err {
+        Error::InvalidConfig(_) => 2,
+        Error::CompositorGone(_) => 3,
+        Error::SwayUnreachable(_) => 4,
+        Error::InvalidLayout { .. } => 5,
+        Error::Incomplete(_) => 6,
+        Error::Locked(_) => 7,
+        Error::Cancelled(_) => 8,
+        Error::Unmatched(_) => 9,
+        Error::TimedOut(_) => 10,
+        Error::Placeholder(_) => 11,
+        Error::Ipc(_) | Error::CommandFailed(_) | Error::Other(_) => 1,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::Context;
+    use std::time::Duration;
+
+    #[test]
+    fn takes_the_error_out_of_its_context() {
+        let err = Err::<(), _>(Locked { pid: Some(42) })
+            .context("taking the lock")
+            .unwrap_err();
+        let err = Error::from(err);
+        assert!(matches!(err, Error::Locked(Locked { pid: Some(42) })));
+        assert_eq!(code(&err), 7);
+        assert_eq!(
+            err.to_string(),
+            "swaystart is already running as process 42"
+        );
+    }
+
+    #[test]
+    fn keeps_the_variant_through_anyhow() {
+        let err = Error::InvalidConfig("unknown group".into());
+        let err = Error::from(anyhow::Error::from(err));
+        assert!(matches!(err, Error::InvalidConfig(_)));
+        let err = Error::from(anyhow::Error::from(err).context("restoring"));
+        assert!(matches!(err, Error::InvalidConfig(_)));
+        assert_eq!(code(&err), 2);
+        assert_eq!(describe(&err), "unknown group");
+    }
+
+    #[test]
+    fn describes_an_invalid_layout_with_its_causes() {
+        let reason = anyhow::anyhow!("unknown field `slot`").context("parsing the layout");
+        let err = Error::invalid_layout(Path::new("work.json"), reason);
+        assert_eq!(code(&err), 5);
+        assert_eq!(
+            describe(&err),
+            "invalid layout work.json: parsing the layout: unknown field `slot`"
+        );
+    }
+
+    #[test]
+    fn timeouts_and_placeholder_failures_have_their_own_status() {
+        let timeout = Duration::from_secs(1);
+        let err = Error::from(anyhow::Error::from(TimedOut { timeout }).context("waiting"));
+        assert!(matches!(err, Error::TimedOut(_)));
+        assert_eq!(code(&err), 10);
+        let err = Error::from(anyhow::Error::from(ClientFailed::Panicked));
+        assert!(matches!(err, Error::Placeholder(ClientFailed::Panicked)));
+        assert_eq!(code(&err), 11);
+    }
+
+    #[test]
+    fn other_errors_are_unexpected() {
+        let err = Error::from(anyhow::anyhow!("something else"));
+        assert!(matches!(err, Error::Other(_)));
+        assert_eq!(code(&err), 1);
+    }
+}
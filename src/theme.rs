@@ -0,0 +1,199 @@
+use crate::canvas::GLYPH_HEIGHT;
+use anyhow::Result;
+use serde::Deserialize;
+use std::convert::TryFrom;
+
+const MAX_BORDER_WIDTH: u32 = 64;
+const MAX_CORNER_RADIUS: u32 = 256;
+const MAX_FONT_SIZE: u32 = 128;
+
+/// A color, written as `#rgb`, `#rrggbb` or `#rrggbbaa` in the layout file.
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(try_from = "String")]
+pub struct Color {
+    pub r: u8,
+    pub g: u8,
+    pub b: u8,
+    /// Opacity, colors without an alpha component are opaque.
+    pub a: u8,
+}
+
+impl TryFrom<String> for Color {
+    type Error = String;
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        let invalid = || format!("invalid color '{}', expected #rgb, #rrggbb or #rrggbbaa", s);
+        let hex = s.strip_prefix('#').ok_or_else(invalid)?;
+        if ![3, 6, 8].contains(&hex.len()) || !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+            return Err(invalid());
+        }
+        // Each digit of the short form stands for two.
+        let hex: String = if hex.len() == 3 {
+            hex.chars().flat_map(|c| [c, c]).collect()
+        } else {
+            hex.to_owned()
+        };
+        let channel = |i: usize| u8::from_str_radix(&hex[i..i + 2], 16).map_err(|_| invalid());
+        Ok(Color {
+            r: channel(0)?,
+            g: channel(2)?,
+            b: channel(4)?,
+            a: if hex.len() == 8 { channel(6)? } else { 0xff },
+        })
+    }
+}
+
+impl Color {
+    /// The color as a premultiplied `0xAARRGGBB` pixel with the given opacity, on top of its own.
+    pub fn pixel(self, alpha: u8) -> u32 {
+        let a = alpha as u32 * self.a as u32 / 255;
+        let premultiply = |c: u8| c as u32 * a / 255;
+        (a << 24) | (premultiply(self.r) << 16) | (premultiply(self.g) << 8) | premultiply(self.b)
+    }
+}
+
+/// Appearance of the placeholder windows.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Theme {
+    /// Fill of the placeholder, transparent if unset.
+    pub background: Option<Color>,
+    /// Color of the label with the application name, not drawn if unset.
+    pub foreground: Option<Color>,
+    /// Color of the waiting animation and of launched placeholders.
+    pub accent: Color,
+    /// Color of placeholders whose application did not show up in time.
+    pub error: Color,
+    pub border_color: Option<Color>,
+    pub border_width: u32,
+    pub corner_radius: u32,
+    /// Height in pixels of the label, a multiple of the built-in font height.
+    pub font_size: u32,
+}
+
+impl Default for Theme {
+    fn default() -> Self {
+        Theme {
+            background: None,
+            foreground: None,
+            accent: Color {
+                r: 0x80,
+                g: 0x80,
+                b: 0x80,
+                a: 0xff,
+            },
+            error: Color {
+                r: 0xbf,
+                g: 0x61,
+                b: 0x6a,
+                a: 0xff,
+            },
+            border_color: None,
+            border_width: 0,
+            corner_radius: 0,
+            font_size: GLYPH_HEIGHT * 2,
+        }
+    }
+}
+
+impl Theme {
+    pub fn validate(&self) -> Result<()> {
+        if self.border_width > MAX_BORDER_WIDTH {
+            anyhow::bail!(
+                "theme: border_width must be at most {}, got {}",
+                MAX_BORDER_WIDTH,
+                self.border_width
+            );
+        }
+        if self.border_width > 0 && self.border_color.is_none() {
+            anyhow::bail!("theme: border_width is set but border_color is missing");
+        }
+        if self.corner_radius > MAX_CORNER_RADIUS {
+            anyhow::bail!(
+                "theme: corner_radius must be at most {}, got {}",
+                MAX_CORNER_RADIUS,
+                self.corner_radius
+            );
+        }
+        let mut font_sizes = (GLYPH_HEIGHT..=MAX_FONT_SIZE).step_by(GLYPH_HEIGHT as usize);
+        if !font_sizes.any(|size| size == self.font_size) {
+            anyhow::bail!(
+                "theme: font_size must be a multiple of {} between {} and {}, got {}",
+                GLYPH_HEIGHT,
+                GLYPH_HEIGHT,
+                MAX_FONT_SIZE,
+                self.font_size
+            );
+        }
+        Ok(())
+    }
+    /// Whether placeholders drawn with this theme cover their whole surface.
+    pub fn is_opaque(&self) -> bool {
+        self.background.is_some_and(|c| c.a == 0xff) && self.corner_radius == 0
+    }
+    /// Scale factor of the built-in font for the label.
+    pub fn font_scale(&self) -> u32 {
+        self.font_size / GLYPH_HEIGHT
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn color(s: &str) -> Result<Color, String> {
+        Color::try_from(s.to_owned())
+    }
+
+    fn rgba(r: u8, g: u8, b: u8, a: u8) -> Color {
+        Color { r, g, b, a }
+    }
+
+    #[test]
+    fn parses_colors() {
+        assert_eq!(color("#2e3440"), Ok(rgba(0x2e, 0x34, 0x40, 0xff)));
+        assert_eq!(color("#2E3440cc"), Ok(rgba(0x2e, 0x34, 0x40, 0xcc)));
+        assert_eq!(color("#f0a"), Ok(rgba(0xff, 0x00, 0xaa, 0xff)));
+        for invalid in [
+            "",
+            "2e3440",
+            "#",
+            "#2e34",
+            "#2e3440c",
+            "#2e3440cc0",
+            "#2e344g",
+            "#ééé",
+        ] {
+            assert_eq!(
+                color(invalid),
+                Err(format!(
+                    "invalid color '{}', expected #rgb, #rrggbb or #rrggbbaa",
+                    invalid
+                ))
+            );
+        }
+    }
+
+    #[test]
+    fn pixels_are_premultiplied() {
+        assert_eq!(rgba(0x2e, 0x34, 0x40, 0xff).pixel(0xff), 0xff2e3440);
+        // Half of the color is left, on both the color and the opacity of the pixel.
+        assert_eq!(rgba(0xff, 0x80, 0x00, 0x80).pixel(0xff), 0x80804000);
+        assert_eq!(rgba(0xff, 0x80, 0x00, 0xff).pixel(0x80), 0x80804000);
+        // The opacity given applies on top of the one of the color.
+        assert_eq!(rgba(0xff, 0xff, 0xff, 0x80).pixel(0x80), 0x40404040);
+        assert_eq!(rgba(0xff, 0xff, 0xff, 0xff).pixel(0), 0);
+    }
+
+    #[test]
+    fn only_a_square_opaque_background_is_opaque() {
+        let theme = |background, corner_radius| Theme {
+            background,
+            corner_radius,
+            ..Default::default()
+        };
+        assert!(!Theme::default().is_opaque());
+        assert!(theme(Some(rgba(0, 0, 0, 0xff)), 0).is_opaque());
+        assert!(!theme(Some(rgba(0, 0, 0, 0xcc)), 0).is_opaque());
+        assert!(!theme(Some(rgba(0, 0, 0, 0xff)), 8).is_opaque());
+    }
+}
@@ -0,0 +1,214 @@
+use anyhow::Result;
+use swayipc::{Node, NodeType};
+
+/// Name of the output that sway keeps the scratchpad workspace on.
+const SCRATCHPAD_OUTPUT: &str = "__i3";
+
+/// The ancestors of a node, from the root of the tree down to its parent.
+pub type NodePath<'a> = Vec<&'a Node>;
+
+/// Whether `node` is the hidden output holding the scratchpad workspace.
+pub fn is_scratchpad(node: &Node) -> bool {
+    node.node_type == NodeType::Output && node.name.as_deref() == Some(SCRATCHPAD_OUTPUT)
+}
+
+/// Whether the nodes under `node` are none of swaystart's business.
+///
+/// That is the case of the dock areas where i3 keeps its bars, and of node types added to sway
+/// after this was written, which can't be assumed to hold windows. With `strict`, such a node
+/// type is an error instead.
+fn is_foreign(node: &Node, strict: bool) -> Result<bool> {
+    match node.node_type {
+        NodeType::Root
+        | NodeType::Output
+        | NodeType::Workspace
+        | NodeType::Con
+        | NodeType::FloatingCon => Ok(false),
+        NodeType::Dockarea => Ok(true),
+        _ if strict => anyhow::bail!("node {} is of unknown type {:?}", node.id, node.node_type),
+        _ => Ok(true),
+    }
+}
+
+/// Fails if a node under `root` is of a type added to sway after this was written.
+pub fn check_types(root: &Node) -> Result<()> {
+    for (n, _, _) in iter_nodes(root, Order::Document) {
+        is_foreign(n, true)?;
+    }
+    Ok(())
+}
+
+/// The app_id of the window `node`, or the class of its X11 window under Xwayland or i3.
+pub fn app_id(node: &Node) -> Option<&str> {
+    node.app_id
+        .as_deref()
+        .or_else(|| node.window_properties.as_ref()?.class.as_deref())
+}
+
+/// Whether `node` is a window.
+pub fn is_view(node: &Node) -> bool {
+    node.nodes.is_empty()
+        && node.floating_nodes.is_empty()
+        && matches!(node.node_type, NodeType::Con | NodeType::FloatingCon)
+}
+
+/// The order in which the children of a node are visited.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Order {
+    /// The tiled children as laid out, then the floating ones.
+    Document,
+    /// The most recently focused child first, then the children missing from the node's focus
+    /// list in document order.
+    Focus,
+}
+
+/// Iterates depth first over `root` and all the nodes under it, with their ancestors and their
+/// rank in the focus list of their parent.
+///
+/// A node comes before its children, which come in the given `order`. The rank is `None` for
+/// `root` and for nodes their parent has no focus information about.
+pub fn iter_nodes(
+    root: &Node,
+    order: Order,
+) -> impl Iterator<Item = (&Node, NodePath<'_>, Option<usize>)> {
+    let mut stack = vec![(root, 0, None)];
+    let mut path: NodePath = Vec::new();
+    std::iter::from_fn(move || {
+        let (node, depth, rank) = stack.pop()?;
+        path.truncate(depth);
+        let ancestors = path.clone();
+        let rank_of = |c: &Node| node.focus.iter().position(|id| *id == c.id);
+        let mut children: Vec<_> = node
+            .nodes
+            .iter()
+            .chain(&node.floating_nodes)
+            .map(|c| (c, depth + 1, rank_of(c)))
+            .collect();
+        if order == Order::Focus {
+            // The sort is stable, so children without a rank stay in document order.
+            children.sort_by_key(|(_, _, rank)| rank.unwrap_or(usize::MAX));
+        }
+        stack.extend(children.into_iter().rev());
+        path.push(node);
+        Some((node, ancestors, rank))
+    })
+}
+
+/// Iterates over the windows under `root`, tiled or floating, leaving out the ones in the
+/// scratchpad unless `scratchpad` is set.
+pub fn iter_views(root: &Node, order: Order, scratchpad: bool) -> impl Iterator<Item = &Node> {
+    iter_nodes(root, order)
+        .filter(move |(n, path, _)| {
+            // Without strict, node types are never an error.
+            let foreign = |n| is_foreign(n, false).unwrap_or(true);
+            if foreign(n) {
+                log::debug!("skipping the {:?} node {}", n.node_type, n.id);
+            }
+            is_view(n)
+                && !path
+                    .iter()
+                    .any(|a| foreign(a) || (!scratchpad && is_scratchpad(a)))
+        })
+        .map(|(n, _, _)| n)
+}
+
+/// The workspace holding the node with the given `id`, if it is not in the scratchpad.
+pub fn find_workspace(root: &Node, id: i64) -> Option<&Node> {
+    let (_, path, _) = iter_nodes(root, Order::Document).find(|(n, _, _)| n.id == id)?;
+    if path.iter().copied().any(is_scratchpad) {
+        return None;
+    }
+    path.into_iter()
+        .rev()
+        .find(|n| n.node_type == NodeType::Workspace)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ipc::mock::node;
+    use serde_json::json;
+
+    /// A workspace with a tiled and a floating window, next to an i3 dock area holding a bar.
+    fn session() -> Node {
+        let dock = node(json!({
+            "id": 3,
+            "type": "dockarea",
+            "nodes": [node(json!({ "id": 30, "name": "i3bar" }))],
+        }));
+        let workspace = node(json!({
+            "id": 4,
+            "type": "workspace",
+            "name": "1",
+            "nodes": [node(json!({ "id": 40, "app_id": "foot" }))],
+            "floating_nodes": [node(json!({ "id": 41, "type": "floating_con", "app_id": "mpv" }))],
+        }));
+        let output =
+            node(json!({ "id": 2, "type": "output", "name": "OUT", "nodes": [dock, workspace] }));
+        node(json!({ "id": 1, "type": "root", "nodes": [output] }))
+    }
+
+    #[test]
+    fn finds_floating_windows_and_skips_dock_areas() {
+        let root = session();
+        let views: Vec<i64> = iter_views(&root, Order::Document, false)
+            .map(|n| n.id)
+            .collect();
+        assert_eq!(views, [40, 41]);
+        assert_eq!(
+            find_workspace(&root, 41).and_then(|w| w.name.as_deref()),
+            Some("1")
+        );
+    }
+
+    #[test]
+    fn dock_areas_are_known_node_types() {
+        let root = session();
+        check_types(&root).unwrap();
+        let dock = &root.nodes[0].nodes[0];
+        assert!(is_foreign(dock, true).unwrap());
+        assert!(!is_foreign(&root.nodes[0].nodes[1].floating_nodes[0], true).unwrap());
+    }
+
+    /// A workspace with the window 40, and the scratchpad holding the window 50, as sway has it.
+    fn with_scratchpad() -> Node {
+        let scratch = node(json!({
+            "id": 5,
+            "type": "workspace",
+            "name": "__i3_scratch",
+            "floating_nodes": [node(json!({ "id": 50, "type": "floating_con", "app_id": "foot" }))],
+        }));
+        let hidden = node(json!({ "id": 2, "type": "output", "name": "__i3", "nodes": [scratch] }));
+        let workspace = node(json!({
+            "id": 4,
+            "type": "workspace",
+            "name": "1",
+            "nodes": [node(json!({ "id": 40, "app_id": "foot" }))],
+        }));
+        let output =
+            node(json!({ "id": 3, "type": "output", "name": "OUT", "nodes": [workspace] }));
+        node(json!({ "id": 1, "type": "root", "nodes": [hidden, output] }))
+    }
+
+    #[test]
+    fn the_scratchpad_is_the_hidden_output() {
+        let root = with_scratchpad();
+        let hidden = &root.nodes[0];
+        assert!(is_scratchpad(hidden));
+        assert!(!is_scratchpad(&hidden.nodes[0]));
+        assert!(!is_scratchpad(&root.nodes[1]));
+    }
+
+    #[test]
+    fn leaves_scratchpad_windows_out_unless_asked() {
+        let root = with_scratchpad();
+        let views = |scratchpad| -> Vec<i64> {
+            iter_views(&root, Order::Document, scratchpad)
+                .map(|n| n.id)
+                .collect()
+        };
+        assert_eq!(views(false), [40]);
+        assert_eq!(views(true), [50, 40]);
+        assert!(find_workspace(&root, 50).is_none());
+    }
+}
@@ -0,0 +1,470 @@
+use crate::report::{Outcome, Slot};
+use crate::{
+    is_selected, Layout, LayoutVisitor, LayoutVisitorMut, Output, SlotContent, VisitAction,
+    VisitContext, Workspace,
+};
+use anyhow::Result;
+use crossterm::cursor::{Hide, MoveToPreviousLine, Show};
+use crossterm::event::{Event, KeyCode, KeyEventKind, KeyModifiers};
+use crossterm::style::Print;
+use crossterm::terminal::{self, Clear, ClearType};
+use crossterm::{execute, queue};
+use std::cell::Cell;
+use std::collections::{HashMap, HashSet};
+use std::io::{IsTerminal, Write};
+use std::ops::Range;
+
+const HELP: &str = "space: restore  e: exec now  enter: start  q: cancel";
+
+/// An app of the layout, as listed by the picker.
+struct App {
+    id: String,
+    app: String,
+    floating: bool,
+    restore: bool,
+    exec: bool,
+}
+
+enum Row {
+    Workspace { name: String, apps: Range<usize> },
+    App(usize),
+}
+
+/// Lists the apps of the workspaces to restore, in the order they are visited.
+struct Lister<'a> {
+    workspaces: &'a [String],
+    exec: bool,
+    apps: Vec<App>,
+    rows: Vec<Row>,
+}
+
+impl Lister<'_> {
+    fn push(&mut self, app: &str, id: &str, floating: bool) {
+        self.rows.push(Row::App(self.apps.len()));
+        self.apps.push(App {
+            id: id.to_owned(),
+            app: app.to_owned(),
+            floating,
+            restore: true,
+            exec: self.exec,
+        });
+    }
+}
+
+impl LayoutVisitor for Lister<'_> {
+    fn on_workspace(&mut self, workspace: &Workspace, _ctx: &VisitContext) -> Result<VisitAction> {
+        if !is_selected(self.workspaces, workspace) {
+            return Ok(VisitAction::SkipChildren);
+        }
+        let start = self.apps.len();
+        self.rows.push(Row::Workspace {
+            name: workspace.name.clone(),
+            apps: start..start,
+        });
+        Ok(VisitAction::Continue)
+    }
+    fn on_workspace_exit(&mut self, _workspace: &Workspace, _ctx: &VisitContext) -> Result<()> {
+        let end = self.apps.len();
+        if let Some(Row::Workspace { apps, .. }) = self
+            .rows
+            .iter_mut()
+            .rev()
+            .find(|r| matches!(r, Row::Workspace { .. }))
+        {
+            apps.end = end;
+        }
+        Ok(())
+    }
+    fn on_app(&mut self, app: &str, id: &str, _ctx: &VisitContext) -> Result<()> {
+        self.push(app, id, false);
+        Ok(())
+    }
+    fn on_floating_app(&mut self, app: &str, id: &str, _ctx: &VisitContext) -> Result<()> {
+        self.push(app, id, true);
+        Ok(())
+    }
+}
+
+/// The apps picked to be restored, in the order they are visited.
+pub struct Selection {
+    restore: Vec<bool>,
+    exec: Vec<bool>,
+}
+
+impl Selection {
+    /// Drops the apps that were not picked from `output`, returning whether to spawn each of
+    /// the apps left, in the order they are visited.
+    pub fn apply(&self, output: &mut Output, workspaces: &[String]) -> Result<Vec<bool>> {
+        let mut dropper = Dropper {
+            workspaces,
+            restore: self.restore.iter().copied(),
+            floating: Vec::new(),
+        };
+        dropper.visit_output(output)?;
+        Ok(self
+            .restore
+            .iter()
+            .zip(&self.exec)
+            .filter(|(restore, _)| **restore)
+            .map(|(_, exec)| *exec)
+            .collect())
+    }
+}
+
+/// Drops the apps left out of a [`Selection`], along with the layouts and workspaces they leave
+/// empty.
+struct Dropper<'a, I> {
+    workspaces: &'a [String],
+    restore: I,
+    /// Whether to keep each floating app of the current workspace.
+    floating: Vec<bool>,
+}
+
+impl<I: Iterator<Item = bool>> LayoutVisitorMut for Dropper<'_, I> {
+    fn on_workspace(&mut self, workspace: &mut Workspace) -> Result<VisitAction> {
+        if !is_selected(self.workspaces, workspace) {
+            return Ok(VisitAction::SkipChildren);
+        }
+        Ok(VisitAction::Continue)
+    }
+    fn on_slot(&mut self, slot: &mut crate::Slot) -> Result<VisitAction> {
+        if let SlotContent::Container(_) = slot.content {
+            return Ok(VisitAction::Continue);
+        }
+        // Slots are visited before their siblings are dropped, so a dropped app is emptied
+        // here and removed with the empty layouts when its parent is left.
+        if !self.restore.next().unwrap_or(true) {
+            slot.content = SlotContent::Container(Layout {
+                style: crate::LayoutStyle::Splith,
+                slots: Vec::new(),
+            });
+        }
+        Ok(VisitAction::Continue)
+    }
+    fn on_layout_exit(&mut self, layout: &mut Layout) -> Result<()> {
+        layout.slots.retain(|s| match s.content {
+            SlotContent::Container(ref c) => !c.slots.is_empty(),
+            _ => true,
+        });
+        Ok(())
+    }
+    fn on_floating_app(&mut self, _app: &mut crate::FloatingApp) -> Result<()> {
+        let keep = self.restore.next().unwrap_or(true);
+        self.floating.push(keep);
+        Ok(())
+    }
+    fn on_workspace_exit(&mut self, workspace: &mut Workspace) -> Result<()> {
+        let mut keep = self.floating.drain(..);
+        workspace.floating.retain(|_| keep.next().unwrap_or(true));
+        Ok(())
+    }
+    fn on_output_exit(&mut self, output: &mut Output) -> Result<()> {
+        output
+            .workspaces
+            .retain(|w| !w.layout.slots.is_empty() || !w.floating.is_empty());
+        Ok(())
+    }
+}
+
+/// Puts the terminal back as it was, even if picking fails.
+struct RawMode;
+
+impl RawMode {
+    fn enable() -> Result<RawMode> {
+        terminal::enable_raw_mode()?;
+        execute!(std::io::stdout(), Hide)?;
+        Ok(RawMode)
+    }
+}
+
+impl Drop for RawMode {
+    fn drop(&mut self) {
+        let _ = execute!(std::io::stdout(), Show);
+        let _ = terminal::disable_raw_mode();
+    }
+}
+
+/// The checkbox of a group of flags, partly checked when only some are set.
+fn checkbox(mut flags: impl Iterator<Item = bool> + Clone) -> &'static str {
+    if flags.clone().all(|f| f) {
+        "[x]"
+    } else if flags.any(|f| f) {
+        "[-]"
+    } else {
+        "[ ]"
+    }
+}
+
+/// Cuts `line` to fit in `width` columns, so that it does not wrap.
+fn fit(line: String, width: usize) -> String {
+    if line.chars().count() < width {
+        return line;
+    }
+    let mut line: String = line.chars().take(width.saturating_sub(2)).collect();
+    line.push('…');
+    line
+}
+
+struct Picker {
+    apps: Vec<App>,
+    rows: Vec<Row>,
+    cursor: usize,
+    /// The first row shown, when they don't all fit on the screen.
+    offset: usize,
+    /// How many lines the last draw took.
+    drawn: u16,
+}
+
+impl Picker {
+    /// The apps the row under the cursor stands for.
+    fn selected(&self) -> Range<usize> {
+        match &self.rows[self.cursor] {
+            Row::Workspace { apps, .. } => apps.clone(),
+            Row::App(i) => *i..*i + 1,
+        }
+    }
+    fn toggle_restore(&mut self) {
+        let selected = self.selected();
+        let apps = &mut self.apps[selected];
+        let restore = !apps.iter().all(|a| a.restore);
+        for a in apps {
+            a.restore = restore;
+        }
+    }
+    fn toggle_exec(&mut self) {
+        let selected = self.selected();
+        let apps = &mut self.apps[selected];
+        let exec = !apps.iter().all(|a| a.exec);
+        for a in apps {
+            a.exec = exec;
+            // Launching an app is pointless without a slot to put it in.
+            a.restore |= exec;
+        }
+    }
+    fn line(&self, row: &Row) -> String {
+        match row {
+            Row::Workspace { name, apps } => {
+                let apps = &self.apps[apps.clone()];
+                format!(
+                    "{} {} workspace {}",
+                    checkbox(apps.iter().map(|a| a.restore)),
+                    checkbox(apps.iter().map(|a| a.exec)),
+                    name
+                )
+            }
+            Row::App(i) => {
+                let a = &self.apps[*i];
+                let mut line = format!(
+                    "{} {}   {}",
+                    if a.restore { "[x]" } else { "[ ]" },
+                    if a.exec { "[x]" } else { "[ ]" },
+                    a.id
+                );
+                if a.app != a.id {
+                    line.push_str(&format!(" ({})", a.app));
+                }
+                if a.floating {
+                    line.push_str(" floating");
+                }
+                line
+            }
+        }
+    }
+    fn draw(&mut self, out: &mut impl Write) -> Result<()> {
+        let (width, height) = terminal::size()?;
+        // The header and a line to spare for the cursor.
+        let visible = (height as usize).saturating_sub(2).max(1);
+        if self.cursor < self.offset {
+            self.offset = self.cursor;
+        } else if self.cursor >= self.offset + visible {
+            self.offset = self.cursor + 1 - visible;
+        }
+        if self.drawn > 0 {
+            queue!(out, MoveToPreviousLine(self.drawn))?;
+        }
+        queue!(out, Clear(ClearType::FromCursorDown))?;
+        let mut lines = vec![format!("restore exec  {}", HELP)];
+        for (i, row) in self.rows.iter().enumerate().skip(self.offset).take(visible) {
+            let cursor = if i == self.cursor { ">" } else { " " };
+            lines.push(format!("{} {}", cursor, self.line(row)));
+        }
+        for line in &lines {
+            queue!(out, Print(fit(line.clone(), width as usize)), Print("\r\n"))?;
+        }
+        out.flush()?;
+        self.drawn = lines.len() as u16;
+        Ok(())
+    }
+    /// Erases what the last draw left on the screen.
+    fn erase(&mut self, out: &mut impl Write) -> Result<()> {
+        if self.drawn > 0 {
+            execute!(
+                out,
+                MoveToPreviousLine(self.drawn),
+                Clear(ClearType::FromCursorDown)
+            )?;
+        }
+        self.drawn = 0;
+        Ok(())
+    }
+}
+
+/// Lets the user pick in the terminal which apps of `output` to restore and which to spawn right
+/// away, returning `None` if they cancel.
+///
+/// Only the workspaces in `workspaces` are listed, all of them if it is empty, and the apps are
+/// first marked to be spawned if `spawn` is set.
+pub fn pick(output: &Output, workspaces: &[String], spawn: bool) -> Result<Option<Selection>> {
+    if !std::io::stdout().is_terminal() {
+        anyhow::bail!("--interactive needs a terminal");
+    }
+    let mut lister = Lister {
+        workspaces,
+        exec: spawn,
+        apps: Vec::new(),
+        rows: Vec::new(),
+    };
+    lister.visit_output(output)?;
+    if lister.rows.is_empty() {
+        anyhow::bail!("there is nothing to restore");
+    }
+    let mut picker = Picker {
+        apps: lister.apps,
+        rows: lister.rows,
+        cursor: 0,
+        offset: 0,
+        drawn: 0,
+    };
+    let mut out = std::io::stdout();
+    let _raw = RawMode::enable()?;
+    let picked = loop {
+        picker.draw(&mut out)?;
+        let Event::Key(key) = crossterm::event::read()? else {
+            continue;
+        };
+        if key.kind != KeyEventKind::Press {
+            continue;
+        }
+        match key.code {
+            KeyCode::Up | KeyCode::Char('k') => picker.cursor = picker.cursor.saturating_sub(1),
+            KeyCode::Down | KeyCode::Char('j') => {
+                picker.cursor = (picker.cursor + 1).min(picker.rows.len() - 1)
+            }
+            KeyCode::Char(' ') => picker.toggle_restore(),
+            KeyCode::Char('e') => picker.toggle_exec(),
+            KeyCode::Enter => break true,
+            KeyCode::Esc | KeyCode::Char('q') => break false,
+            KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => break false,
+            _ => {}
+        }
+    };
+    picker.erase(&mut out)?;
+    if !picked {
+        return Ok(None);
+    }
+    Ok(Some(Selection {
+        restore: picker.apps.iter().map(|a| a.restore).collect(),
+        exec: picker.apps.iter().map(|a| a.exec).collect(),
+    }))
+}
+
+/// Where a slot is at while the restore runs.
+enum State {
+    Waiting { launched: bool },
+    Done(Outcome),
+}
+
+impl std::fmt::Display for State {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            State::Waiting { launched: true } => write!(f, "launched, waiting"),
+            State::Waiting { launched: false } => write!(f, "waiting"),
+            State::Done(outcome) => outcome.fmt(f),
+        }
+    }
+}
+
+/// Shows the state of each slot while the restore runs, redrawn in place on every update.
+#[derive(Debug)]
+pub struct Status {
+    slots: Vec<Slot>,
+    /// How many lines each draw takes.
+    lines: Cell<u16>,
+}
+
+impl Status {
+    pub fn new(slots: &[Slot]) -> Status {
+        Status {
+            slots: slots.to_vec(),
+            lines: Cell::new(0),
+        }
+    }
+    /// Redraws the slots, the placeholders in `pending` still waiting for their window and the
+    /// others having the outcome in `outcomes`, or else having been closed.
+    pub fn update(&self, pending: &HashSet<i64>, outcomes: &HashMap<i64, Outcome>) {
+        let states = self.slots.iter().map(|slot| {
+            let Some(p) = slot.placeholder else {
+                return State::Done(slot.outcome.clone());
+            };
+            if let Some(outcome) = outcomes.get(&p) {
+                return State::Done(outcome.clone());
+            }
+            if pending.contains(&p) {
+                return State::Waiting {
+                    launched: slot.launched,
+                };
+            }
+            State::Done(Outcome::Closed)
+        });
+        let states: Vec<State> = states.collect();
+        self.draw(&states);
+    }
+    /// Draws the final outcome of `slots`.
+    pub fn finish(&self, slots: &[Slot]) {
+        let states: Vec<State> = slots
+            .iter()
+            .map(|s| State::Done(s.outcome.clone()))
+            .collect();
+        self.draw(&states);
+    }
+    fn draw(&self, states: &[State]) {
+        // There is nowhere to report a failure to draw, the restore goes on regardless.
+        let _ = self.try_draw(states);
+    }
+    fn try_draw(&self, states: &[State]) -> Result<()> {
+        let (width, height) = terminal::size()?;
+        let mut out = std::io::stdout().lock();
+        if self.lines.get() > 0 {
+            queue!(out, MoveToPreviousLine(self.lines.get()))?;
+        }
+        queue!(out, Clear(ClearType::FromCursorDown))?;
+        let filled = states
+            .iter()
+            .filter(|s| matches!(s, State::Done(Outcome::Filled { .. })))
+            .count();
+        let mut lines = vec![format!("{}/{} windows restored", filled, states.len())];
+        // The lines must stay on the screen to be drawn over.
+        let visible = (height as usize).saturating_sub(3).max(1);
+        let workspace_width = self.slots.iter().map(|s| s.workspace.len()).max();
+        let id_width = self.slots.iter().map(|s| s.id.len()).max();
+        for (slot, state) in self.slots.iter().zip(states).take(visible) {
+            lines.push(format!(
+                "  {:<w$}  {:<i$}  {}",
+                slot.workspace,
+                slot.id,
+                state,
+                w = workspace_width.unwrap_or(0),
+                i = id_width.unwrap_or(0)
+            ));
+        }
+        if self.slots.len() > visible {
+            lines.push(format!("  and {} more", self.slots.len() - visible));
+        }
+        for line in &lines {
+            queue!(out, Print(fit(line.clone(), width as usize)), Print("\n"))?;
+        }
+        out.flush()?;
+        self.lines.set(lines.len() as u16);
+        Ok(())
+    }
+}
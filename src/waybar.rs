@@ -0,0 +1,26 @@
+use crate::report::Summary;
+use crate::status::{self, Error};
+use anyhow::Result;
+use serde_json::json;
+
+/// Prints the state of the restore as a line of JSON, the input of a custom waybar module.
+///
+/// `class` is `restoring` while the restore runs, then `done` or `failed`.
+pub fn print(text: &str, tooltip: &str, class: &str) {
+    println!(
+        "{}",
+        json!({ "text": text, "tooltip": tooltip, "class": class })
+    );
+}
+
+/// Prints the final state of a restore that ended with `res`.
+pub fn print_outcome(res: &Result<(Summary, Result<(), Error>), Error>) {
+    match res {
+        Ok((summary, res)) => {
+            let class = if res.is_ok() { "done" } else { "failed" };
+            let text = format!("{}/{}", summary.filled(), summary.total());
+            print(&text, &summary.headline(), class);
+        }
+        Err(e) => print("failed", &status::describe(e), "failed"),
+    }
+}
@@ -0,0 +1,337 @@
+use crate::placeholder::{ClientEvent, ClientMsg, PlaceholderState};
+use crate::theme::{Color, Theme};
+use anyhow::Result;
+use calloop::channel::{Channel, Event};
+use calloop::EventLoop;
+use std::sync::mpsc;
+use std::time::{Duration, Instant};
+use x11rb::connection::Connection;
+use x11rb::protocol::xproto::{
+    AtomEnum, ChangeWindowAttributesAux, ConnectionExt as _, CreateWindowAux, EventMask, PropMode,
+    Window, WindowClass,
+};
+use x11rb::protocol::Event as XEvent;
+use x11rb::rust_connection::RustConnection;
+use x11rb::wrapper::ConnectionExt as _;
+use x11rb::COPY_DEPTH_FROM_PARENT;
+
+/// Size of the placeholders until the window manager lays them out.
+const SIZE: u16 = 256;
+/// How long a skipped placeholder stays on screen.
+const SKIP_DELAY: Duration = Duration::from_millis(300);
+
+struct Atoms {
+    wm_protocols: u32,
+    wm_delete_window: u32,
+    net_wm_name: u32,
+    net_wm_pid: u32,
+    utf8_string: u32,
+}
+
+struct Placeholder {
+    window: Window,
+    token: u64,
+    title: String,
+    matcher: String,
+    state: PlaceholderState,
+    /// When a skipped placeholder gets destroyed.
+    close_at: Option<Instant>,
+}
+
+/// The placeholder client of i3: plain X11 windows filled with a color, without the animation
+/// and the progress overlay of the Wayland one.
+struct Client {
+    conn: RustConnection,
+    root: Window,
+    atoms: Atoms,
+    theme: Theme,
+    windows: Vec<Placeholder>,
+    events: mpsc::Sender<ClientEvent>,
+    exit: bool,
+    idle_deadline: Option<Instant>,
+    /// Whether the missing progress overlay was reported.
+    no_overlay: bool,
+}
+
+/// The color as an X11 pixel of a true color visual, ignoring its opacity.
+fn pixel(color: Color) -> u32 {
+    (color.r as u32) << 16 | (color.g as u32) << 8 | color.b as u32
+}
+
+impl Client {
+    fn new(events: mpsc::Sender<ClientEvent>, theme: Theme) -> Result<Self> {
+        let (conn, screen) = x11rb::connect(None)?;
+        let root = conn.setup().roots[screen].root;
+        let atom =
+            |name: &[u8]| -> Result<u32> { Ok(conn.intern_atom(false, name)?.reply()?.atom) };
+        let atoms = Atoms {
+            wm_protocols: atom(b"WM_PROTOCOLS")?,
+            wm_delete_window: atom(b"WM_DELETE_WINDOW")?,
+            net_wm_name: atom(b"_NET_WM_NAME")?,
+            net_wm_pid: atom(b"_NET_WM_PID")?,
+            utf8_string: atom(b"UTF8_STRING")?,
+        };
+        Ok(Client {
+            conn,
+            root,
+            atoms,
+            theme,
+            windows: Vec::new(),
+            events,
+            exit: false,
+            idle_deadline: None,
+            no_overlay: false,
+        })
+    }
+    /// The fill of a placeholder in `state`, X11 windows being opaque.
+    fn color(&self, state: PlaceholderState) -> Color {
+        match state {
+            PlaceholderState::Launched => self.theme.accent,
+            PlaceholderState::TimedOut => self.theme.error,
+            PlaceholderState::Waiting | PlaceholderState::Skipped => {
+                self.theme.background.unwrap_or(self.theme.accent)
+            }
+        }
+    }
+    fn set_title(&self, window: Window, title: &str) -> Result<()> {
+        let conn = &self.conn;
+        conn.change_property8(
+            PropMode::REPLACE,
+            window,
+            AtomEnum::WM_NAME,
+            AtomEnum::STRING,
+            title.as_bytes(),
+        )?;
+        conn.change_property8(
+            PropMode::REPLACE,
+            window,
+            self.atoms.net_wm_name,
+            self.atoms.utf8_string,
+            title.as_bytes(),
+        )?;
+        Ok(())
+    }
+    fn new_window(&mut self, token: u64, title: &str, app_id: &str, matcher: String) -> Result<()> {
+        let conn = &self.conn;
+        let window = conn.generate_id()?;
+        let state = PlaceholderState::Waiting;
+        let aux = CreateWindowAux::new()
+            .background_pixel(pixel(self.color(state)))
+            .event_mask(EventMask::STRUCTURE_NOTIFY);
+        conn.create_window(
+            COPY_DEPTH_FROM_PARENT,
+            window,
+            self.root,
+            0,
+            0,
+            SIZE,
+            SIZE,
+            0,
+            WindowClass::INPUT_OUTPUT,
+            0,
+            &aux,
+        )?;
+        self.set_title(window, &state.window_title(title, &matcher))?;
+        // i3 matches on the class, which plays the part of the app_id on sway.
+        let class = format!("{0}\0{0}\0", app_id);
+        conn.change_property8(
+            PropMode::REPLACE,
+            window,
+            AtomEnum::WM_CLASS,
+            AtomEnum::STRING,
+            class.as_bytes(),
+        )?;
+        conn.change_property32(
+            PropMode::REPLACE,
+            window,
+            self.atoms.net_wm_pid,
+            AtomEnum::CARDINAL,
+            &[std::process::id()],
+        )?;
+        // Without WM_DELETE_WINDOW, i3 kills the placeholders by closing the whole connection.
+        conn.change_property32(
+            PropMode::REPLACE,
+            window,
+            self.atoms.wm_protocols,
+            AtomEnum::ATOM,
+            &[self.atoms.wm_delete_window],
+        )?;
+        conn.map_window(window)?;
+        conn.flush()?;
+        self.windows.push(Placeholder {
+            window,
+            token,
+            title: title.to_owned(),
+            matcher,
+            state,
+            close_at: None,
+        });
+        Ok(())
+    }
+    fn set_state(&mut self, token: u64, state: PlaceholderState) -> Result<()> {
+        let Some(idx) = self.windows.iter().position(|p| p.token == token) else {
+            return Ok(());
+        };
+        if self.windows[idx].state == state {
+            return Ok(());
+        }
+        log::debug!(
+            "placeholder {}: {:?} -> {:?}",
+            token,
+            self.windows[idx].state,
+            state
+        );
+        self.windows[idx].state = state;
+        if state == PlaceholderState::Skipped {
+            self.windows[idx].close_at = Some(Instant::now() + SKIP_DELAY);
+        }
+        let p = &self.windows[idx];
+        let aux = ChangeWindowAttributesAux::new().background_pixel(pixel(self.color(state)));
+        self.conn.change_window_attributes(p.window, &aux)?;
+        self.conn.clear_area(true, p.window, 0, 0, 0, 0)?;
+        self.set_title(p.window, &state.window_title(&p.title, &p.matcher))?;
+        self.conn.flush()?;
+        Ok(())
+    }
+    fn destroy(&mut self, idx: usize) -> Result<()> {
+        let p = self.windows.remove(idx);
+        self.conn.destroy_window(p.window)?;
+        self.conn.flush()?;
+        Ok(())
+    }
+    fn handle(&mut self, msg: ClientMsg) -> Result<()> {
+        match msg {
+            ClientMsg::NewWindow {
+                token,
+                title,
+                app_id,
+                matcher,
+            } => self.new_window(token, &title, &app_id, matcher)?,
+            ClientMsg::SetState { token, state } => self.set_state(token, state)?,
+            // X11 has no activation tokens, the apps are launched without one.
+            ClientMsg::RequestActivation { token } => {
+                let _ = self.events.send(ClientEvent::Activation {
+                    token,
+                    activation: None,
+                });
+            }
+            ClientMsg::ExitOnIdle { timeout } => {
+                self.idle_deadline = Some(Instant::now() + timeout);
+            }
+            ClientMsg::Progress { .. } => {
+                if !std::mem::replace(&mut self.no_overlay, true) {
+                    log::info!("the progress overlay is not available on i3");
+                }
+            }
+            ClientMsg::HideProgress => {}
+            ClientMsg::Shutdown => self.exit = true,
+        }
+        Ok(())
+    }
+    /// Handles the X11 events received so far.
+    fn dispatch(&mut self) -> Result<()> {
+        while let Some(event) = self.conn.poll_for_event()? {
+            match event {
+                XEvent::MapNotify(e) => {
+                    if let Some(p) = self.windows.iter().find(|p| p.window == e.window) {
+                        let _ = self.events.send(ClientEvent::Mapped {
+                            token: p.token,
+                            surface_id: p.window,
+                        });
+                    }
+                }
+                XEvent::ClientMessage(e)
+                    if e.type_ == self.atoms.wm_protocols
+                        && e.data.as_data32()[0] == self.atoms.wm_delete_window =>
+                {
+                    // As on Wayland, the placeholder shows as skipped for a moment first.
+                    let closed = self.windows.iter().find(|p| p.window == e.window);
+                    if let Some(token) = closed.map(|p| p.token) {
+                        log::debug!("placeholder {} closed", token);
+                        self.set_state(token, PlaceholderState::Skipped)?;
+                    }
+                }
+                XEvent::DestroyNotify(e) => self.windows.retain(|p| p.window != e.window),
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+    fn destroy_stuck_windows(&mut self) {
+        let tokens: Vec<u64> = self.windows.iter().map(|p| p.token).collect();
+        log::warn!("destroying placeholders still open at exit: {:?}", tokens);
+        while !self.windows.is_empty() {
+            if let Err(e) = self.destroy(0) {
+                log::warn!("placeholder client: {}", e);
+                break;
+            }
+        }
+        let _ = self.events.send(ClientEvent::Stuck { tokens });
+    }
+}
+
+/// Runs the X11 client until it is shut down, or it is idle after `ClientMsg::ExitOnIdle`.
+pub(crate) fn run(
+    receiver: Channel<ClientMsg>,
+    event_sender: mpsc::Sender<ClientEvent>,
+    theme: Theme,
+) {
+    let mut client = match Client::new(event_sender, theme) {
+        Ok(client) => client,
+        Err(e) => {
+            log::warn!("placeholder client: can't connect to the X server: {}", e);
+            return;
+        }
+    };
+    let mut event_loop: EventLoop<Client> =
+        EventLoop::try_new().expect("Failed to initialize the event loop!");
+    event_loop
+        .handle()
+        .insert_source(receiver, |ev, _, client| {
+            let res = match ev {
+                Event::Msg(msg) => client.handle(msg),
+                Event::Closed => {
+                    client.exit = true;
+                    Ok(())
+                }
+            };
+            if let Err(e) = res {
+                log::warn!("placeholder client: {}", e);
+            }
+        })
+        .expect("failed to register channel source");
+
+    loop {
+        let res = event_loop
+            .dispatch(Duration::from_millis(16), &mut client)
+            .map_err(anyhow::Error::from)
+            .and_then(|_| client.dispatch());
+        // The connection breaks when the X server goes away.
+        if let Err(e) = res {
+            log::warn!("placeholder client: {}", e);
+            break;
+        }
+        if client.exit {
+            break;
+        }
+        let now = Instant::now();
+        while let Some(idx) = client
+            .windows
+            .iter()
+            .position(|p| matches!(p.close_at, Some(t) if t <= now))
+        {
+            if let Err(e) = client.destroy(idx) {
+                log::warn!("placeholder client: {}", e);
+            }
+        }
+        if let Some(deadline) = client.idle_deadline {
+            if client.windows.is_empty() {
+                break;
+            }
+            if now >= deadline {
+                client.destroy_stuck_windows();
+                break;
+            }
+        }
+    }
+}